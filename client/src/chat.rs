@@ -0,0 +1,138 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use shared::{ChatScope, PlayerMessage};
+
+/// How many lines the chat log keeps.
+const LOG_LINES: usize = 8;
+
+/// How long a line stays on screen while the player is not typing.
+const LINE_DURATION: Duration = Duration::from_secs(20);
+
+/// The chat log and the line being typed. Knows nothing about drawing; `lines` is what should
+/// be on screen and `take_changed` tells when that changed.
+#[derive(Default)]
+pub struct Chat {
+    log: VecDeque<(String, Instant)>,
+    /// The line being typed, while the player is typing.
+    input: Option<String>,
+    changed: bool,
+}
+
+/// What the player asked for by submitting a line.
+#[derive(Debug, PartialEq)]
+pub enum ChatCommand {
+    /// Send `message` to `scope`.
+    Send(ChatScope, String),
+    /// Nothing to send; show this line locally instead.
+    Local(String),
+}
+
+impl Chat {
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.input = Some(String::new());
+        self.changed = true;
+    }
+
+    pub fn cancel(&mut self) {
+        self.input = None;
+        self.changed = true;
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        if let Some(input) = &mut self.input {
+            input.extend(text.chars().filter(|c| !c.is_control()));
+            self.changed = true;
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(input) = &mut self.input {
+            input.pop();
+            self.changed = true;
+        }
+    }
+
+    /// Stops typing and returns what was typed.
+    pub fn submit(&mut self) -> Option<String> {
+        self.changed = true;
+        self.input.take()
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back((line, Instant::now()));
+        self.changed = true;
+    }
+
+    /// True once since the last call if `lines` would return something different, including
+    /// because a line got too old to show.
+    pub fn take_changed(&mut self) -> bool {
+        let expired = !self.is_typing()
+            && self
+                .log
+                .front()
+                .is_some_and(|(_, added)| added.elapsed() >= LINE_DURATION);
+        if expired {
+            self.log
+                .retain(|(_, added)| added.elapsed() < LINE_DURATION);
+        }
+        std::mem::take(&mut self.changed) || expired
+    }
+
+    /// The lines to show, oldest first, ending with the input line while typing.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.log.iter().map(|(line, _)| line.clone()).collect();
+        if let Some(input) = &self.input {
+            lines.push(format!("> {input}_"));
+        }
+        lines
+    }
+}
+
+/// Works out what a submitted line asks for. `/w <name> <message>` whispers to the player
+/// called `name`, whose id `find` looks up, and `/n <message>` talks to players nearby.
+/// Anything else goes to everyone, except that the server runs lines starting with any other
+/// `/` as commands, such as `/help`.
+pub fn parse_input(line: &str, find: impl Fn(&str) -> Option<String>) -> Option<ChatCommand> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim_start();
+    let command = match command {
+        "/w" | "/whisper" => {
+            let Some((name, message)) = rest.split_once(' ') else {
+                return Some(ChatCommand::Local("Usage: /w <name> <message>".into()));
+            };
+            match find(name) {
+                Some(id) => ChatCommand::Send(ChatScope::Whisper(id), message.trim().into()),
+                None => ChatCommand::Local(format!("No player called {name} is online")),
+            }
+        }
+        "/n" | "/nearby" => ChatCommand::Send(ChatScope::Nearby, rest.into()),
+        _ => ChatCommand::Send(ChatScope::Global, line.into()),
+    };
+    Some(command)
+}
+
+/// The log line for `message`, with player ids turned into names by `name_of`.
+pub fn format_message(message: &PlayerMessage, name_of: impl Fn(&str) -> String) -> String {
+    let sender = name_of(&message.id);
+    match &message.scope {
+        ChatScope::Global => format!("[{sender}] {}", message.message),
+        ChatScope::Nearby => format!("[{sender} (nearby)] {}", message.message),
+        ChatScope::Whisper(target) => {
+            format!("[{sender} -> {}] {}", name_of(target), message.message)
+        }
+    }
+}
@@ -9,6 +9,8 @@ use crate::{
 
 pub struct ClientPlayer {
     pub id: String,
+    /// Display name the player chose.
+    pub name: String,
     pub tile: ClientTile,
     pub scale: f32,
     pub speed: f32,
@@ -18,36 +20,37 @@ impl ClientPlayer {
     pub fn new(
         device: &Device,
         layout: &BindGroupLayout,
-        id: String,
-        position: [f32; 3],
+        player: Player,
         tex_info: &TexInfo,
         scale: f32,
-        speed: f32,
     ) -> Self {
-        let tile: ClientTile = ClientTile::new(device, layout, position, &tex_info, scale);
+        let tile: ClientTile = ClientTile::new(device, layout, player.position, tex_info, scale);
 
         Self {
-            id,
+            id: player.id,
+            name: player.name,
             tile,
             scale,
-            speed,
+            speed: player.speed,
         }
     }
 
     pub fn update_player(&mut self, queue: &Queue, player: Player) {
+        self.name = player.name;
         self.speed = player.speed;
         self.tile.world_position = player.position;
 
         let iso_coords: [f32; 3] = [
-            (self.tile.world_position[0] - self.tile.world_position[1]) as f32 * 0.5 * self.scale,
-            (self.tile.world_position[0] + self.tile.world_position[1]) as f32 * 0.25 * self.scale
-                + (self.tile.world_position[2] as f32 * 0.5 * self.scale),
+            (self.tile.world_position[0] - self.tile.world_position[1]) * 0.5 * self.scale,
+            (self.tile.world_position[0] + self.tile.world_position[1]) * 0.25 * self.scale
+                + (self.tile.world_position[2] * 0.5 * self.scale),
             0.0,
         ];
 
         self.tile.translate(queue, Vec3::new(iso_coords[0], iso_coords[1], iso_coords[2]));
     }
 
+    #[allow(dead_code)]
     pub fn move_player(&mut self, queue: &Queue, direction: [f32; 3]) {
         let pos = [
             self.tile.world_position[0] + direction[0] * self.speed,
@@ -56,8 +59,8 @@ impl ClientPlayer {
         ];
 
         let iso_coords: [f32; 3] = [
-            (pos[0] - pos[1]) as f32 * 0.5 * self.scale,
-            (pos[0] + pos[1]) as f32 * 0.25 * self.scale + (pos[2] as f32 * 0.5 * self.scale),
+            (pos[0] - pos[1]) * 0.5 * self.scale,
+            (pos[0] + pos[1]) * 0.25 * self.scale + (pos[2] * 0.5 * self.scale),
             0.0,
         ];
 
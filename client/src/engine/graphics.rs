@@ -23,6 +23,7 @@ pub struct Graphics {
     pub config: SurfaceConfiguration,
     pub pipeline: RenderPipeline,
     pub tile_bind_group_layout: BindGroupLayout,
+    #[allow(dead_code)]
     pub camera_bind_group_layout: BindGroupLayout,
 }
 
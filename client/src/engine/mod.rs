@@ -2,5 +2,7 @@ mod graphics;
 pub use graphics::*;
 mod texture;
 pub use texture::*;
+mod text;
+pub use text::*;
 // mod camera;
 // pub use camera::*;
\ No newline at end of file
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use fontdue::{Font, FontSettings, Metrics};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer,
+    BufferUsages, Device, Queue, RenderPass,
+};
+
+use crate::{engine::Texture, map::Drawable, vertex::VertexFloat32};
+
+const FONT: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+
+/// Glyph height in pixels.
+const FONT_SIZE: f32 = 16.0;
+
+/// Space between the text and the edge of its background, and between the background and
+/// the edge of the window, in pixels.
+const PADDING: u32 = 6;
+
+const TEXT_COLOR: [u8; 3] = [255, 255, 255];
+const BACKGROUND: [u8; 4] = [0, 0, 0, 140];
+
+/// Lines of text drawn in the bottom-left corner of the window, on top of the game.
+///
+/// The text is rasterized on the CPU into one texture each time it changes, which suits
+/// text like the chat log that changes a few times a second at most.
+pub struct TextOverlay {
+    font: Font,
+    glyphs: HashMap<char, (Metrics, Vec<u8>)>,
+    quad: Option<TextQuad>,
+}
+
+/// The GPU side of the text currently shown.
+struct TextQuad {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl TextOverlay {
+    pub fn new() -> Result<Self> {
+        let font = Font::from_bytes(FONT, FontSettings::default())
+            .map_err(|e| anyhow!("Could not load the overlay font: {e}"))?;
+        Ok(Self {
+            font,
+            glyphs: HashMap::new(),
+            quad: None,
+        })
+    }
+
+    /// Shows `lines` instead of whatever was shown before, in a window of `screen` pixels.
+    pub fn set_lines(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        lines: &[String],
+        screen: (u32, u32),
+    ) {
+        if lines.is_empty() || screen.0 == 0 || screen.1 == 0 {
+            self.quad = None;
+            return;
+        }
+
+        let (width, height, pixels) = self.rasterize(lines);
+        let texture = Texture::from_rgba(device, queue, width, height, &pixels);
+
+        // The vertices are in clip space, sized so one texel lands on one pixel.
+        let left = -1.0 + 2.0 * PADDING as f32 / screen.0 as f32;
+        let bottom = -1.0 + 2.0 * PADDING as f32 / screen.1 as f32;
+        let right = left + 2.0 * width as f32 / screen.0 as f32;
+        let top = bottom + 2.0 * height as f32 / screen.1 as f32;
+        let vertices = [
+            VertexFloat32 {
+                position: [left, bottom],
+                uv: [0.0, 1.0],
+            },
+            VertexFloat32 {
+                position: [right, bottom],
+                uv: [1.0, 1.0],
+            },
+            VertexFloat32 {
+                position: [right, top],
+                uv: [1.0, 0.0],
+            },
+            VertexFloat32 {
+                position: [left, top],
+                uv: [0.0, 0.0],
+            },
+        ];
+        let indices: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: BufferUsages::INDEX,
+        });
+        let transform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text Transform Buffer"),
+            contents: bytemuck::cast_slice(glam::Mat4::IDENTITY.as_ref()),
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Text Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: transform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&texture.view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+
+        self.quad = Some(TextQuad {
+            vertex_buffer,
+            index_buffer,
+            bind_group,
+        });
+    }
+
+    /// Draws `lines` onto a translucent background. Returns its width, height and RGBA
+    /// pixels.
+    fn rasterize(&mut self, lines: &[String]) -> (u32, u32, Vec<u8>) {
+        let line_metrics = self.font.horizontal_line_metrics(FONT_SIZE);
+        let ascent = line_metrics.map_or(FONT_SIZE, |m| m.ascent).ceil() as i32;
+        let line_height = line_metrics.map_or(FONT_SIZE, |m| m.new_line_size).ceil() as u32;
+
+        let text_width = lines
+            .iter()
+            .map(|line| {
+                line.chars()
+                    .map(|c| self.glyph(c).0.advance_width)
+                    .sum::<f32>()
+                    .ceil() as u32
+            })
+            .max()
+            .unwrap_or(0);
+        let width = text_width + 2 * PADDING;
+        let height = line_height * lines.len() as u32 + 2 * PADDING;
+
+        let mut pixels = BACKGROUND.repeat((width * height) as usize);
+        for (row, line) in lines.iter().enumerate() {
+            let baseline = (PADDING + row as u32 * line_height) as i32 + ascent;
+            let mut pen = PADDING as f32;
+            for c in line.chars() {
+                let (metrics, coverage) = self.glyph(c);
+                let x0 = pen.round() as i32 + metrics.xmin;
+                let y0 = baseline - metrics.height as i32 - metrics.ymin;
+                for (i, &alpha) in coverage.iter().enumerate() {
+                    let x = x0 + (i % metrics.width) as i32;
+                    let y = y0 + (i / metrics.width) as i32;
+                    if alpha == 0 || x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                        continue;
+                    }
+                    let pixel = ((y as u32 * width + x as u32) * 4) as usize;
+                    blend(&mut pixels[pixel..pixel + 4], alpha);
+                }
+                pen += metrics.advance_width;
+            }
+        }
+
+        (width, height, pixels)
+    }
+
+    fn glyph(&mut self, c: char) -> &(Metrics, Vec<u8>) {
+        self.glyphs
+            .entry(c)
+            .or_insert_with(|| self.font.rasterize(c, FONT_SIZE))
+    }
+}
+
+/// Blends `TEXT_COLOR` at `alpha` coverage over `pixel`.
+fn blend(pixel: &mut [u8], alpha: u8) {
+    let a = alpha as u32;
+    for (channel, text) in pixel.iter_mut().zip(TEXT_COLOR) {
+        *channel = ((text as u32 * a + *channel as u32 * (255 - a)) / 255) as u8;
+    }
+    pixel[3] = (a + pixel[3] as u32 * (255 - a) / 255) as u8;
+}
+
+impl Drawable for TextOverlay {
+    fn render(&self, render_pass: &mut RenderPass) {
+        if let Some(quad) = &self.quad {
+            render_pass.set_bind_group(0, &quad.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, quad.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(quad.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..6, 0, 0..1);
+        }
+    }
+}
@@ -73,6 +73,56 @@ impl Texture {
         })
     }
 
+    /// A texture holding `pixels`, RGBA rows of `width` pixels each from the top down.
+    pub fn from_rgba(device: &Device, queue: &Queue, width: u32, height: u32, pixels: &[u8]) -> Self {
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Pixel Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            pixels,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            view,
+            sampler,
+            x_count: 1,
+            y_count: 1,
+        }
+    }
+
     pub fn from_color(device: &Device, queue: &Queue, color: [u8; 4]) -> Self {
         let size = Extent3d {
             width: 1,
@@ -139,6 +189,7 @@ impl TexInfo {
 pub static TEXTURE_MAP: LazyLock<RwLock<HashMap<TileType, TexInfo>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
 
 
+#[allow(dead_code)]
 #[derive(Hash, PartialEq, Eq)]
 pub enum PlayerTexture {
     North,
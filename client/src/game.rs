@@ -1,36 +1,83 @@
-use anyhow::Result;
-use shared::{read_message, send_message, ClientMessage, ServerMessage};
+use anyhow::{Context, Result};
+use shared::{
+    client_handshake, Capabilities, ChunkCoord, ChunkStreamer, ClientEnvelope, ClientHello,
+    ClientMessage, ConditionedWriter, FrameReader, FrameWriter, HandshakeError, LinkConditions,
+    MessageReader, MessageWriter, Player, PlayerMessage, RequestError, RequestId, RequestTracker,
+    ServerEnvelope, ServerMessage, SessionToken, Snapshot, SnapshotReceiver, Timeout, UdpConfig,
+    UdpConnection,
+};
 use std::{
     collections::{HashMap, HashSet},
+    fs,
     sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::{
+    io::split,
     net::TcpStream,
     sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
 };
 use winit::{
     application::ApplicationHandler,
-    event::{StartCause, WindowEvent},
+    dpi::LogicalSize,
+    event::{KeyEvent, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::{Key, NamedKey, SmolStr},
     window::{Fullscreen, Window},
 };
 
 use crate::{
+    chat::{format_message, parse_input, Chat, ChatCommand},
     client_player::ClientPlayer,
-    engine::{init_textures, Graphics, TexInfo, Texture, PLAYER_TEXTURES},
+    engine::{init_textures, Graphics, TexInfo, TextOverlay, Texture, PLAYER_TEXTURES},
     map::{ClientTileManager, Drawable},
+    TransportKind,
 };
 
+const WINDOW_TITLE: &str = "Isometric Game!";
+
+/// Where the token from the server is kept, so the next start resumes the same player.
+const SESSION_FILE: &str = "session.token";
+
+/// How long a server error stays in the window title.
+const STATUS_DURATION: Duration = Duration::from_secs(5);
+
+/// How many received chunks get their tiles built per update, so a new stretch of terrain is
+/// spread over several frames instead of stalling one.
+const CHUNKS_BUILT_PER_UPDATE: usize = 2;
+
+/// How the game window opens.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowSettings {
+    /// Inner size in logical pixels. Without one the window opens maximized.
+    pub size: Option<(u32, u32)>,
+    pub fullscreen: bool,
+}
+
+/// Everything the game is started with.
+pub struct Settings {
+    pub host: String,
+    pub port: u16,
+    /// Shown to other players; must pass `shared::check_name`.
+    pub name: String,
+    pub transport: TransportKind,
+    pub link: LinkConditions,
+    pub window: WindowSettings,
+    /// Chunks kept loaded in every direction around the player.
+    pub view_radius: i64,
+}
+
 struct GameManager {
     last_frame: Instant,
     target_frame_duration: Duration,
 
     window: Option<Arc<Window>>,
+    window_settings: WindowSettings,
     fullscreen: bool,
 
     graphics: Option<Graphics>,
+    /// Draws the chat; created along with `graphics`.
+    overlay: Option<TextOverlay>,
 
     pressed_named_keys: HashSet<NamedKey>,
     pressed_keys: HashSet<SmolStr>,
@@ -39,26 +86,67 @@ struct GameManager {
     player: Option<ClientPlayer>,
     other_players: HashMap<String, ClientPlayer>,
 
-    tile_manager: Option<ClientTileManager>,
-
-    incoming_rx: UnboundedReceiver<ServerMessage>,
-    outgoing_tx: UnboundedSender<ClientMessage>,
+    /// Which chunks to ask for, build and drop as the player moves.
+    chunks: ChunkStreamer,
+    tile_manager: ClientTileManager,
+    /// The chunks each unanswered `MapRequest` asked for.
+    map_requests: HashMap<RequestId, Vec<ChunkCoord>>,
+
+    incoming_rx: UnboundedReceiver<ServerEnvelope>,
+    outgoing_tx: UnboundedSender<ClientEnvelope>,
+    requests: RequestTracker,
+    snapshots: SnapshotReceiver,
+    session: Option<SessionToken>,
+    /// Sent with every `ConnectionRequest`.
+    name: String,
+    chat: Chat,
+
+    /// Shown in the window title until it expires.
+    status: Option<(String, Instant)>,
+    /// Why the server went away, shown in the window title from then on.
+    disconnected: Option<String>,
 }
 
 impl GameManager {
-    pub async fn new() -> Result<Self> {
-        let stream = TcpStream::connect("game-server.local:5250").await?;
-        println!("Connected to server");
-        let (mut reader, mut writer) = stream.into_split();
+    pub async fn new<R, W>(
+        mut reader: R,
+        mut writer: W,
+        name: String,
+        window_settings: WindowSettings,
+        view_radius: i64,
+    ) -> Result<Self>
+    where
+        R: MessageReader + 'static,
+        W: MessageWriter + 'static,
+    {
+        match client_handshake(
+            &mut reader,
+            &mut writer,
+            ClientHello::new(Capabilities::ALL),
+        )
+        .await
+        {
+            Ok(negotiated) => println!("Using protocol v{}", negotiated.version),
+            Err(HandshakeError::Rejected(rejection)) => {
+                println!("Server refused connection: {rejection}");
+                return Err(HandshakeError::Rejected(rejection).into());
+            }
+            Err(e) => return Err(e.into()),
+        }
 
-        let (incoming_tx, incoming_rx) = unbounded_channel::<ServerMessage>();
+        let (incoming_tx, incoming_rx) = unbounded_channel::<ServerEnvelope>();
         let incoming_tx_clone = incoming_tx.clone();
 
         tokio::spawn(async move {
             loop {
-                match read_message(&mut reader).await {
+                match reader.recv::<ServerEnvelope>().await {
                     Ok(msg) => {
+                        let shutdown = matches!(msg.message, ServerMessage::Shutdown(_));
                         let _ = incoming_tx_clone.send(msg);
+                        // The game shows why; the server closes the connection next.
+                        if shutdown {
+                            break;
+                        }
                     }
                     Err(e) => {
                         println!("Disconnected from server: {e}");
@@ -68,11 +156,11 @@ impl GameManager {
             }
         });
 
-        let (outgoing_tx, mut outgoing_rx) = unbounded_channel::<ClientMessage>();
+        let (outgoing_tx, mut outgoing_rx) = unbounded_channel::<ClientEnvelope>();
 
         tokio::spawn(async move {
             while let Some(msg) = outgoing_rx.recv().await {
-                if let Err(e) = send_message(&mut writer, &msg).await {
+                if let Err(e) = writer.send(&msg).await {
                     println!("Writer task failed: {e}");
                     break;
                 }
@@ -84,22 +172,98 @@ impl GameManager {
             target_frame_duration: Duration::from_secs_f64(1.0 / 120.0),
 
             window: None,
-            fullscreen: false,
+            window_settings,
+            fullscreen: window_settings.fullscreen,
 
             graphics: None,
+            overlay: None,
 
             pressed_named_keys: HashSet::new(),
             pressed_keys: HashSet::new(),
 
             player: None,
             other_players: HashMap::new(),
-            tile_manager: None,
+            chunks: ChunkStreamer::new(view_radius),
+            tile_manager: ClientTileManager::default(),
+            map_requests: HashMap::new(),
 
             incoming_rx,
             outgoing_tx,
+            requests: RequestTracker::default(),
+            snapshots: SnapshotReceiver::default(),
+            session: load_session(),
+            name,
+            chat: Chat::default(),
+
+            status: None,
+            disconnected: None,
         })
     }
 
+    /// Sends `msg` under a fresh request id, tracking it if it expects a reply.
+    fn request(&mut self, msg: ClientMessage) -> RequestId {
+        let envelope = self.requests.send(msg, Instant::now());
+        let id = envelope.id;
+        let _ = self.outgoing_tx.send(envelope);
+        id
+    }
+
+    /// Resends requests whose reply is overdue, and gives up on those out of attempts.
+    fn retry_requests(&mut self) {
+        for timeout in self.requests.poll(Instant::now()) {
+            match timeout {
+                Timeout::Retry(envelope) => {
+                    println!("No reply to request {} yet, retrying", envelope.id);
+                    let _ = self.outgoing_tx.send(envelope);
+                }
+                Timeout::GaveUp(id, message) => {
+                    if let ClientMessage::MapRequest(coords) = message {
+                        self.map_requests.remove(&id);
+                        self.chunks.forget(&coords, Instant::now());
+                    }
+                    self.show_status(format!("Server never answered request {id}"));
+                }
+            }
+        }
+    }
+
+    /// Logs `status` and shows it in the window title for `STATUS_DURATION`.
+    fn show_status(&mut self, status: String) {
+        println!("{status}");
+        if let Some(ref window) = self.window {
+            window.set_title(&format!("{WINDOW_TITLE} - {status}"));
+        }
+        self.status = Some((status, Instant::now()));
+    }
+
+    fn update_status(&mut self) {
+        if let Some((_, shown)) = &self.status {
+            if shown.elapsed() >= STATUS_DURATION {
+                self.status = None;
+                if let Some(ref window) = self.window {
+                    window.set_title(&self.idle_title());
+                }
+            }
+        }
+    }
+
+    /// The window title when no status is shown.
+    fn idle_title(&self) -> String {
+        match &self.disconnected {
+            Some(reason) => format!("{WINDOW_TITLE} - Disconnected: {reason}"),
+            None => WINDOW_TITLE.to_string(),
+        }
+    }
+
+    fn report_error(&mut self, error: RequestError, request: Option<RequestId>) {
+        // Failed chat commands are answered this way, and belong next to the command.
+        self.chat.push(error.to_string());
+        match request {
+            Some(id) => self.show_status(format!("Request {id} failed: {error}")),
+            None => self.show_status(format!("Server error: {error}")),
+        }
+    }
+
     pub fn handle_named_key(&mut self, key: NamedKey, pressed: bool) {
         if pressed {
             self.pressed_named_keys.insert(key);
@@ -144,28 +308,24 @@ impl GameManager {
     }
 
     pub fn update_player(&mut self) {
-        if let Some(player) = &self.player {
+        if self.player.is_some() {
             if self.pressed_keys.contains("w") {
-                let _ = self.outgoing_tx.send(ClientMessage::MoveRequest {
-                    player: player.id.clone(),
+                self.request(ClientMessage::MoveRequest {
                     direction: [0.0, 1.0, 0.0],
                 });
             }
             if self.pressed_keys.contains("s") {
-                let _ = self.outgoing_tx.send(ClientMessage::MoveRequest {
-                    player: player.id.clone(),
+                self.request(ClientMessage::MoveRequest {
                     direction: [0.0, -1.0, 0.0],
                 });
             }
             if self.pressed_keys.contains("a") {
-                let _ = self.outgoing_tx.send(ClientMessage::MoveRequest {
-                    player: player.id.clone(),
+                self.request(ClientMessage::MoveRequest {
                     direction: [-1.0, 0.0, 0.0],
                 });
             }
             if self.pressed_keys.contains("d") {
-                let _ = self.outgoing_tx.send(ClientMessage::MoveRequest {
-                    player: player.id.clone(),
+                self.request(ClientMessage::MoveRequest {
                     direction: [1.0, 0.0, 0.0],
                 });
             }
@@ -183,55 +343,130 @@ impl GameManager {
 
     pub fn update_game(&mut self) {}
 
-    pub fn process_server_input(&mut self) {
-        while let Ok(msg) = self.incoming_rx.try_recv() {
-            match msg {
-                ServerMessage::OtherPlayer(p) => {
-                    if let Some(ref graphics) = self.graphics {
-                        let Graphics {
+    /// Streams the terrain around the player: drops the chunks that went out of view, builds
+    /// a few of those received and asks for the ones that came into view.
+    pub fn update_chunks(&mut self) {
+        let Some(position) = self.player.as_ref().map(|p| p.tile.world_position) else {
+            return;
+        };
+        for coord in self.chunks.move_to(position) {
+            self.tile_manager.remove_chunk(coord);
+        }
+
+        if let Some(ref graphics) = self.graphics {
+            let Graphics {
+                device,
+                tile_bind_group_layout,
+                ..
+            } = graphics;
+            for _ in 0..CHUNKS_BUILT_PER_UPDATE {
+                let Some(chunk) = self.chunks.next_to_build() else {
+                    break;
+                };
+                self.tile_manager
+                    .add_chunk(&chunk, device, tile_bind_group_layout, 0.25);
+            }
+        }
+
+        let coords = self.chunks.to_request();
+        if !coords.is_empty() {
+            let id = self.request(ClientMessage::MapRequest(coords.clone()));
+            self.map_requests.insert(id, coords);
+        }
+    }
+
+    /// Brings every player in line with a snapshot from the server and acknowledges it.
+    fn apply_snapshot(&mut self, snapshot: Snapshot) {
+        // Without graphics no player can be shown yet, and until our own player arrives it
+        // cannot be told apart from the others. Leaving the snapshot unacknowledged makes the
+        // server send everything again later.
+        if self.graphics.is_none() || self.player.is_none() {
+            return;
+        }
+        let Some(state) = self.snapshots.receive(&snapshot) else {
+            return;
+        };
+        let tick = state.tick;
+        let players: Vec<Player> = state.players.values().cloned().collect();
+        self.other_players
+            .retain(|id, _| state.players.contains_key(id));
+
+        for p in players {
+            match (&mut self.player, &self.graphics) {
+                (Some(player), Some(graphics)) if player.id == p.id => {
+                    player.update_player(&graphics.queue, p);
+                }
+                _ => {
+                    if !self.other_players.contains_key(&p.id) {
+                        self.announce(format!("{} joined", p.name));
+                    }
+                    self.update_other_player(p);
+                }
+            }
+        }
+
+        self.request(ClientMessage::SnapshotAck { tick });
+    }
+
+    fn update_other_player(&mut self, p: Player) {
+        if let Some(ref graphics) = self.graphics {
+            let Graphics {
+                device,
+                tile_bind_group_layout,
+                queue,
+                ..
+            } = graphics;
+            self.other_players
+                .entry(p.id.clone())
+                .and_modify(|player| {
+                    player.update_player(queue, p.clone());
+                })
+                .or_insert_with(|| match PLAYER_TEXTURES.read() {
+                    Ok(textures) => {
+                        let tex_info = if let Some(tex_info) =
+                            textures.get(&crate::engine::PlayerTexture::South)
+                        {
+                            tex_info
+                        } else {
+                            &TexInfo {
+                                texture: Arc::new(Texture::from_color(
+                                    device,
+                                    queue,
+                                    [255, 255, 255, 255],
+                                )),
+                                index: [0, 0],
+                            }
+                        };
+                        ClientPlayer::new(
                             device,
                             tile_bind_group_layout,
-                            queue,
-                            ..
-                        } = graphics;
-                        self.other_players
-                            .entry(p.id.clone())
-                            .and_modify(|player| {
-                                player.update_player(queue, p.clone());
-                            })
-                            .or_insert_with(|| match PLAYER_TEXTURES.read() {
-                                Ok(textures) => {
-                                    let tex_info = if let Some(tex_info) =
-                                        textures.get(&crate::engine::PlayerTexture::South)
-                                    {
-                                        tex_info
-                                    } else {
-                                        &TexInfo {
-                                            texture: Arc::new(Texture::from_color(
-                                                device,
-                                                queue,
-                                                [255, 255, 255, 255],
-                                            )),
-                                            index: [0, 0],
-                                        }
-                                    };
-                                    ClientPlayer::new(
-                                        device,
-                                        tile_bind_group_layout,
-                                        p.id,
-                                        p.position,
-                                        tex_info,
-                                        0.25,
-                                        p.speed,
-                                    )
-                                }
-                                Err(e) => {
-                                    panic!("Could not get PLAYER_TEXTURES for reading: {e}");
-                                }
-                            });
+                            p,
+                            tex_info,
+                            0.25,
+                        )
                     }
-                }
+                    Err(e) => {
+                        panic!("Could not get PLAYER_TEXTURES for reading: {e}");
+                    }
+                });
+        }
+    }
+
+    pub fn process_server_input(&mut self) {
+        self.retry_requests();
+
+        while let Ok(envelope) = self.incoming_rx.try_recv() {
+            if let Some(id) = envelope.reply_to {
+                self.requests.resolve(id);
+            }
+            let chunk_request = envelope
+                .reply_to
+                .and_then(|id| self.map_requests.remove(&id));
+            match envelope.message {
+                ServerMessage::Snapshot(snapshot) => self.apply_snapshot(snapshot),
                 ServerMessage::Player(p) => {
+                    // A snapshot sent before this showed our own player as someone else.
+                    self.other_players.remove(&p.id);
                     if let Some(ref graphics) = self.graphics {
                         let Graphics {
                             device,
@@ -250,11 +485,9 @@ impl GameManager {
                                         let player = ClientPlayer::new(
                                             device,
                                             tile_bind_group_layout,
-                                            p.id,
-                                            p.position,
-                                            &tex_info,
+                                            p,
+                                            tex_info,
                                             0.25,
-                                            p.speed,
                                         );
                                         self.player = Some(player);
                                     }
@@ -266,27 +499,142 @@ impl GameManager {
                         }
                     }
                 }
-                ServerMessage::Map(m) => {
-                    if let Some(ref graphics) = self.graphics {
-                        let Graphics {
-                            device,
-                            tile_bind_group_layout,
-                            ..
-                        } = graphics;
-                        let size = m.size;
-                        self.tile_manager = Some(ClientTileManager::from_server(
-                            m,
-                            &device,
-                            &tile_bind_group_layout,
-                            size,
-                            0.25,
-                        ));
+                ServerMessage::Map(chunks) => self.chunks.receive(chunks),
+                ServerMessage::Roster(players) => {
+                    let own_id = self.player.as_ref().map(|player| player.id.clone());
+                    for p in players {
+                        if Some(&p.id) != own_id.as_ref() {
+                            self.update_other_player(p);
+                        }
+                    }
+                }
+                ServerMessage::Session(token) => {
+                    if let Err(e) = fs::write(SESSION_FILE, &token.0) {
+                        println!("Could not save session token: {e}");
+                    }
+                    self.session = Some(token);
+                }
+                ServerMessage::Error { code, message } => {
+                    let error = RequestError { code, message };
+                    match chunk_request {
+                        // The player never asked for these, so the chat is no place for them.
+                        Some(coords) => {
+                            self.chunks.forget(&coords, Instant::now());
+                            self.show_status(format!("Could not load the map: {error}"));
+                        }
+                        None => self.report_error(error, envelope.reply_to),
+                    }
+                }
+                ServerMessage::Message(message) => {
+                    let line = format_message(&message, |id| self.name_of(id));
+                    println!("{line}");
+                    self.chat.push(line);
+                }
+                ServerMessage::CommandOutput(output) => {
+                    for line in output.lines() {
+                        self.announce(line.to_string());
+                    }
+                }
+                ServerMessage::Disconnect(id) => {
+                    if let Some(player) = self.other_players.remove(&id) {
+                        self.announce(format!("{} left", player.name));
+                    }
+                }
+                ServerMessage::Shutdown(reason) => {
+                    println!("Disconnected from server: {reason}");
+                    self.status = None;
+                    self.disconnected = Some(reason);
+                    if let Some(ref window) = self.window {
+                        window.set_title(&self.idle_title());
                     }
                 }
-                _ => {}
             }
         }
     }
+
+    /// Logs `line` and adds it to the chat log.
+    fn announce(&mut self, line: String) {
+        println!("{line}");
+        self.chat.push(line);
+    }
+
+    /// The name of the player `id`, or the id if this client does not know the player.
+    fn name_of(&self, id: &str) -> String {
+        self.player
+            .iter()
+            .chain(self.other_players.values())
+            .find(|player| player.id == id)
+            .map_or_else(|| id.to_string(), |player| player.name.clone())
+    }
+
+    /// Handles a key while typing a chat message.
+    fn type_chat(&mut self, event: &KeyEvent) {
+        if !event.state.is_pressed() {
+            return;
+        }
+        match &event.logical_key {
+            Key::Named(NamedKey::Enter) => {
+                let Some(line) = self.chat.submit() else {
+                    return;
+                };
+                let find = |name: &str| {
+                    self.player
+                        .iter()
+                        .chain(self.other_players.values())
+                        .find(|player| player.name == name)
+                        .map(|player| player.id.clone())
+                };
+                match parse_input(&line, find) {
+                    Some(ChatCommand::Send(scope, message)) => {
+                        let id = self.player.as_ref().map(|p| p.id.clone()).unwrap_or_default();
+                        self.request(ClientMessage::MessageRequest(PlayerMessage {
+                            id,
+                            scope,
+                            message,
+                        }));
+                    }
+                    Some(ChatCommand::Local(line)) => self.chat.push(line),
+                    None => {}
+                }
+            }
+            Key::Named(NamedKey::Escape) => self.chat.cancel(),
+            Key::Named(NamedKey::Backspace) => self.chat.backspace(),
+            _ => {
+                if let Some(text) = &event.text {
+                    self.chat.type_text(text);
+                }
+            }
+        }
+    }
+
+    /// Redraws the chat overlay if the chat changed, or always if `force` is set.
+    fn update_overlay(&mut self, force: bool) {
+        let changed = self.chat.take_changed();
+        if !(changed || force) {
+            return;
+        }
+        if let (Some(graphics), Some(overlay)) = (&self.graphics, &mut self.overlay) {
+            let Graphics {
+                device,
+                queue,
+                tile_bind_group_layout,
+                config,
+                ..
+            } = graphics;
+            overlay.set_lines(
+                device,
+                queue,
+                tile_bind_group_layout,
+                &self.chat.lines(),
+                (config.width, config.height),
+            );
+        }
+    }
+}
+
+fn load_session() -> Option<SessionToken> {
+    let token = fs::read_to_string(SESSION_FILE).ok()?;
+    Some(SessionToken(token.trim().to_string()))
 }
 
 impl ApplicationHandler for GameManager {
@@ -295,9 +643,11 @@ impl ApplicationHandler for GameManager {
         let next_frame_time = self.last_frame + self.target_frame_duration;
 
         self.process_server_input();
+        self.update_status();
 
         self.update_game();
         self.update_player();
+        self.update_chunks();
         self.update_camera();
 
         if now >= next_frame_time || matches!(cause, StartCause::Init) {
@@ -313,12 +663,17 @@ impl ApplicationHandler for GameManager {
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
-            if let Ok(window) = event_loop.create_window(
-                Window::default_attributes()
-                    .with_title("Isometric Game!")
-                    .with_maximized(true),
-            ) {
-                self.window = Some(Arc::new(window));
+            let mut attributes = Window::default_attributes().with_title(WINDOW_TITLE);
+            attributes = match self.window_settings.size {
+                Some((width, height)) => attributes.with_inner_size(LogicalSize::new(width, height)),
+                None => attributes.with_maximized(true),
+            };
+            if self.window_settings.fullscreen {
+                attributes = attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+            }
+            match event_loop.create_window(attributes) {
+                Ok(window) => self.window = Some(Arc::new(window)),
+                Err(e) => println!("Could not create window: {e}"),
             }
         }
 
@@ -333,8 +688,15 @@ impl ApplicationHandler for GameManager {
                                 println!("Could not init textures: {e}");
                             }
                         }
+                        match TextOverlay::new() {
+                            Ok(overlay) => self.overlay = Some(overlay),
+                            Err(e) => println!("Could not set up the chat overlay: {e}"),
+                        }
                         self.graphics = Some(graphics);
-                        let _ = self.outgoing_tx.send(ClientMessage::ConnectionRequest);
+                        self.request(ClientMessage::ConnectionRequest {
+                            token: self.session.clone(),
+                            name: self.name.clone(),
+                        });
                     }
                     Err(e) => {
                         println!("Could not create graphics: {e}");
@@ -351,7 +713,21 @@ impl ApplicationHandler for GameManager {
         event: winit::event::WindowEvent,
     ) {
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                self.request(ClientMessage::Disconnect);
+                event_loop.exit();
+            }
+            WindowEvent::KeyboardInput { event, .. } if self.chat.is_typing() => {
+                self.type_chat(&event);
+            }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state.is_pressed() && event.logical_key == Key::Named(NamedKey::Enter) =>
+            {
+                // Typing must not keep the player walking.
+                self.pressed_keys.clear();
+                self.pressed_named_keys.clear();
+                self.chat.open();
+            }
             WindowEvent::KeyboardInput { event, .. } => {
                 if let Key::Named(key) = event.logical_key {
                     self.handle_named_key(key, event.state.is_pressed());
@@ -362,17 +738,23 @@ impl ApplicationHandler for GameManager {
                 self.update_window();
             }
             WindowEvent::RedrawRequested => {
+                self.update_overlay(false);
                 if let Some(graphics) = &mut self.graphics {
-                    if let Some(ref tile_manager) = self.tile_manager {
-                        let mut drawables: Vec<&dyn Drawable> = vec![tile_manager];
-                        if let Some(ref player) = self.player {
-                            drawables.push(player);
-                        };
-                        drawables.extend(self.other_players.iter().map(|(id, player)| player as &dyn Drawable));
-                        
-                        if let Err(e) = graphics.render(drawables) {
-                            println!("Could not render frame: {e}");
-                        }
+                    let mut drawables: Vec<&dyn Drawable> = vec![&self.tile_manager];
+                    if let Some(ref player) = self.player {
+                        drawables.push(player);
+                    };
+                    drawables.extend(
+                        self.other_players
+                            .values()
+                            .map(|player| player as &dyn Drawable),
+                    );
+                    if let Some(ref overlay) = self.overlay {
+                        drawables.push(overlay);
+                    }
+
+                    if let Err(e) = graphics.render(drawables) {
+                        println!("Could not render frame: {e}");
                     }
                 }
             }
@@ -380,6 +762,8 @@ impl ApplicationHandler for GameManager {
                 if let Some(ref mut graphics) = self.graphics {
                     graphics.resize(size.width, size.height);
                 }
+                // The overlay is sized in pixels, so it has to be laid out again.
+                self.update_overlay(true);
                 // if let (Some(window), Some(camera)) = (&mut self.window, &mut self.camera) {
                 //     let PhysicalSize { width, height } = window.inner_size();
                 //     camera.update_projection(width as f32 / height as f32);
@@ -396,10 +780,47 @@ pub struct Game {
 }
 
 impl Game {
-    pub fn new() -> Result<Self> {
+    /// Connects to the server in `settings`. Fails if it cannot be reached or refuses the
+    /// handshake.
+    pub fn new(settings: Settings) -> Result<Self> {
+        let Settings {
+            host,
+            port,
+            name,
+            transport,
+            link,
+            window,
+            view_radius,
+        } = settings;
+        let addr = format!("{host}:{port}");
         let event_loop = EventLoop::new()?;
         event_loop.set_control_flow(ControlFlow::Poll);
-        let game_manager = pollster::block_on(GameManager::new())?;
+        let game_manager = pollster::block_on(async {
+            match transport {
+                TransportKind::Tcp => {
+                    let stream = TcpStream::connect(&addr)
+                        .await
+                        .with_context(|| format!("cannot connect to {addr}"))?;
+                    println!("Connected to {addr}");
+                    let (reader, writer) = split(stream);
+                    let writer = ConditionedWriter::new(FrameWriter::new(writer), link);
+                    GameManager::new(FrameReader::new(reader), writer, name, window, view_radius)
+                        .await
+                }
+                TransportKind::Udp => {
+                    let config = UdpConfig {
+                        conditions: link,
+                        ..UdpConfig::default()
+                    };
+                    let connection = UdpConnection::connect(&addr, config)
+                        .await
+                        .with_context(|| format!("cannot connect to {addr}"))?;
+                    println!("Connected to {addr}");
+                    let (reader, writer) = connection.into_split();
+                    GameManager::new(reader, writer, name, window, view_radius).await
+                }
+            }
+        })?;
         Ok(Self {
             event_loop,
             game_manager,
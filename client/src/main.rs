@@ -1,17 +1,109 @@
-use anyhow::Result;
+use std::{env, process::ExitCode};
 
-use crate::game::Game;
+use clap::{Parser, ValueEnum};
+use shared::{check_name, LinkConditions, DEFAULT_VIEW_RADIUS, MAX_VIEW_RADIUS};
 
+use crate::game::{Game, Settings, WindowSettings};
+
+mod chat;
 mod engine;
 mod game;
 mod vertex;
 mod map;
 mod client_player;
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TransportKind {
+    Tcp,
+    Udp,
+}
+
+#[derive(Parser)]
+struct Args {
+    /// Host name or IP address of the server.
+    #[arg(long, default_value = "game-server.local")]
+    host: String,
+
+    /// Port the server listens on.
+    #[arg(long, default_value_t = 5250)]
+    port: u16,
+
+    /// Name other players see [default: $USER, or `Player`].
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Transport used to reach the server; must match the server's.
+    #[arg(long, value_enum, default_value_t = TransportKind::Tcp)]
+    transport: TransportKind,
+
+    /// Simulate a bad network on everything the client sends, e.g.
+    /// `latency=75ms,jitter=10ms,loss=0.02,reorder=0.01,bandwidth=64000`.
+    #[arg(long)]
+    link: Option<LinkConditions>,
+
+    /// Width of the window in logical pixels. Without a size the window opens maximized.
+    #[arg(long, requires = "height")]
+    width: Option<u32>,
+
+    /// Height of the window in logical pixels.
+    #[arg(long, requires = "width")]
+    height: Option<u32>,
+
+    /// Start in borderless fullscreen. F11 toggles it either way.
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Chunks of terrain kept loaded in every direction around the player. Chunks further
+    /// away are dropped.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_VIEW_RADIUS,
+        value_parser = clap::value_parser!(i64).range(0..=MAX_VIEW_RADIUS)
+    )]
+    view_radius: i64,
+}
+
+impl Args {
+    fn into_settings(self) -> Settings {
+        let name = self.name.unwrap_or_else(|| {
+            env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_else(|_| "Player".to_string())
+        });
+        Settings {
+            host: self.host,
+            port: self.port,
+            name,
+            transport: self.transport,
+            link: self.link.unwrap_or_default(),
+            window: WindowSettings {
+                size: self.width.zip(self.height),
+                fullscreen: self.fullscreen,
+            },
+            view_radius: self.view_radius,
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let game = Game::new()?;
-    game.run()?;
-    
-    Ok(())
+async fn main() -> ExitCode {
+    let settings = Args::parse().into_settings();
+    if let Err(e) = check_name(&settings.name) {
+        eprintln!("Invalid --name: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    let game = match Game::new(settings) {
+        Ok(game) => game,
+        Err(e) => {
+            eprintln!("Could not join the game: {e:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = game.run() {
+        eprintln!("The game stopped unexpectedly: {e:#}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
 }
@@ -29,19 +29,19 @@ impl ClientTile {
 
         let vertices = [
             VertexFloat32 {
-                position: [-1.0 * half_size, -1.0 * half_size],
+                position: [-half_size, -half_size],
                 uv: tex_info.map_uv([0.0, 1.0]),
             },
             VertexFloat32 {
-                position: [1.0 * half_size, -1.0 * half_size],
+                position: [half_size, -half_size],
                 uv: tex_info.map_uv([1.0, 1.0]),
             },
             VertexFloat32 {
-                position: [1.0 * half_size, 1.0 * half_size],
+                position: [half_size, half_size],
                 uv: tex_info.map_uv([1.0, 0.0]),
             },
             VertexFloat32 {
-                position: [-1.0 * half_size, 1.0 * half_size],
+                position: [-half_size, half_size],
                 uv: tex_info.map_uv([0.0, 0.0]),
             },
         ];
@@ -54,14 +54,14 @@ impl ClientTile {
 
         let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&indices),
+            contents: bytemuck::cast_slice(indices),
             usage: BufferUsages::INDEX,
         });
 
         let iso_coords: [f32; 2] = [
-            (world_position[0] - world_position[1]) as f32 * 0.5 * scale,
-            (world_position[0] + world_position[1]) as f32 * 0.25 * scale
-                + (world_position[2] as f32 * 0.5 * scale),
+            (world_position[0] - world_position[1]) * 0.5 * scale,
+            (world_position[0] + world_position[1]) * 0.25 * scale
+                + (world_position[2] * 0.5 * scale),
         ];
 
         let transform = Mat4::IDENTITY * Mat4::from_translation(Vec3::new(iso_coords[0], iso_coords[1], 0.0));
@@ -1,20 +1,33 @@
 use std::collections::BTreeMap;
 
-use shared::TileManager;
+use shared::{Chunk, ChunkCoord};
 use wgpu::{BindGroupLayout, Device};
 
-use crate::{engine::{TEXTURE_MAP}, map::{ClientTile, Drawable}};
-
-
+use crate::{
+    engine::TEXTURE_MAP,
+    map::{ClientTile, Drawable},
+};
 
+/// The GPU side of the loaded chunks. Chunks are added and removed one at a time as the
+/// player moves, so the map never has to be rebuilt as a whole.
+#[derive(Default)]
 pub struct ClientTileManager {
-    position: [i64; 2],
+    /// Keyed by `(z, -y, -x)`, the order tiles have to be drawn in to overlap properly.
     tiles: BTreeMap<(i64, i64, i64), ClientTile>,
+    /// The keys in `tiles` of every chunk's tiles.
+    chunks: BTreeMap<ChunkCoord, Vec<(i64, i64, i64)>>,
 }
 
 impl ClientTileManager {
-    pub fn from_server(value: TileManager, device: &Device, layout: &BindGroupLayout, size: u8, scale: f32) -> Self {
-        let mut tiles: BTreeMap<(i64, i64, i64), ClientTile> = BTreeMap::new();
+    /// Builds the tiles of `chunk`, replacing any built for it before.
+    pub fn add_chunk(
+        &mut self,
+        chunk: &Chunk,
+        device: &Device,
+        layout: &BindGroupLayout,
+        scale: f32,
+    ) {
+        self.remove_chunk(chunk.coord);
 
         let textures = if let Ok(textures) = TEXTURE_MAP.read() {
             textures
@@ -27,23 +40,37 @@ impl ClientTileManager {
         } else {
             panic!("Could not get TexInfo");
         };
-        
-        value.tiles.iter().for_each(|((x, y, z), tile)| {
-            let client_tile: ClientTile = ClientTile::new(device, layout, [*x as f32,*y as f32,*z as f32], &tex_info, scale);
-            tiles.insert((*z, -y, -x), client_tile);
-        });
 
-        Self {
-            tiles,
-            position: value.position,
+        let keys = chunk
+            .tiles
+            .keys()
+            .map(|&(x, y, z)| {
+                let client_tile = ClientTile::new(
+                    device,
+                    layout,
+                    [x as f32, y as f32, z as f32],
+                    tex_info,
+                    scale,
+                );
+                self.tiles.insert((z, -y, -x), client_tile);
+                (z, -y, -x)
+            })
+            .collect();
+        self.chunks.insert(chunk.coord, keys);
+    }
+
+    /// Drops the tiles of the chunk `coord`, freeing their buffers.
+    pub fn remove_chunk(&mut self, coord: ChunkCoord) {
+        for key in self.chunks.remove(&coord).unwrap_or_default() {
+            self.tiles.remove(&key);
         }
     }
 }
 
 impl Drawable for ClientTileManager {
     fn render(&self, render_pass: &mut wgpu::RenderPass) {
-        self.tiles.iter().for_each(|((z, ny, nx), tile)| {
+        self.tiles.iter().for_each(|((_z, _ny, _nx), tile)| {
             tile.render(render_pass);
         });
     }
-}
\ No newline at end of file
+}
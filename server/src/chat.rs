@@ -0,0 +1,61 @@
+use shared::{
+    ChatScope, ErrorCode, PlayerMessage, RequestError, ServerMessage, SnapshotState, check_message,
+};
+
+use crate::events::Event;
+
+/// How far a nearby chat message carries, in tiles, when no radius is configured.
+pub const DEFAULT_CHAT_RADIUS: f32 = 10.0;
+
+/// Checks a chat message from the player `sender` and works out who it reaches, going by
+/// where everyone is in `world`. The message is relayed as coming from `sender` whatever id
+/// it carries, and the sender always gets it back so its chat log shows what was sent.
+///
+/// A sender not in `world` yet, because it joined after the latest tick, is nearby nobody.
+pub fn route(
+    mut message: PlayerMessage,
+    sender: &str,
+    world: &SnapshotState,
+    radius: f32,
+) -> Result<Event, RequestError> {
+    check_message(&message.message).map_err(|e| RequestError::new(ErrorCode::InvalidMessage, e))?;
+    message.id = sender.to_string();
+
+    let event = match &message.scope {
+        ChatScope::Global => Event::everyone(ServerMessage::Message(message)),
+        ChatScope::Nearby => {
+            let mut audience = vec![sender.to_string()];
+            if let Some(origin) = world.players.get(sender) {
+                audience.extend(
+                    world
+                        .players
+                        .values()
+                        .filter(|p| {
+                            p.id != sender && distance(p.position, origin.position) <= radius
+                        })
+                        .map(|p| p.id.clone()),
+                );
+            }
+            Event::to(audience, ServerMessage::Message(message))
+        }
+        ChatScope::Whisper(target) => {
+            if !world.players.contains_key(target) {
+                return Err(RequestError::new(
+                    ErrorCode::UnknownPlayer,
+                    format!("there is no player {target} online to whisper to"),
+                ));
+            }
+            let mut audience = vec![sender.to_string()];
+            if target != sender {
+                audience.push(target.clone());
+            }
+            Event::to(audience, ServerMessage::Message(message))
+        }
+    };
+    Ok(event)
+}
+
+/// Distance between two positions across the map, ignoring height.
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
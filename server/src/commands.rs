@@ -0,0 +1,445 @@
+use std::{collections::BTreeMap, fmt, future::Future, pin::Pin, sync::Arc};
+
+use shared::{ErrorCode, Player, RequestError};
+
+use crate::{ServerState, connection::lock_sessions, moderation::lock_moderation};
+
+/// Who runs a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Caller {
+    /// The operator at the server's terminal, who may run every command.
+    Console,
+    /// The player with this id, from chat.
+    Player(String),
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Caller::Console => f.write_str("the console"),
+            Caller::Player(id) => write!(f, "player {id}"),
+        }
+    }
+}
+
+/// Who may run a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Anyone,
+    /// The console, and players listed in `ServerConfig::admins`.
+    Admin,
+}
+
+/// What `/help` shows about a command, and who may run it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandSpec {
+    /// What follows the `/`.
+    pub name: &'static str,
+    /// The arguments, e.g. `<player> [reason]`.
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub permission: Permission,
+}
+
+/// What a command is run with.
+pub struct CommandContext {
+    pub state: ServerState,
+    pub caller: Caller,
+    /// The words after the command name.
+    pub args: Vec<String>,
+    pub spec: CommandSpec,
+}
+
+/// What a command prints, or why it failed.
+pub type CommandResult = Result<String, RequestError>;
+
+pub type CommandFuture = Pin<Box<dyn Future<Output = CommandResult> + Send>>;
+
+type Handler = dyn Fn(CommandContext) -> CommandFuture + Send + Sync;
+
+/// Every command a server knows, by name.
+#[derive(Clone, Default)]
+pub struct Commands {
+    commands: BTreeMap<&'static str, (CommandSpec, Arc<Handler>)>,
+}
+
+impl Commands {
+    /// No commands at all.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commands every server starts with.
+    pub fn builtin() -> Self {
+        let mut commands = Self::new();
+        commands.register(HELP, help);
+        commands.register(LIST, list);
+        commands.register(SEED, seed);
+        commands.register(SAVE, save);
+        commands.register(KICK, kick);
+        commands.register(TP, tp);
+        commands.register(STOP, stop);
+        commands.register(MUTE, mute);
+        commands.register(UNMUTE, unmute);
+        commands.register(BAN, ban);
+        commands.register(UNBAN, unban);
+        commands
+    }
+
+    /// Adds a command, replacing any other with the same name. `handler` runs it, e.g.
+    /// `|ctx| Box::pin(async move { Ok(format!("hello {}", ctx.caller)) })`.
+    pub fn register<F>(&mut self, spec: CommandSpec, handler: F)
+    where
+        F: Fn(CommandContext) -> CommandFuture + Send + Sync + 'static,
+    {
+        self.commands.insert(spec.name, (spec, Arc::new(handler)));
+    }
+
+    pub fn specs(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.commands.values().map(|(spec, _)| spec)
+    }
+
+    fn get(&self, name: &str) -> Option<(CommandSpec, Arc<Handler>)> {
+        self.commands.get(name).cloned()
+    }
+}
+
+impl ServerState {
+    /// Adds a command players and the console can run, replacing any with the same name.
+    pub fn register_command<F>(&self, spec: CommandSpec, handler: F)
+    where
+        F: Fn(CommandContext) -> CommandFuture + Send + Sync + 'static,
+    {
+        self.commands
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .register(spec, handler);
+    }
+
+    pub fn is_admin(&self, caller: &Caller) -> bool {
+        match caller {
+            Caller::Console => true,
+            Caller::Player(id) => self.admins.contains(id),
+        }
+    }
+
+    /// Runs `line`, a command name with its arguments and an optional leading `/`, on behalf
+    /// of `caller`.
+    pub async fn run_command(&self, caller: Caller, line: &str) -> CommandResult {
+        let line = line.trim();
+        let mut words = line.strip_prefix('/').unwrap_or(line).split_whitespace();
+        let Some(name) = words.next() else {
+            return Err(RequestError::new(
+                ErrorCode::InvalidArguments,
+                "type a command, or /help to list them",
+            ));
+        };
+        let command = self
+            .commands
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name);
+        let Some((spec, handler)) = command else {
+            return Err(RequestError::new(
+                ErrorCode::UnknownCommand,
+                format!("there is no /{name} command, /help lists them"),
+            ));
+        };
+        if spec.permission == Permission::Admin && !self.is_admin(&caller) {
+            return Err(RequestError::new(
+                ErrorCode::NotPermitted,
+                format!("only admins can run /{name}"),
+            ));
+        }
+
+        println!("{caller} ran: {line}");
+        handler(CommandContext {
+            state: self.clone(),
+            caller,
+            args: words.map(str::to_string).collect(),
+            spec,
+        })
+        .await
+    }
+}
+
+impl CommandContext {
+    /// The error for arguments the command cannot make sense of.
+    pub fn usage(&self) -> RequestError {
+        RequestError::new(
+            ErrorCode::InvalidArguments,
+            format!("usage: /{} {}", self.spec.name, self.spec.usage),
+        )
+    }
+
+    /// The online player with `name_or_id` as its id or, failing that, as its name.
+    pub fn online_player(&self, name_or_id: &str) -> Result<Player, RequestError> {
+        let world = self.state.simulation.subscribe().borrow().clone();
+        if let Some(player) = world.players.get(name_or_id) {
+            return Ok(player.clone());
+        }
+        let mut named = world.players.values().filter(|p| p.name == name_or_id);
+        match (named.next(), named.next()) {
+            (Some(player), None) => Ok(player.clone()),
+            (Some(_), Some(_)) => Err(RequestError::new(
+                ErrorCode::InvalidArguments,
+                format!("several players are called {name_or_id}, use an id from /list"),
+            )),
+            (None, _) => Err(RequestError::new(
+                ErrorCode::UnknownPlayer,
+                format!("nobody called {name_or_id} is online"),
+            )),
+        }
+    }
+
+    /// The id of the online player with `name_or_id` as its id or name, or else `name_or_id`
+    /// itself taken as the id of a player who is offline.
+    pub fn player_id(&self, name_or_id: &str) -> Result<String, RequestError> {
+        match self.online_player(name_or_id) {
+            Ok(player) => Ok(player.id),
+            Err(e) if e.code == ErrorCode::UnknownPlayer => Ok(name_or_id.to_string()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The id of the calling player. The console has none.
+    pub fn caller_id(&self) -> Result<&str, RequestError> {
+        match &self.caller {
+            Caller::Player(id) => Ok(id),
+            Caller::Console => Err(RequestError::new(
+                ErrorCode::InvalidArguments,
+                "the console has no player of its own, name one",
+            )),
+        }
+    }
+}
+
+const HELP: CommandSpec = CommandSpec {
+    name: "help",
+    usage: "",
+    help: "lists the commands you can run",
+    permission: Permission::Anyone,
+};
+
+fn help(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let commands = ctx
+            .state
+            .commands
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        let lines: Vec<String> = commands
+            .specs()
+            .filter(|spec| spec.permission == Permission::Anyone || ctx.state.is_admin(&ctx.caller))
+            .map(|spec| {
+                let command = format!("/{} {}", spec.name, spec.usage);
+                format!("{} - {}", command.trim_end(), spec.help)
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    })
+}
+
+const LIST: CommandSpec = CommandSpec {
+    name: "list",
+    usage: "",
+    help: "lists the players online",
+    permission: Permission::Anyone,
+};
+
+fn list(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let world = ctx.state.simulation.subscribe().borrow().clone();
+        let mut lines = vec![format!("{} players online", world.players.len())];
+        lines.extend(world.players.values().map(|p| {
+            let [x, y, z] = p.position;
+            format!("{} ({}) at {x:.1}, {y:.1}, {z:.1}", p.name, p.id)
+        }));
+        Ok(lines.join("\n"))
+    })
+}
+
+const SEED: CommandSpec = CommandSpec {
+    name: "seed",
+    usage: "",
+    help: "shows the seed the terrain was generated from",
+    permission: Permission::Anyone,
+};
+
+fn seed(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let settings = ctx.state.simulation.settings().await?;
+        Ok(format!("World seed: {}", settings.seed))
+    })
+}
+
+const SAVE: CommandSpec = CommandSpec {
+    name: "save",
+    usage: "",
+    help: "saves the world now",
+    permission: Permission::Admin,
+};
+
+fn save(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let Some(saves) = &ctx.state.saves else {
+            return Ok("This server has no save directory".to_string());
+        };
+        ctx.state
+            .save()
+            .await
+            .map_err(|e| RequestError::new(ErrorCode::Internal, format!("could not save: {e}")))?;
+        Ok(format!("Saved the world to {}", saves.path().display()))
+    })
+}
+
+const KICK: CommandSpec = CommandSpec {
+    name: "kick",
+    usage: "<player> [reason]",
+    help: "disconnects a player",
+    permission: Permission::Admin,
+};
+
+fn kick(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let Some((target, reason)) = ctx.args.split_first() else {
+            return Err(ctx.usage());
+        };
+        let player = ctx.online_player(target)?;
+        let reason = match reason {
+            [] => "kicked by an admin".to_string(),
+            words => words.join(" "),
+        };
+        let why = RequestError::new(ErrorCode::Kicked, reason);
+        if !lock_sessions(&ctx.state)?.kick(&player.id, why) {
+            return Err(RequestError::new(
+                ErrorCode::UnknownPlayer,
+                format!("{} is not connected", player.name),
+            ));
+        }
+        Ok(format!("Kicked {}", player.name))
+    })
+}
+
+const TP: CommandSpec = CommandSpec {
+    name: "tp",
+    usage: "[player] <x> <y>",
+    help: "moves a player, or yourself, onto the tile column at x, y",
+    permission: Permission::Admin,
+};
+
+fn tp(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let (player, coords) = match ctx.args.as_slice() {
+            [x, y] => (ctx.caller_id()?.to_string(), [x, y]),
+            [player, x, y] => (ctx.online_player(player)?.id, [x, y]),
+            _ => return Err(ctx.usage()),
+        };
+        let [Ok(x), Ok(y)] = coords.map(|c| c.parse::<i64>()) else {
+            return Err(ctx.usage());
+        };
+        let [x, y, z] = ctx.state.simulation.teleport(&player, [x, y]).await?;
+        Ok(format!("Teleported {player} to {x}, {y}, {z}"))
+    })
+}
+
+const STOP: CommandSpec = CommandSpec {
+    name: "stop",
+    usage: "[reason]",
+    help: "shuts the server down, telling everyone why",
+    permission: Permission::Admin,
+};
+
+fn stop(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let reason = match ctx.args.as_slice() {
+            [] => "the server is shutting down".to_string(),
+            words => words.join(" "),
+        };
+        ctx.state.stop(reason);
+        Ok("Stopping the server".to_string())
+    })
+}
+
+const MUTE: CommandSpec = CommandSpec {
+    name: "mute",
+    usage: "<player>",
+    help: "stops a player's chat from reaching anyone",
+    permission: Permission::Admin,
+};
+
+fn mute(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let [target] = ctx.args.as_slice() else {
+            return Err(ctx.usage());
+        };
+        let id = ctx.player_id(target)?;
+        if !lock_moderation(&ctx.state)?.mute(&id) {
+            return Ok(format!("{target} is already muted"));
+        }
+        Ok(format!("Muted {target}"))
+    })
+}
+
+const UNMUTE: CommandSpec = CommandSpec {
+    name: "unmute",
+    usage: "<player>",
+    help: "lets a muted player chat again",
+    permission: Permission::Admin,
+};
+
+fn unmute(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let [target] = ctx.args.as_slice() else {
+            return Err(ctx.usage());
+        };
+        let id = ctx.player_id(target)?;
+        if !lock_moderation(&ctx.state)?.unmute(&id) {
+            return Ok(format!("{target} is not muted"));
+        }
+        Ok(format!("Unmuted {target}"))
+    })
+}
+
+const BAN: CommandSpec = CommandSpec {
+    name: "ban",
+    usage: "<player> [reason]",
+    help: "disconnects a player and bans their id and the address they joined from",
+    permission: Permission::Admin,
+};
+
+fn ban(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let Some((target, reason)) = ctx.args.split_first() else {
+            return Err(ctx.usage());
+        };
+        let id = ctx.player_id(target)?;
+        let reason = match reason {
+            [] => "banned by an admin".to_string(),
+            words => words.join(" "),
+        };
+        lock_moderation(&ctx.state)?.ban(&id);
+        lock_sessions(&ctx.state)?.kick(&id, RequestError::new(ErrorCode::Banned, reason));
+        Ok(format!("Banned {target}"))
+    })
+}
+
+const UNBAN: CommandSpec = CommandSpec {
+    name: "unban",
+    usage: "<player id>",
+    help: "lets a banned player join again, from any address",
+    permission: Permission::Admin,
+};
+
+fn unban(ctx: CommandContext) -> CommandFuture {
+    Box::pin(async move {
+        let [id] = ctx.args.as_slice() else {
+            return Err(ctx.usage());
+        };
+        if !lock_moderation(&ctx.state)?.unban(id) {
+            return Ok(format!("{id} is not banned"));
+        }
+        Ok(format!("Unbanned {id}"))
+    })
+}
@@ -0,0 +1,354 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, de};
+use shared::{LinkConditions, WorldGen};
+use thiserror::Error;
+
+use crate::{
+    DEFAULT_CHAT_RADIUS, DEFAULT_MESSAGE_BURST, DEFAULT_MESSAGE_RATE, DEFAULT_PLAYER_SPEED,
+    DEFAULT_RECONNECT_GRACE, DEFAULT_SAVE_INTERVAL, DEFAULT_TICK_RATE,
+};
+
+pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0:5250";
+
+/// How long a new connection gets to send its `ClientHello`, when no timeout is configured.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long clients get to close their connections on shutdown, when no timeout is
+/// configured.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many pushed events a slow connection may fall behind before it misses some, when no
+/// capacity is configured.
+pub const DEFAULT_EVENT_CAPACITY: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Tcp,
+    Udp,
+}
+
+/// Everything a server is set up from. `load` reads one from a TOML file; see
+/// `server.example.toml` for every setting the file can hold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    pub bind: String,
+    /// Transport clients connect over.
+    pub transport: TransportKind,
+    /// Simulated network conditions on everything the server sends.
+    pub link: LinkConditions,
+    /// How many pushed events a slow connection may fall behind before it misses some.
+    pub event_capacity: usize,
+    /// How long a new connection gets to finish the handshake before it is dropped.
+    pub handshake_timeout: Duration,
+    /// How long shutting down waits for clients to close their connections.
+    pub shutdown_timeout: Duration,
+    /// Terrain of a newly generated world. A loaded save keeps the terrain it was saved with.
+    pub world: WorldGen,
+    /// Simulation ticks per second.
+    pub tick_rate: u32,
+    /// How far a new player moves per step, in tiles.
+    pub player_speed: f32,
+    /// How long a player who lost its connection can come back to where it was.
+    pub reconnect_grace: Duration,
+    /// How far nearby chat carries, in tiles.
+    pub chat_radius: f32,
+    /// Where accounts are kept across restarts. Without one they are forgotten on exit.
+    pub sessions_file: Option<PathBuf>,
+    /// Where the world is saved, and loaded from on startup. Without one every start
+    /// generates a new world.
+    pub save_dir: Option<PathBuf>,
+    /// How often the world is saved while running, on top of the save on shutdown.
+    pub save_interval: Duration,
+    /// Ids of the players allowed to run admin commands such as `/kick`.
+    pub admins: Vec<String>,
+    /// Chat messages and commands per second a connection may keep sending.
+    pub message_rate: f32,
+    /// How many messages a connection that has been quiet may send at once.
+    pub message_burst: u32,
+    /// Ids of the players whose chat is not relayed.
+    pub muted: Vec<String>,
+    /// Ids of the players who may not join.
+    pub banned: Vec<String>,
+    /// Words masked with `*` in chat, whatever their case.
+    pub blocked_words: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: DEFAULT_BIND_ADDR.to_string(),
+            transport: TransportKind::Tcp,
+            link: LinkConditions::default(),
+            event_capacity: DEFAULT_EVENT_CAPACITY,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            world: WorldGen::default(),
+            tick_rate: DEFAULT_TICK_RATE,
+            player_speed: DEFAULT_PLAYER_SPEED,
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+            chat_radius: DEFAULT_CHAT_RADIUS,
+            sessions_file: None,
+            save_dir: None,
+            save_interval: DEFAULT_SAVE_INTERVAL,
+            admins: Vec::new(),
+            message_rate: DEFAULT_MESSAGE_RATE,
+            message_burst: DEFAULT_MESSAGE_BURST,
+            muted: Vec::new(),
+            banned: Vec::new(),
+            blocked_words: Vec::new(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read {}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("invalid config file {}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// Every setting that is out of range, one per entry.
+    #[error("invalid configuration:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+impl ServerConfig {
+    /// Reads the TOML file at `path`. Settings it leaves out keep their defaults.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::from_toml(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Parses a config file's contents. Settings it leaves out keep their defaults, and
+    /// unknown ones are an error so that typos do not go unnoticed.
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        let file: ConfigFile = toml::from_str(text)?;
+        let mut config = Self::default();
+        file.apply(&mut config);
+        Ok(config)
+    }
+
+    /// Overwrites the setting `field` picks if a value was given, for layering the config file
+    /// and the command line over the defaults.
+    pub fn set<T>(&mut self, field: impl FnOnce(&mut Self) -> &mut T, value: Option<T>) {
+        if let Some(value) = value {
+            *field(self) = value;
+        }
+    }
+
+    /// Checks every setting is usable, listing all that are not.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        let port = self
+            .bind
+            .rsplit_once(':')
+            .map(|(_, port)| port.parse::<u16>());
+        if !matches!(port, Some(Ok(_))) {
+            problems.push(format!(
+                "network.bind must be `host:port`, got `{}`",
+                self.bind
+            ));
+        }
+        if self.event_capacity == 0 {
+            problems.push("network.event_capacity must be at least 1".to_string());
+        }
+        if self.handshake_timeout.is_zero() {
+            problems.push("network.handshake_timeout must be at least 1 second".to_string());
+        }
+        if !(self.world.noise_scale.is_finite() && self.world.noise_scale > 0.0) {
+            problems.push(format!(
+                "worldgen.noise_scale must be above 0, got {}",
+                self.world.noise_scale
+            ));
+        }
+        if self.world.levels == 0 {
+            problems.push("worldgen.levels must be at least 1".to_string());
+        }
+        if !(1..=1000).contains(&self.tick_rate) {
+            problems.push(format!(
+                "gameplay.tick_rate must be between 1 and 1000, got {}",
+                self.tick_rate
+            ));
+        }
+        // A step longer than a tile could jump over a tile it should have climbed.
+        if !(self.player_speed > 0.0 && self.player_speed <= 1.0) {
+            problems.push(format!(
+                "gameplay.player_speed must be above 0 and at most 1, got {}",
+                self.player_speed
+            ));
+        }
+        if !(self.chat_radius.is_finite() && self.chat_radius > 0.0) {
+            problems.push(format!(
+                "gameplay.chat_radius must be above 0, got {}",
+                self.chat_radius
+            ));
+        }
+        if !(self.message_rate.is_finite() && self.message_rate > 0.0) {
+            problems.push(format!(
+                "moderation.message_rate must be above 0, got {}",
+                self.message_rate
+            ));
+        }
+        if self.message_burst == 0 {
+            problems.push("moderation.message_burst must be at least 1".to_string());
+        }
+        if self.save_interval.is_zero() {
+            problems.push("persistence.save_interval must be at least 1 second".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+/// The layout of a config file. Every setting is optional.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    network: NetworkSection,
+    worldgen: WorldGenSection,
+    gameplay: GameplaySection,
+    persistence: PersistenceSection,
+    permissions: PermissionsSection,
+    moderation: ModerationSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct NetworkSection {
+    bind: Option<String>,
+    transport: Option<TransportKind>,
+    #[serde(deserialize_with = "link")]
+    link: Option<LinkConditions>,
+    event_capacity: Option<usize>,
+    /// Seconds.
+    handshake_timeout: Option<u64>,
+    /// Seconds.
+    shutdown_timeout: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct WorldGenSection {
+    seed: Option<u32>,
+    noise_scale: Option<f64>,
+    levels: Option<u8>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct GameplaySection {
+    tick_rate: Option<u32>,
+    player_speed: Option<f32>,
+    /// Seconds.
+    reconnect_grace: Option<u64>,
+    chat_radius: Option<f32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct PersistenceSection {
+    sessions_file: Option<PathBuf>,
+    save_dir: Option<PathBuf>,
+    /// Seconds.
+    save_interval: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct PermissionsSection {
+    admins: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ModerationSection {
+    message_rate: Option<f32>,
+    message_burst: Option<u32>,
+    muted: Option<Vec<String>>,
+    banned: Option<Vec<String>>,
+    blocked_words: Option<Vec<String>>,
+}
+
+impl ConfigFile {
+    fn apply(self, config: &mut ServerConfig) {
+        let Self {
+            network,
+            worldgen,
+            gameplay,
+            persistence,
+            permissions,
+            moderation,
+        } = self;
+
+        config.set(|c| &mut c.bind, network.bind);
+        config.set(|c| &mut c.transport, network.transport);
+        config.set(|c| &mut c.link, network.link);
+        config.set(|c| &mut c.event_capacity, network.event_capacity);
+        config.set(
+            |c| &mut c.handshake_timeout,
+            network.handshake_timeout.map(Duration::from_secs),
+        );
+        config.set(
+            |c| &mut c.shutdown_timeout,
+            network.shutdown_timeout.map(Duration::from_secs),
+        );
+
+        config.set(|c| &mut c.world.seed, worldgen.seed);
+        config.set(|c| &mut c.world.noise_scale, worldgen.noise_scale);
+        config.set(|c| &mut c.world.levels, worldgen.levels);
+
+        config.set(|c| &mut c.tick_rate, gameplay.tick_rate);
+        config.set(|c| &mut c.player_speed, gameplay.player_speed);
+        config.set(
+            |c| &mut c.reconnect_grace,
+            gameplay.reconnect_grace.map(Duration::from_secs),
+        );
+        config.set(|c| &mut c.chat_radius, gameplay.chat_radius);
+
+        config.set(
+            |c| &mut c.sessions_file,
+            persistence.sessions_file.map(Some),
+        );
+        config.set(|c| &mut c.save_dir, persistence.save_dir.map(Some));
+        config.set(
+            |c| &mut c.save_interval,
+            persistence.save_interval.map(Duration::from_secs),
+        );
+
+        config.set(|c| &mut c.admins, permissions.admins);
+
+        config.set(|c| &mut c.message_rate, moderation.message_rate);
+        config.set(|c| &mut c.message_burst, moderation.message_burst);
+        config.set(|c| &mut c.muted, moderation.muted);
+        config.set(|c| &mut c.banned, moderation.banned);
+        config.set(|c| &mut c.blocked_words, moderation.blocked_words);
+    }
+}
+
+/// Reads `link` in the same `latency=75ms,loss=0.02` format as the command line.
+fn link<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<LinkConditions>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse()
+        .map(Some)
+        .map_err(|e: anyhow::Error| de::Error::custom(format!("{e:#}")))
+}
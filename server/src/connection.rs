@@ -0,0 +1,385 @@
+use std::{net::SocketAddr, sync::MutexGuard};
+
+use tokio::{
+    sync::{
+        broadcast::error::RecvError,
+        mpsc::{UnboundedSender, unbounded_channel},
+        watch,
+    },
+    time::timeout,
+};
+
+use shared::{
+    Capabilities, ClientEnvelope, ClientMessage, ErrorCode, FrameError, MAX_CHUNKS_PER_REQUEST,
+    MessageReader, MessageWriter, RequestError, RequestId, ServerEnvelope, ServerMessage,
+    SessionToken, SnapshotSender, check_name, chunk_in_bounds, server_handshake,
+};
+use uuid::Uuid;
+
+use crate::{Caller, Event, RateLimit, ServerState, Sessions, chat, moderation::lock_moderation};
+
+/// Map requests per second a connection may keep sending.
+pub const MAP_REQUEST_RATE: f32 = 5.0;
+
+/// Map requests a connection may send at once, enough for a client viewing as far as
+/// `MAX_VIEW_RADIUS` to fill its view in one go.
+pub const MAP_REQUEST_BURST: u32 = 10;
+
+/// What the requests on one connection share.
+struct Session {
+    /// Unique to this connection. Until the client joins it is also the player id.
+    connection: String,
+    /// The player this connection controls once joined.
+    id: String,
+    peer: SocketAddr,
+    joined: bool,
+    /// Issued when the connection joins.
+    token: Option<SessionToken>,
+    outgoing: UnboundedSender<ServerEnvelope>,
+    acks: watch::Sender<Option<u32>>,
+    /// The joined player, so the writer knows which events are meant for this connection.
+    player: watch::Sender<Option<String>>,
+    /// Handed to `Sessions` on join, which sends an error through it to disconnect this
+    /// connection.
+    kick: UnboundedSender<RequestError>,
+    /// How many more chat messages and commands the client may send right now.
+    messages: RateLimit,
+    /// How many more map requests the client may send right now.
+    map_requests: RateLimit,
+}
+
+pub(crate) fn lock_sessions(state: &ServerState) -> Result<MutexGuard<'_, Sessions>, RequestError> {
+    state
+        .sessions
+        .lock()
+        .map_err(|_| RequestError::new(ErrorCode::Internal, "the session store is unavailable"))
+}
+
+/// Carries out one request. Replies go to `session.outgoing`; a returned error is reported to
+/// the client as a `ServerMessage::Error` answering the same request.
+async fn handle_client_message(
+    msg: ClientMessage,
+    request: RequestId,
+    session: &mut Session,
+    state: &ServerState,
+) -> Result<(), RequestError> {
+    let simulation = &state.simulation;
+    let reply = |msg: ServerMessage| {
+        session
+            .outgoing
+            .send(ServerEnvelope::reply(request, msg))
+            .map_err(|e| {
+                RequestError::new(ErrorCode::Internal, format!("could not queue reply: {e}"))
+            })
+    };
+
+    match msg {
+        // The reader loop stops after this, and the player is removed like on any disconnect.
+        ClientMessage::Disconnect => {
+            println!("Client {} is leaving", session.peer);
+        }
+        ClientMessage::ConnectionRequest { token, name } => {
+            check_name(&name).map_err(|e| RequestError::new(ErrorCode::InvalidName, e))?;
+            let account = match &token {
+                Some(token) => lock_sessions(state)?.account(token).map(str::to_string),
+                None => None,
+            };
+            {
+                let mut moderation = lock_moderation(state)?;
+                if let Some(id) = &account {
+                    moderation.seen(id, session.peer.ip());
+                }
+                // Seeing a banned account bans its address too, so this refuses both.
+                if let Some(id) = moderation.banned_address(session.peer.ip()) {
+                    println!("Refused banned player {id} from client {}", session.peer);
+                    return Err(RequestError::new(
+                        ErrorCode::Banned,
+                        "you are banned from this server",
+                    ));
+                }
+            }
+            if !session.joined {
+                let (id, token) = lock_sessions(state)?.claim(
+                    token.as_ref(),
+                    &session.connection,
+                    session.kick.clone(),
+                )?;
+                lock_moderation(state)?.seen(&id, session.peer.ip());
+                session.player.send_replace(Some(id.clone()));
+                session.id = id;
+                session.token = Some(token);
+                session.joined = true;
+            }
+
+            let joined = simulation.join(&session.id, &name).await?;
+            println!("Client {} joined as {name}", session.peer);
+            reply(ServerMessage::Player(joined.player))?;
+            if let Some(token) = &session.token {
+                reply(ServerMessage::Session(token.clone()))?;
+            }
+            reply(ServerMessage::Map(joined.map))?;
+            reply(ServerMessage::Roster(joined.others))?;
+        }
+        ClientMessage::MapRequest(coords) => {
+            if !session.joined {
+                return Err(RequestError::new(
+                    ErrorCode::NotConnected,
+                    "send a connection request before asking for the map",
+                ));
+            }
+            if !session.map_requests.allow() {
+                println!("Client {} is asking for the map too fast", session.peer);
+                return Err(RequestError::new(
+                    ErrorCode::RateLimited,
+                    "you are asking for the map too fast, wait a moment",
+                ));
+            }
+            if coords.len() > MAX_CHUNKS_PER_REQUEST {
+                return Err(RequestError::new(
+                    ErrorCode::InvalidChunks,
+                    format!("ask for at most {MAX_CHUNKS_PER_REQUEST} chunks at once"),
+                ));
+            }
+            if let Some([x, y]) = coords.iter().find(|&&coord| !chunk_in_bounds(coord)) {
+                return Err(RequestError::new(
+                    ErrorCode::InvalidChunks,
+                    format!("chunk {x}, {y} is beyond the edge of the world"),
+                ));
+            }
+            let chunks = simulation.map(&session.id, coords).await?;
+            reply(ServerMessage::Map(chunks))?;
+        }
+        ClientMessage::MoveRequest { direction } => {
+            println!("Move request from client {}", session.peer);
+            if !session.joined {
+                return Err(RequestError::new(
+                    ErrorCode::NotConnected,
+                    "send a connection request before moving",
+                ));
+            }
+            simulation.queue_move(&session.id, direction)?;
+        }
+        ClientMessage::SnapshotAck { tick } => {
+            session.acks.send_if_modified(|acked| {
+                let newer = acked.is_none_or(|acked| tick > acked);
+                if newer {
+                    *acked = Some(tick);
+                }
+                newer
+            });
+        }
+        ClientMessage::MessageRequest(message) => {
+            if !session.joined {
+                return Err(RequestError::new(
+                    ErrorCode::NotConnected,
+                    "send a connection request before chatting",
+                ));
+            }
+            if !session.messages.allow() {
+                println!("Client {} is sending messages too fast", session.peer);
+                return Err(RequestError::new(
+                    ErrorCode::RateLimited,
+                    "you are sending messages too fast, wait a moment",
+                ));
+            }
+            if message.message.starts_with('/') {
+                let caller = Caller::Player(session.id.clone());
+                let output = state.run_command(caller, &message.message).await?;
+                reply(ServerMessage::CommandOutput(output))?;
+                return Ok(());
+            }
+            let message = state.moderate(&session.id, message).inspect_err(|e| {
+                println!("Refused a message from player {}: {e}", session.id);
+            })?;
+            let world = simulation.subscribe().borrow().clone();
+            let event = chat::route(message, &session.id, &world, state.chat_radius)?;
+            // Nobody being connected to receive it is not the sender's problem.
+            let _ = state.events.send(event);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs one client from handshake to disconnect, reading its requests while writing replies,
+/// snapshots and events. When the client goes, its player is removed and everyone else is
+/// told. When the server shuts down, the client is told why and the connection closed.
+pub(crate) async fn handle_connection<R, W>(
+    mut reader: R,
+    mut writer: W,
+    peer: SocketAddr,
+    state: ServerState,
+) where
+    R: MessageReader + 'static,
+    W: MessageWriter + 'static,
+{
+    // A client that never says hello would otherwise hold its connection open forever.
+    let handshake = server_handshake(&mut reader, &mut writer, Capabilities::ALL);
+    match timeout(state.handshake_timeout, handshake).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => {
+            println!("Handshake with client {peer} failed: {e}");
+            return;
+        }
+        Err(_) => {
+            println!("Client {peer} did not finish the handshake in time");
+            return;
+        }
+    }
+
+    let connection = Uuid::new_v4().to_string();
+
+    let (outgoing, mut outgoing_rx) = unbounded_channel::<ServerEnvelope>();
+    let (acks, mut acked) = watch::channel(None);
+    let (player, joined_as) = watch::channel(None::<String>);
+    let (kick, mut kicks) = unbounded_channel();
+    let mut snapshots = state.simulation.subscribe();
+    let mut events = state.events.subscribe();
+    let mut shutdown = state.shutdown.subscribe();
+
+    let reading = {
+        let mut session = Session {
+            id: connection.clone(),
+            connection,
+            peer,
+            joined: false,
+            token: None,
+            outgoing,
+            acks,
+            player,
+            kick,
+            messages: RateLimit::new(state.message_rate, state.message_burst),
+            map_requests: RateLimit::new(MAP_REQUEST_RATE, MAP_REQUEST_BURST),
+        };
+        async move {
+            loop {
+                let received = tokio::select! {
+                    received = reader.recv::<ClientEnvelope>() => received,
+                    reason = shutdown.wait_for(Option::is_some) => {
+                        let reason = reason.ok().and_then(|reason| reason.clone()).unwrap_or_default();
+                        let _ = session
+                            .outgoing
+                            .send(ServerEnvelope::push(ServerMessage::Shutdown(reason)));
+                        break;
+                    }
+                    Some(error) = kicks.recv() => {
+                        println!("Disconnecting client {}: {error}", session.peer);
+                        let _ = session.outgoing.send(ServerEnvelope::push(error.into()));
+                        break;
+                    }
+                };
+                match received {
+                    Ok(ClientEnvelope {
+                        id: request,
+                        message,
+                    }) => {
+                        let leaving = matches!(message, ClientMessage::Disconnect);
+                        let result =
+                            handle_client_message(message, request, &mut session, &state).await;
+                        if let Err(e) = result {
+                            println!("Request {request} from client {} failed: {e}", session.peer);
+                            let _ = session
+                                .outgoing
+                                .send(ServerEnvelope::reply(request, e.into()));
+                        }
+                        if leaving {
+                            break;
+                        }
+                    }
+                    Err(FrameError::Closed) => {
+                        println!("Client {} disconnected", session.peer);
+                        break;
+                    }
+                    Err(e) if e.is_malformed() => {
+                        println!(
+                            "Dropping client {} after malformed frame: {e}",
+                            session.peer
+                        );
+                        let error = RequestError::new(ErrorCode::Malformed, e.to_string());
+                        let _ = session.outgoing.send(ServerEnvelope::push(error.into()));
+                        break;
+                    }
+                    Err(e) => {
+                        println!("Client {} disconnected: {e}", session.peer);
+                        break;
+                    }
+                }
+            }
+
+            // A player taken over by another connection stays in the world for it.
+            let still_ours = session.joined
+                && lock_sessions(&state)
+                    .is_ok_and(|mut sessions| sessions.release(&session.id, &session.connection));
+            if still_ours {
+                let _ = state.simulation.leave(&session.id);
+                let _ = state
+                    .events
+                    .send(Event::everyone(ServerMessage::Disconnect(session.id)));
+            }
+            // Dropping the session closes `outgoing`, which stops the writer.
+        }
+    };
+
+    let writing = async move {
+        let mut replication = SnapshotSender::default();
+
+        loop {
+            tokio::select! {
+                outgoing_msg = outgoing_rx.recv() => {
+                    // The reading half owns the only sender, so `None` means the client is gone.
+                    let Some(msg) = outgoing_msg else {
+                        break;
+                    };
+                    if let Err(e) = writer.send(&msg).await {
+                        println!("Error sending message to client {peer}: {e}");
+                        break;
+                    }
+                    if matches!(msg.message, ServerMessage::Shutdown(_)) {
+                        break;
+                    }
+                }
+
+                event = events.recv() => {
+                    let msg = match event {
+                        Ok(event) if event.reaches(joined_as.borrow().as_deref()) => event.message,
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(missed)) => RequestError::new(
+                            ErrorCode::MissedUpdates,
+                            format!("fell behind and missed {missed} updates"),
+                        )
+                        .into(),
+                        Err(RecvError::Closed) => break,
+                    };
+                    if let Err(e) = writer.send(&ServerEnvelope::push(msg)).await {
+                        println!("Error sending event to client {peer}: {e}");
+                        break;
+                    }
+                }
+
+                changed = snapshots.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    if let Some(tick) = *acked.borrow_and_update() {
+                        replication.ack(tick);
+                    }
+                    let state = snapshots.borrow_and_update().clone();
+                    let Some(snapshot) = replication.next(&state) else {
+                        continue;
+                    };
+                    let msg = ServerEnvelope::push(ServerMessage::Snapshot(snapshot));
+                    if let Err(e) = writer.send(&msg).await {
+                        println!("Error sending snapshot to client {peer}: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = writer.close().await {
+            println!("Error closing connection to client {peer}: {e}");
+        }
+    };
+
+    tokio::join!(reading, writing);
+}
@@ -0,0 +1,33 @@
+use std::{io, thread};
+
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::{ServerState, commands::Caller};
+
+/// Runs every line typed on the server's standard input as a command from the console, until
+/// the input closes.
+pub(crate) async fn run(state: ServerState) {
+    // Tokio reads stdin on a blocking thread that keeps the runtime from exiting while it
+    // waits for a line, so a detached thread reads it instead.
+    let (lines_tx, mut lines) = unbounded_channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if lines_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(line) = lines.recv().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match state.run_command(Caller::Console, &line).await {
+            Ok(output) => println!("{output}"),
+            Err(e) => println!("{e}"),
+        }
+    }
+}
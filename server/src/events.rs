@@ -0,0 +1,41 @@
+use shared::ServerMessage;
+
+/// Which connections an `Event` is pushed to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Audience {
+    /// Every connection, including ones that have not joined yet.
+    Everyone,
+    /// Only the connections controlling these players.
+    Players(Vec<String>),
+}
+
+/// A message pushed to connected clients through `ServerState::events`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub message: ServerMessage,
+    pub audience: Audience,
+}
+
+impl Event {
+    pub fn everyone(message: ServerMessage) -> Self {
+        Self {
+            message,
+            audience: Audience::Everyone,
+        }
+    }
+
+    pub fn to(players: Vec<String>, message: ServerMessage) -> Self {
+        Self {
+            message,
+            audience: Audience::Players(players),
+        }
+    }
+
+    /// Whether a connection controlling `player`, or no player yet, gets this event.
+    pub fn reaches(&self, player: Option<&str>) -> bool {
+        match &self.audience {
+            Audience::Everyone => true,
+            Audience::Players(players) => player.is_some_and(|id| players.iter().any(|p| p == id)),
+        }
+    }
+}
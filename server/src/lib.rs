@@ -0,0 +1,350 @@
+use std::{
+    collections::HashSet,
+    future, io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use tokio::{
+    io::split,
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, watch},
+    task::{JoinSet, spawn_blocking},
+    time::{MissedTickBehavior, interval, sleep, timeout},
+};
+
+use shared::{
+    ConditionedWriter, FrameConfig, FrameReader, FrameWriter, LinkConditions, UdpConfig,
+    UdpConnection, UdpListener,
+};
+
+use crate::connection::handle_connection;
+
+mod chat;
+mod commands;
+mod config;
+mod connection;
+mod console;
+mod events;
+mod moderation;
+mod save;
+mod sessions;
+mod simulation;
+mod state;
+
+pub use chat::{DEFAULT_CHAT_RADIUS, route};
+pub use commands::{
+    Caller, CommandContext, CommandFuture, CommandResult, CommandSpec, Commands, Permission,
+};
+pub use config::{
+    ConfigError, DEFAULT_BIND_ADDR, DEFAULT_EVENT_CAPACITY, DEFAULT_HANDSHAKE_TIMEOUT,
+    DEFAULT_SHUTDOWN_TIMEOUT, ServerConfig, TransportKind,
+};
+pub use connection::{MAP_REQUEST_BURST, MAP_REQUEST_RATE};
+pub use events::{Audience, Event};
+pub use moderation::{
+    ChatFilter, DEFAULT_MESSAGE_BURST, DEFAULT_MESSAGE_RATE, Moderation, RateLimit, WordFilter,
+};
+pub use save::{DEFAULT_SAVE_INTERVAL, SAVE_VERSION, SaveDir, WorldSave};
+pub use sessions::Sessions;
+pub use simulation::{
+    DEFAULT_RECONNECT_GRACE, DEFAULT_TICK_RATE, Joined, MAP_REACH, NEARBY_CHUNK_RADIUS, Simulation,
+};
+pub use state::{DEFAULT_PLAYER_SPEED, World};
+
+/// Clients only ever send small requests, so anything bigger than this is treated as hostile.
+const CLIENT_FRAMES: FrameConfig = FrameConfig {
+    max_frame_len: 64 * 1024,
+};
+
+/// How long to wait before accepting again after the listener failed to accept a client.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Everything the connections of one server share. Clones are cheap and see the same world.
+#[derive(Clone)]
+pub struct ServerState {
+    pub simulation: Simulation,
+    /// Messages pushed to connected clients, such as players leaving or chat.
+    pub events: broadcast::Sender<Event>,
+    pub sessions: Arc<Mutex<Sessions>>,
+    /// How far nearby chat carries, in tiles.
+    chat_radius: f32,
+    commands: Arc<RwLock<Commands>>,
+    /// Ids of the players allowed to run admin commands.
+    admins: Arc<HashSet<String>>,
+    /// Who may not chat or join. Changes are forgotten when the server exits.
+    pub moderation: Arc<Mutex<Moderation>>,
+    filters: Arc<RwLock<Vec<Arc<dyn ChatFilter>>>>,
+    /// Chat messages and commands per second each connection may keep sending.
+    message_rate: f32,
+    message_burst: u32,
+    /// How long a new connection gets to finish the handshake.
+    handshake_timeout: Duration,
+    saves: Option<SaveDir>,
+    /// Set to the reason once the server starts shutting down.
+    shutdown: watch::Sender<Option<String>>,
+}
+
+impl ServerState {
+    /// Starts simulating `world` with the timings in `config`, saving it to `saves` every
+    /// `config.save_interval`.
+    pub fn new(
+        world: World,
+        sessions: Sessions,
+        saves: Option<SaveDir>,
+        config: &ServerConfig,
+    ) -> Self {
+        let state = Self {
+            simulation: Simulation::spawn(world, config.tick_rate, config.reconnect_grace),
+            events: broadcast::channel(config.event_capacity).0,
+            sessions: Arc::new(Mutex::new(sessions)),
+            chat_radius: config.chat_radius,
+            commands: Arc::new(RwLock::new(Commands::builtin())),
+            admins: Arc::new(config.admins.iter().cloned().collect()),
+            moderation: Arc::new(Mutex::new(Moderation::new(&config.muted, &config.banned))),
+            filters: Arc::new(RwLock::new(Vec::new())),
+            message_rate: config.message_rate,
+            message_burst: config.message_burst,
+            handshake_timeout: config.handshake_timeout,
+            saves,
+            shutdown: watch::channel(None).0,
+        };
+
+        if !config.blocked_words.is_empty() {
+            state.add_chat_filter(WordFilter::new(&config.blocked_words));
+        }
+
+        if state.saves.is_some() {
+            let saving = state.clone();
+            let mut ticker = interval(config.save_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            tokio::spawn(async move {
+                // The first tick is immediate and there is nothing new to save yet.
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    if let Err(e) = saving.save().await {
+                        eprintln!("Failed to save the world: {e}");
+                    }
+                }
+            });
+        }
+
+        state
+    }
+
+    /// Writes the world to the save directory, if there is one.
+    pub async fn save(&self) -> io::Result<()> {
+        let Some(saves) = self.saves.clone() else {
+            return Ok(());
+        };
+        let save = self
+            .simulation
+            .save()
+            .await
+            .map_err(|e| io::Error::other(e.message))?;
+        spawn_blocking(move || saves.store(&save)).await?
+    }
+
+    /// Makes the server shut down as if `run_until` had been told to, with `reason`.
+    pub fn stop(&self, reason: String) {
+        self.shutdown.send_if_modified(|shutdown| {
+            let first = shutdown.is_none();
+            shutdown.get_or_insert(reason);
+            first
+        });
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Udp(UdpListener),
+}
+
+/// A new client, before its connection is set up.
+enum Accepted {
+    Tcp(TcpStream),
+    Udp(UdpConnection),
+}
+
+impl Listener {
+    async fn accept(&mut self) -> io::Result<(Accepted, SocketAddr)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((Accepted::Tcp(stream), addr))
+            }
+            Listener::Udp(listener) => {
+                let (connection, addr) = listener.accept().await?;
+                Ok((Accepted::Udp(connection), addr))
+            }
+        }
+    }
+
+    /// Whether `e`, returned by `accept`, means no more clients can be accepted. A TCP listener
+    /// also fails for single clients, or while the process is out of file descriptors, and
+    /// recovers from that. The UDP listener only fails once its socket is gone.
+    fn is_fatal(&self, e: &io::Error) -> bool {
+        match self {
+            Listener::Tcp(_) => matches!(
+                e.kind(),
+                io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported
+            ),
+            Listener::Udp(_) => true,
+        }
+    }
+}
+
+/// A bound server. Nothing is accepted until `run` is called.
+pub struct Server {
+    listener: Listener,
+    link: LinkConditions,
+    shutdown_timeout: Duration,
+    state: ServerState,
+}
+
+impl Server {
+    /// Binds the listener. The world is the one saved in `config.save_dir` if there is one,
+    /// and `world` otherwise.
+    pub async fn bind(config: ServerConfig, world: World) -> io::Result<Self> {
+        let listener = match config.transport {
+            TransportKind::Tcp => Listener::Tcp(TcpListener::bind(&config.bind).await?),
+            TransportKind::Udp => Listener::Udp(
+                UdpListener::bind(
+                    &config.bind,
+                    UdpConfig {
+                        frame: CLIENT_FRAMES,
+                        conditions: config.link,
+                        ..UdpConfig::default()
+                    },
+                )
+                .await?,
+            ),
+        };
+
+        let sessions = match &config.sessions_file {
+            Some(path) => Sessions::open(path.clone())?,
+            None => Sessions::in_memory(),
+        };
+
+        let saves = config.save_dir.clone().map(SaveDir::create).transpose()?;
+        let world = match &saves {
+            Some(saves) => match saves.load()? {
+                Some(save) => {
+                    println!("Loaded the world saved in {}", saves.path().display());
+                    World::restore(save, config.player_speed)
+                }
+                None => world,
+            },
+            None => world,
+        };
+
+        Ok(Self {
+            listener,
+            link: config.link,
+            shutdown_timeout: config.shutdown_timeout,
+            state: ServerState::new(world, sessions, saves, &config),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.listener {
+            Listener::Tcp(listener) => listener.local_addr(),
+            Listener::Udp(listener) => Ok(listener.local_addr()),
+        }
+    }
+
+    pub fn state(&self) -> &ServerState {
+        &self.state
+    }
+
+    /// Runs what is typed on standard input as commands from the console.
+    pub fn spawn_console(&self) {
+        tokio::spawn(console::run(self.state.clone()));
+    }
+
+    /// Accepts clients until the listener fails for good.
+    pub async fn run(self) -> io::Result<()> {
+        self.run_until(future::pending()).await
+    }
+
+    /// Accepts clients until the listener fails for good, or `shutdown` resolves or `/stop` is
+    /// run with the reason to give them. Then every client is told that reason, and once their
+    /// connections have closed or `shutdown_timeout` has passed, the world is saved. Returns the
+    /// listener's error if that is what stopped it.
+    pub async fn run_until(mut self, shutdown: impl Future<Output = String>) -> io::Result<()> {
+        let mut connections = JoinSet::new();
+        tokio::pin!(shutdown);
+        let mut stopped = self.state.shutdown.subscribe();
+        let mut failed = None;
+        let mut back_off = false;
+
+        let reason = loop {
+            if back_off {
+                sleep(ACCEPT_BACKOFF).await;
+                back_off = false;
+            }
+            tokio::select! {
+                reason = &mut shutdown => break reason,
+                Ok(reason) = stopped.wait_for(Option::is_some) => {
+                    break reason.clone().unwrap_or_default();
+                }
+                accepted = self.listener.accept() => {
+                    let (accepted, addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) if self.listener.is_fatal(&e) => {
+                            println!("Cannot accept clients any more: {e}");
+                            failed = Some(e);
+                            break "the server can no longer accept clients".to_string();
+                        }
+                        Err(e) => {
+                            println!("Could not accept a client: {e}");
+                            back_off = true;
+                            continue;
+                        }
+                    };
+                    println!("Client connected: {addr}");
+
+                    let state = self.state.clone();
+                    match accepted {
+                        Accepted::Tcp(stream) => {
+                            let (reader, writer) = split(stream);
+                            let reader = FrameReader::with_config(reader, CLIENT_FRAMES);
+                            let writer =
+                                ConditionedWriter::new(FrameWriter::new(writer), self.link);
+                            connections.spawn(handle_connection(reader, writer, addr, state));
+                        }
+                        Accepted::Udp(connection) => {
+                            let (reader, writer) = connection.into_split();
+                            connections.spawn(handle_connection(reader, writer, addr, state));
+                        }
+                    }
+                }
+                // Reaps finished connections, so that only live ones are waited for below.
+                Some(_) = connections.join_next() => {}
+            }
+        };
+
+        // The listener stays open until the end: UDP connections share its socket.
+        println!("Shutting down: {reason}");
+        self.state.shutdown.send_replace(Some(reason));
+        let closed = timeout(self.shutdown_timeout, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if closed.is_err() {
+            println!(
+                "Dropping {} connections that did not close in time",
+                connections.len()
+            );
+            connections.shutdown().await;
+        }
+
+        if let Some(saves) = &self.state.saves {
+            self.state.save().await?;
+            println!("Saved the world to {}", saves.path().display());
+        }
+        failed.map_or(Ok(()), Err)
+    }
+}
@@ -1,211 +1,178 @@
-use std::net::SocketAddr;
+use std::{future, path::PathBuf, time::Duration};
 
 use anyhow::Result;
-use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::{
-        broadcast::{self, error::RecvError},
-        mpsc::{UnboundedSender, unbounded_channel},
-    },
-};
-
-use shared::{ClientMessage, Player, ServerMessage, read_message, send_message};
-use uuid::Uuid;
-
-use crate::state::{PLAYERS, TILE_MANAGER};
-
-mod state;
-
-async fn handle_client_message(
-    msg: ClientMessage,
-    tx: &broadcast::Sender<ServerMessage>,
-    incoming_tx: &UnboundedSender<ServerMessage>,
-    addr: &SocketAddr,
-    id: String,
-) {
-    match msg {
-        ClientMessage::Disconnect => {
-            println!("Client disconnected");
-            return;
-        }
-        ClientMessage::ConnectionRequest => {
-            let player = {
-                let mut players = PLAYERS.write().await;
-                let map = TILE_MANAGER.read().await;
-                let pos = if let Some(pos) = map
-                    .tiles
-                    .iter()
-                    .filter(|((x, y, z), tile)| *x == 0 && *y == 0)
-                    .map(|((x, y, z), tile)| [*x as f32, *y as f32, *z as f32])
-                    .next()
-                {
-                    pos
-                } else {
-                    [0.0, 0.0, 0.0]
-                };
-                let player = players.entry(id.clone()).or_insert(Player {
-                    id: id.clone(),
-                    position: pos,
-                    speed: 0.025,
-                });
-                player.clone()
-            };
-
-            if let Err(e) = incoming_tx.send(ServerMessage::Player(player.clone())) {
-                println!("Could not update client {addr} new player: {e}");
-                return;
-            }
-
-            if let Err(e) = incoming_tx.send(ServerMessage::Map(TILE_MANAGER.read().await.clone()))
-            {
-                println!("Could not update client {addr} with map: {e}");
-            }
-
-            if let Err(e) = tx.send(ServerMessage::OtherPlayer(player)) {
-                println!("Could not broadcast client {addr} player to other clients: {e}");
-            }
-        }
-        ClientMessage::MoveRequest { player, direction } => {
-            println!("Move request from client {addr}");
-            let player: Option<Player> = {
-                let mut players = PLAYERS.write().await;
-
-                let player = if let Some(player) = players.get_mut(&player) {
-                    let new_x = player.position[0] + direction[0] * player.speed;
-                    let new_y = player.position[1] + direction[1] * player.speed;
-                    let current_z = player.position[2];
-
-                    let tx = new_x.floor() as i64;
-                    let ty = new_y.floor() as i64;
-                    let tz = current_z.floor() as i64;
-
-                    let should_jump = {
-                        let map = TILE_MANAGER.read().await;
-                        map.tiles.get(&(tx, ty, tz + 1)).is_some()
-                    };
-
-                    let should_fall = {
-                        let map = TILE_MANAGER.read().await;
-                        map.tiles.get(&(tx, ty, tz - 1)).is_some()
-                    };
-
-                    if should_jump {
-                        player.position[2] += 1.0;
-                    } else if should_fall {
-                        player.position[2] -= 1.0;
-                    } else {
-                        player.position[0] = new_x;
-                        player.position[1] = new_y;
-                    }
-
-                    Some(player.clone())
-                } else {
-                    None
-                };
-
-                player
-            };
-
-            if let Some(player) = player {
-                if let Err(e) = incoming_tx.send(ServerMessage::Player(player.clone())) {
-                    println!("Could not update player location: {e}");
-                    return;
-                }
-                if let Err(e) = tx.send(ServerMessage::OtherPlayer(player.clone())) {
-                    println!("Could not broadcast player location: {e}");
-                }
-            }
-        }
-        _ => {}
-    }
+use clap::Parser;
+
+use server::{Server, ServerConfig, TransportKind, World};
+use shared::LinkConditions;
+use tokio::signal::ctrl_c;
+#[cfg(unix)]
+use tokio::signal::unix::{SignalKind, signal};
+
+/// Every option overrides the same setting from `--config`, which in turn overrides the
+/// defaults.
+#[derive(Parser)]
+struct Args {
+    /// TOML file with the server's settings. See `server.example.toml` for all of them.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Address to listen on [default: 0.0.0.0:5250].
+    #[arg(long)]
+    bind: Option<String>,
+
+    /// Transport clients connect over [default: tcp].
+    #[arg(long, value_enum)]
+    transport: Option<TransportKind>,
+
+    /// Simulate a bad network on everything the server sends, e.g.
+    /// `latency=75ms,jitter=10ms,loss=0.02,reorder=0.01,bandwidth=64000`.
+    #[arg(long)]
+    link: Option<LinkConditions>,
+
+    /// How many pushed events a slow client may fall behind before it misses some
+    /// [default: 100].
+    #[arg(long)]
+    event_capacity: Option<usize>,
+
+    /// Seconds a new client gets to finish the handshake before it is dropped [default: 10].
+    #[arg(long)]
+    handshake_timeout: Option<u64>,
+
+    /// Seconds clients get to close their connections on Ctrl-C or SIGTERM before the server
+    /// exits anyway [default: 5].
+    #[arg(long)]
+    shutdown_timeout: Option<u64>,
+
+    /// Seed of the terrain noise of a newly generated world [default: 0].
+    #[arg(long)]
+    seed: Option<u32>,
+
+    /// Simulation ticks per second. Each tick moves every player at most one step and
+    /// replicates the result to clients [default: 30].
+    #[arg(long)]
+    tick_rate: Option<u32>,
+
+    /// Tiles a new player moves per step [default: 0.025].
+    #[arg(long)]
+    player_speed: Option<f32>,
+
+    /// Seconds a disconnected player is kept so its client can reconnect to it
+    /// [default: 60].
+    #[arg(long)]
+    reconnect_grace: Option<u64>,
+
+    /// How far nearby chat carries, in tiles [default: 10].
+    #[arg(long)]
+    chat_radius: Option<f32>,
+
+    /// File keeping player accounts across restarts. Without it, accounts are forgotten when
+    /// the server exits.
+    #[arg(long)]
+    sessions_file: Option<PathBuf>,
+
+    /// Directory the world is saved to, and loaded from on startup when it holds a save.
+    /// Without it, every start generates a new world.
+    #[arg(long)]
+    save_dir: Option<PathBuf>,
+
+    /// Seconds between saves while the server runs. The world is also saved on shutdown
+    /// [default: 60].
+    #[arg(long)]
+    save_interval: Option<u64>,
+
+    /// Chat messages and commands per second each client may keep sending [default: 1].
+    #[arg(long)]
+    message_rate: Option<f32>,
+
+    /// How many messages a client that has been quiet may send at once [default: 5].
+    #[arg(long)]
+    message_burst: Option<u32>,
+
+    /// Id of a player who may run admin commands. Repeat it for several; any given replace
+    /// the config file's list.
+    #[arg(long = "admin", value_name = "PLAYER_ID")]
+    admins: Vec<String>,
 }
 
-// async fn handle_broadcast_message()
-
-async fn handle_connection(
-    stream: TcpStream,
-    addr: SocketAddr,
-    tx: broadcast::Sender<ServerMessage>,
-    mut rx: broadcast::Receiver<ServerMessage>,
-) {
-    let (mut reader, mut writer) = stream.into_split();
-
-    let id = Uuid::new_v4().to_string();
-
-    let (incoming_tx, mut incoming_rx) = unbounded_channel::<ServerMessage>();
-
-    tokio::spawn({
-        let id = id.clone();
-        async move {
-            loop {
-                match read_message::<ClientMessage>(&mut reader).await {
-                    Ok(msg) => {
-                        handle_client_message(msg, &tx, &incoming_tx, &addr, id.clone()).await
-                    }
-                    Err(e) => {
-                        println!("Client {addr} disconnected: {e}");
-                        break;
-                    }
-                }
-            }
-        }
-    });
-
-    tokio::spawn({
-        let id = id.clone();
-        async move {
-            loop {
-                tokio::select! {
-                    incoming_msg = incoming_rx.recv() => {
-                                if let Some(msg) = incoming_msg {
-                                    if let Err(e) = send_message(&mut writer, &msg).await {
-                                        println!("Error sending message to client {addr}: {e}");
-                                    }
-                                };
-                            }
-
-                    msg = rx.recv() => {
-                        match msg {
-                            Ok(msg) => {
-                                let broadcast: bool = match msg.clone() {
-                                    ServerMessage::OtherPlayer(p) => {
-                                        p.id != id.clone()
-                                    }
-                                    _ => true
-                                };
-
-                                if broadcast && let Err(e) = send_message(&mut writer, &msg).await {
-                                    println!("Error broadcasting message to client {addr}: {e}");
-                                }
-                            },
-                            Err(RecvError::Closed) => {
-                                println!("Broadcast channel closed for {addr}");
-                                break;
-                            },
-                            Err(e) => {
-                                println!("Error receiving from broadcast channel: {e}");
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    });
+impl Args {
+    /// Applies every option that was given on top of `config`.
+    fn apply(self, config: &mut ServerConfig) {
+        let seconds = |secs: Option<u64>| secs.map(Duration::from_secs);
+
+        config.set(|c| &mut c.bind, self.bind);
+        config.set(|c| &mut c.transport, self.transport);
+        config.set(|c| &mut c.link, self.link);
+        config.set(|c| &mut c.event_capacity, self.event_capacity);
+        config.set(
+            |c| &mut c.handshake_timeout,
+            seconds(self.handshake_timeout),
+        );
+        config.set(|c| &mut c.shutdown_timeout, seconds(self.shutdown_timeout));
+        config.set(|c| &mut c.world.seed, self.seed);
+        config.set(|c| &mut c.tick_rate, self.tick_rate);
+        config.set(|c| &mut c.player_speed, self.player_speed);
+        config.set(|c| &mut c.reconnect_grace, seconds(self.reconnect_grace));
+        config.set(|c| &mut c.chat_radius, self.chat_radius);
+        config.set(|c| &mut c.sessions_file, self.sessions_file.map(Some));
+        config.set(|c| &mut c.save_dir, self.save_dir.map(Some));
+        config.set(|c| &mut c.save_interval, seconds(self.save_interval));
+        config.set(|c| &mut c.message_rate, self.message_rate);
+        config.set(|c| &mut c.message_burst, self.message_burst);
+        config.set(
+            |c| &mut c.admins,
+            (!self.admins.is_empty()).then_some(self.admins),
+        );
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let listener = TcpListener::bind("0.0.0.0:5250").await?;
-
-    let (tx, _rx) = broadcast::channel::<ServerMessage>(100);
+    let mut args = Args::parse();
+    let mut config = match args.config.take() {
+        Some(path) => ServerConfig::load(&path)?,
+        None => ServerConfig::default(),
+    };
+    args.apply(&mut config);
+    config.validate()?;
+
+    if !config.link.is_perfect() {
+        println!("Simulating link conditions: {}", config.link);
+    }
 
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        println!("Client connected: {addr}");
+    let world = World::generate(&config.world, config.player_speed);
+    let server = Server::bind(config.clone(), world).await?;
+    let scheme = match config.transport {
+        TransportKind::Tcp => "tcp",
+        TransportKind::Udp => "udp",
+    };
+    println!("Listening on {scheme}://{}", server.local_addr()?);
+
+    server.spawn_console();
+    server.run_until(shutdown_signal()).await?;
+    Ok(())
+}
 
-        let tx = tx.clone();
-        let rx = tx.subscribe();
+/// Resolves on Ctrl-C, or SIGTERM on Unix, with the reason to give clients.
+async fn shutdown_signal() -> String {
+    #[cfg(unix)]
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Cannot listen for SIGTERM: {e}");
+                future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
 
-        tokio::spawn(async move { handle_connection(stream, addr, tx, rx).await });
+    tokio::select! {
+        _ = ctrl_c() => {}
+        _ = terminate => {}
     }
+    "the server is shutting down".to_string()
 }
@@ -0,0 +1,218 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{Arc, MutexGuard},
+    time::Instant,
+};
+
+use shared::{ErrorCode, PlayerMessage, RequestError};
+
+use crate::ServerState;
+
+/// Messages per second a connection may keep sending, when no rate is configured.
+pub const DEFAULT_MESSAGE_RATE: f32 = 1.0;
+
+/// Messages a quiet connection may send at once, when no burst is configured.
+pub const DEFAULT_MESSAGE_BURST: u32 = 5;
+
+/// A token bucket: `burst` messages at once, refilling at `rate` messages per second.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    rate: f32,
+    burst: f32,
+    allowance: f32,
+    last: Instant,
+}
+
+impl RateLimit {
+    /// Starts full, so a new connection may send `burst` messages straight away.
+    pub fn new(rate: f32, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: burst as f32,
+            allowance: burst as f32,
+            last: Instant::now(),
+        }
+    }
+
+    /// Uses up one message, or returns false if none is left right now.
+    pub fn allow(&mut self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    /// Like `allow`, as if it were `now`.
+    pub fn allow_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f32();
+        self.last = self.last.max(now);
+        self.allowance = (self.allowance + elapsed * self.rate).min(self.burst);
+        if self.allowance < 1.0 {
+            return false;
+        }
+        self.allowance -= 1.0;
+        true
+    }
+}
+
+/// Looks at chat before it is relayed. `ServerState::add_chat_filter` adds one.
+pub trait ChatFilter: Send + Sync {
+    /// The text to relay instead of `message` from the player `sender`, or why it must not
+    /// be relayed at all.
+    fn filter(&self, sender: &str, message: &str) -> Result<String, String>;
+}
+
+/// Masks blocked words with `*`, ignoring case. Only whole words are masked, so blocking
+/// "ass" leaves "class" alone.
+pub struct WordFilter {
+    words: HashSet<String>,
+}
+
+impl WordFilter {
+    pub fn new<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(|word| word.as_ref().to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl ChatFilter for WordFilter {
+    fn filter(&self, _sender: &str, message: &str) -> Result<String, String> {
+        let mut filtered = String::with_capacity(message.len());
+        let mut rest = message;
+        while !rest.is_empty() {
+            // Alternate between runs of word characters and runs of everything else.
+            let in_word = rest.starts_with(char::is_alphanumeric);
+            let end = rest
+                .find(|c: char| c.is_alphanumeric() != in_word)
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(end);
+            if self.words.contains(&run.to_lowercase()) {
+                filtered.extend(run.chars().map(|_| '*'));
+            } else {
+                filtered.push_str(run);
+            }
+            rest = tail;
+        }
+        Ok(filtered)
+    }
+}
+
+/// Players who may not chat, and players who may not join. A ban also covers the address the
+/// player was last seen at, so joining again as a new player without the session token does
+/// not get around it. Addresses are only known once the player has connected since the server
+/// started, and address bans end with `unban` or a restart.
+#[derive(Debug, Default)]
+pub struct Moderation {
+    muted: HashSet<String>,
+    banned: HashSet<String>,
+    /// Where each player connected from last.
+    addresses: HashMap<String, IpAddr>,
+    /// Addresses that may not join, with the banned player each was taken from.
+    banned_addresses: HashMap<IpAddr, String>,
+}
+
+impl Moderation {
+    pub fn new(muted: &[String], banned: &[String]) -> Self {
+        Self {
+            muted: muted.iter().cloned().collect(),
+            banned: banned.iter().cloned().collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_muted(&self, id: &str) -> bool {
+        self.muted.contains(id)
+    }
+
+    /// Returns false if `id` was already muted.
+    pub fn mute(&mut self, id: &str) -> bool {
+        self.muted.insert(id.to_string())
+    }
+
+    /// Returns false if `id` was not muted.
+    pub fn unmute(&mut self, id: &str) -> bool {
+        self.muted.remove(id)
+    }
+
+    pub fn is_banned(&self, id: &str) -> bool {
+        self.banned.contains(id)
+    }
+
+    /// Notes that the player `id` connects from `addr`. If `id` is banned, `addr` is banned
+    /// along with it.
+    pub fn seen(&mut self, id: &str, addr: IpAddr) {
+        self.addresses.insert(id.to_string(), addr);
+        if self.banned.contains(id) {
+            self.banned_addresses.insert(addr, id.to_string());
+        }
+    }
+
+    /// The banned player whose address `addr` is, if any.
+    pub fn banned_address(&self, addr: IpAddr) -> Option<&str> {
+        self.banned_addresses.get(&addr).map(String::as_str)
+    }
+
+    /// Bans `id` and the address it was last seen at. Returns false if `id` was already
+    /// banned.
+    pub fn ban(&mut self, id: &str) -> bool {
+        if let Some(&addr) = self.addresses.get(id) {
+            self.banned_addresses.insert(addr, id.to_string());
+        }
+        self.banned.insert(id.to_string())
+    }
+
+    /// Lifts the ban on `id` and on the addresses banned with it. Returns false if `id` was
+    /// not banned.
+    pub fn unban(&mut self, id: &str) -> bool {
+        self.banned_addresses.retain(|_, banned| banned != id);
+        self.banned.remove(id)
+    }
+}
+
+pub(crate) fn lock_moderation(
+    state: &ServerState,
+) -> Result<MutexGuard<'_, Moderation>, RequestError> {
+    state
+        .moderation
+        .lock()
+        .map_err(|_| RequestError::new(ErrorCode::Internal, "the mute list is unavailable"))
+}
+
+impl ServerState {
+    /// Runs `filter` on every chat message, after the filters added before it.
+    pub fn add_chat_filter(&self, filter: impl ChatFilter + 'static) {
+        self.filters
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(Arc::new(filter));
+    }
+
+    /// What of `message` from `sender` may be relayed: nothing if `sender` is muted or a
+    /// filter refuses it, and otherwise the text the filters leave.
+    pub(crate) fn moderate(
+        &self,
+        sender: &str,
+        mut message: PlayerMessage,
+    ) -> Result<PlayerMessage, RequestError> {
+        if lock_moderation(self)?.is_muted(sender) {
+            return Err(RequestError::new(
+                ErrorCode::Muted,
+                "an admin muted you, so nobody sees your messages",
+            ));
+        }
+
+        let filters = self
+            .filters
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        for filter in filters {
+            message.message = filter
+                .filter(sender, &message.message)
+                .map_err(|why| RequestError::new(ErrorCode::Blocked, why))?;
+        }
+        Ok(message)
+    }
+}
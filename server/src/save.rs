@@ -0,0 +1,106 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use shared::{Player, TileManager, WorldGen};
+
+/// Version of the save file format. Bump it whenever `WorldSave` or anything inside it
+/// changes shape; older saves are then refused rather than mis-read.
+pub const SAVE_VERSION: u32 = 4;
+
+/// How often the world is saved while the server runs, when no interval is given on the
+/// command line.
+pub const DEFAULT_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Leading bytes of every save file.
+const SAVE_MAGIC: [u8; 4] = *b"ISWS";
+
+const SAVE_FILE: &str = "world.sav";
+
+/// Everything about a world worth keeping across restarts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorldSave {
+    /// The settings the terrain was generated with.
+    pub settings: WorldGen,
+    /// The chunks that changed since they were generated. The rest are generated again.
+    pub map: TileManager,
+    /// Every player the world remembers, whether or not it was online when saved.
+    pub players: BTreeMap<String, Player>,
+}
+
+/// A directory holding the save of one world.
+///
+/// The file starts with `SAVE_MAGIC` and `SAVE_VERSION` (little-endian), followed by the
+/// `bincode::config::standard()` encoding of a `WorldSave`.
+#[derive(Clone, Debug)]
+pub struct SaveDir {
+    path: PathBuf,
+}
+
+impl SaveDir {
+    /// Uses `path`, creating it if it does not exist yet.
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the save, or returns `None` if nothing was saved here yet.
+    pub fn load(&self) -> io::Result<Option<WorldSave>> {
+        let file = self.path.join(SAVE_FILE);
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {reason}", file.display()),
+            )
+        };
+
+        let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+            return Err(invalid("not a world save".into()));
+        };
+        if *magic != SAVE_MAGIC {
+            return Err(invalid("not a world save".into()));
+        }
+        let Some((version, payload)) = rest.split_first_chunk::<4>() else {
+            return Err(invalid("truncated header".into()));
+        };
+        let version = u32::from_le_bytes(*version);
+        if version != SAVE_VERSION {
+            return Err(invalid(format!(
+                "saved with format v{version} but this server reads v{SAVE_VERSION}"
+            )));
+        }
+
+        let (save, _) = bincode::serde::decode_from_slice(payload, bincode::config::standard())
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(Some(save))
+    }
+
+    /// Writes `save`, replacing the previous one. The old save stays intact until the new one
+    /// is complete, so a crash mid-write loses at most the latest changes.
+    pub fn store(&self, save: &WorldSave) -> io::Result<()> {
+        let payload = bincode::serde::encode_to_vec(save, bincode::config::standard())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut bytes = Vec::with_capacity(8 + payload.len());
+        bytes.extend_from_slice(&SAVE_MAGIC);
+        bytes.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let file = self.path.join(SAVE_FILE);
+        let partial = file.with_extension("sav.tmp");
+        fs::write(&partial, bytes)?;
+        fs::rename(partial, file)
+    }
+}
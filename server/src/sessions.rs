@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use shared::{ErrorCode, RequestError, SessionToken};
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+/// Which player every issued session token belongs to, and which connection each online
+/// player is on.
+///
+/// With a file, accounts survive restarts: each new one is appended as a `token player-id`
+/// line.
+pub struct Sessions {
+    path: Option<PathBuf>,
+    accounts: HashMap<SessionToken, String>,
+    online: HashMap<String, Online>,
+}
+
+/// The connection a player is on, and how to close it: sending an error makes the
+/// connection pass it on to its client and disconnect.
+struct Online {
+    connection: String,
+    kick: UnboundedSender<RequestError>,
+}
+
+impl Sessions {
+    /// Accounts that only live as long as the server.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            accounts: HashMap::new(),
+            online: HashMap::new(),
+        }
+    }
+
+    /// Loads the accounts in `path`, which need not exist yet.
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut accounts = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let Some((token, id)) = line.split_once(' ') else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: expected `token player-id`",
+                        path.display(),
+                        number + 1
+                    ),
+                ));
+            };
+            accounts.insert(SessionToken(token.to_string()), id.to_string());
+        }
+
+        Ok(Self {
+            path: Some(path),
+            accounts,
+            online: HashMap::new(),
+        })
+    }
+
+    /// The id of the player owning `token`, if this server issued it.
+    pub fn account(&self, token: &SessionToken) -> Option<&str> {
+        self.accounts.get(token).map(String::as_str)
+    }
+
+    /// Puts the player owning `token` on `connection` and returns its id with the token to
+    /// keep using. Without a token, or with one this server never issued, a new account is
+    /// created with the connection's id as the player id.
+    ///
+    /// If the player was on another connection, that one is kicked with `TakenOver` and loses
+    /// the player. `kick` is how this connection gets kicked in turn.
+    pub fn claim(
+        &mut self,
+        token: Option<&SessionToken>,
+        connection: &str,
+        kick: UnboundedSender<RequestError>,
+    ) -> Result<(String, SessionToken), RequestError> {
+        let known =
+            token.and_then(|token| Some((token.clone(), self.accounts.get(token)?.clone())));
+        let (token, id) = match known {
+            Some(account) => account,
+            None => {
+                let token = SessionToken(Uuid::new_v4().simple().to_string());
+                self.create(token.clone(), connection.to_string())
+                    .map_err(|e| {
+                        RequestError::new(
+                            ErrorCode::Internal,
+                            format!("could not save the new account: {e}"),
+                        )
+                    })?;
+                (token, connection.to_string())
+            }
+        };
+
+        let online = Online {
+            connection: connection.to_string(),
+            kick,
+        };
+        if let Some(previous) = self.online.insert(id.clone(), online) {
+            let _ = previous.kick.send(RequestError::new(
+                ErrorCode::TakenOver,
+                "your player was resumed from another connection",
+            ));
+        }
+        Ok((id, token))
+    }
+
+    /// Disconnects the player `id`, telling its client `why`. Returns false if it is not
+    /// online.
+    pub fn kick(&mut self, id: &str, why: RequestError) -> bool {
+        self.online
+            .get(id)
+            .is_some_and(|online| online.kick.send(why).is_ok())
+    }
+
+    /// Marks the player `id` as offline when `connection` goes away. Returns false if another
+    /// connection has taken the player over since, in which case nothing changes.
+    pub fn release(&mut self, id: &str, connection: &str) -> bool {
+        if self
+            .online
+            .get(id)
+            .is_some_and(|online| online.connection == connection)
+        {
+            self.online.remove(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn create(&mut self, token: SessionToken, id: String) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{} {id}", token.0)?;
+        }
+        self.accounts.insert(token, id);
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use shared::{
+    Chunk, ChunkCoord, ErrorCode, MAX_VIEW_RADIUS, Player, RequestError, SnapshotState, WorldGen,
+    chunk_at, chunk_within, chunks_around,
+};
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        oneshot, watch,
+    },
+    time::{MissedTickBehavior, interval},
+};
+
+use crate::{save::WorldSave, state::World};
+
+/// Simulation rate used when none is given on the command line.
+pub const DEFAULT_TICK_RATE: u32 = 30;
+
+/// How long a departed player is kept for its client to reconnect, when none is given on the
+/// command line.
+pub const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(60);
+
+/// How many chunks around each player are generated ahead of it, and sent to a player when
+/// it joins. Clients ask for anything further away themselves.
+pub const NEARBY_CHUNK_RADIUS: i64 = 1;
+
+/// How many chunks from its player a client may ask for, along either axis: as far as any
+/// client views, and a little further for the player moving while the request is on its way.
+/// Chunks further than this from every player are dropped from memory.
+pub const MAP_REACH: i64 = MAX_VIEW_RADIUS + 2;
+
+/// How often chunks out of every player's reach are dropped from memory.
+const FORGET_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What a player joining the world needs to start playing.
+pub struct Joined {
+    pub player: Player,
+    /// The chunks within `NEARBY_CHUNK_RADIUS` of the player, nearest first.
+    pub map: Vec<Chunk>,
+    /// Everyone else in the world.
+    pub others: Vec<Player>,
+}
+
+enum Command {
+    Join {
+        id: String,
+        name: String,
+        reply: oneshot::Sender<Joined>,
+    },
+    Map {
+        id: String,
+        coords: Vec<ChunkCoord>,
+        reply: oneshot::Sender<Result<Vec<Chunk>, RequestError>>,
+    },
+    Move {
+        id: String,
+        direction: [f32; 3],
+    },
+    Leave {
+        id: String,
+    },
+    Save {
+        reply: oneshot::Sender<WorldSave>,
+    },
+    Teleport {
+        id: String,
+        column: [i64; 2],
+        reply: oneshot::Sender<Result<[f32; 3], RequestError>>,
+    },
+    Settings {
+        reply: oneshot::Sender<WorldGen>,
+    },
+}
+
+/// Handle to the simulation task, which owns the `World`. Connections queue commands through
+/// it and watch the snapshots it publishes after every tick.
+#[derive(Clone)]
+pub struct Simulation {
+    commands: UnboundedSender<Command>,
+    snapshots: watch::Receiver<Arc<SnapshotState>>,
+}
+
+impl Simulation {
+    /// Starts simulating `world` at `tick_rate` ticks per second. Players who leave can
+    /// resume where they were for `reconnect_grace`.
+    pub fn spawn(world: World, tick_rate: u32, reconnect_grace: Duration) -> Self {
+        let (commands, commands_rx) = unbounded_channel();
+        let (snapshots_tx, snapshots) = watch::channel(Arc::new(world.snapshot(0)));
+        let period = Duration::from_secs(1) / tick_rate.max(1);
+
+        tokio::spawn(run(
+            world,
+            commands_rx,
+            snapshots_tx,
+            period,
+            reconnect_grace,
+        ));
+
+        Self {
+            commands,
+            snapshots,
+        }
+    }
+
+    /// Adds the player for `id` to the world, or finds it if it already joined, under the
+    /// display name `name`.
+    pub async fn join(&self, id: &str, name: &str) -> Result<Joined, RequestError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Command::Join {
+            id: id.to_string(),
+            name: name.to_string(),
+            reply,
+        })?;
+        rx.await.map_err(|_| stopped())
+    }
+
+    /// The chunks at `coords` for the player `id`, leaving out any beyond the edge of the
+    /// world. Fails if any is further than `MAP_REACH` from the player.
+    pub async fn map(&self, id: &str, coords: Vec<ChunkCoord>) -> Result<Vec<Chunk>, RequestError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Command::Map {
+            id: id.to_string(),
+            coords,
+            reply,
+        })?;
+        rx.await.map_err(|_| stopped())?
+    }
+
+    /// Queues a move for the player `id`, applied on the next tick.
+    pub fn queue_move(&self, id: &str, direction: [f32; 3]) -> Result<(), RequestError> {
+        self.send(Command::Move {
+            id: id.to_string(),
+            direction,
+        })
+    }
+
+    /// Removes the player `id` from the world. Snapshots from the next tick on leave it out,
+    /// but it can still be resumed for the reconnect grace period.
+    pub fn leave(&self, id: &str) -> Result<(), RequestError> {
+        self.send(Command::Leave { id: id.to_string() })
+    }
+
+    /// A copy of the world as it is now, to be written to disk.
+    pub async fn save(&self) -> Result<WorldSave, RequestError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Command::Save { reply })?;
+        rx.await.map_err(|_| stopped())
+    }
+
+    /// Puts the player `id` on top of the tile column `column`, and returns where it ended
+    /// up.
+    pub async fn teleport(&self, id: &str, column: [i64; 2]) -> Result<[f32; 3], RequestError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Command::Teleport {
+            id: id.to_string(),
+            column,
+            reply,
+        })?;
+        rx.await.map_err(|_| stopped())?
+    }
+
+    /// The settings the world's terrain was generated with.
+    pub async fn settings(&self) -> Result<WorldGen, RequestError> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Command::Settings { reply })?;
+        rx.await.map_err(|_| stopped())
+    }
+
+    /// World state after every tick.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<SnapshotState>> {
+        self.snapshots.clone()
+    }
+
+    fn send(&self, command: Command) -> Result<(), RequestError> {
+        self.commands.send(command).map_err(|_| stopped())
+    }
+}
+
+fn stopped() -> RequestError {
+    RequestError::new(ErrorCode::Internal, "the simulation has stopped")
+}
+
+/// Answers commands as they arrive, and every `period` applies the queued moves and publishes
+/// the result.
+async fn run(
+    mut world: World,
+    mut commands: UnboundedReceiver<Command>,
+    snapshots: watch::Sender<Arc<SnapshotState>>,
+    period: Duration,
+    reconnect_grace: Duration,
+) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut forgetting = interval(FORGET_INTERVAL);
+    forgetting.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // Every move queued since the last tick, merged per player so that sending more of them
+    // never moves a player further than one step.
+    let mut inputs: HashMap<String, [f32; 3]> = HashMap::new();
+    let mut tick = 0;
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    break;
+                };
+                match command {
+                    Command::Join { id, name, reply } => {
+                        let player = world.join(&id, &name);
+                        let others = world
+                            .players
+                            .values()
+                            .filter(|other| other.id != id)
+                            .cloned()
+                            .collect();
+                        let nearby = chunks_around(chunk_at(player.position), NEARBY_CHUNK_RADIUS);
+                        let _ = reply.send(Joined {
+                            player,
+                            map: world.chunks(&nearby),
+                            others,
+                        });
+                    }
+                    Command::Map { id, coords, reply } => {
+                        let result = match world.players.get(&id) {
+                            Some(player) => {
+                                let center = chunk_at(player.position);
+                                let far = coords
+                                    .iter()
+                                    .find(|&&coord| !chunk_within(center, MAP_REACH, coord));
+                                match far {
+                                    Some([x, y]) => Err(RequestError::new(
+                                        ErrorCode::InvalidChunks,
+                                        format!("chunk {x}, {y} is too far from your player"),
+                                    )),
+                                    None => Ok(world.chunks(&coords)),
+                                }
+                            }
+                            None => Err(RequestError::new(
+                                ErrorCode::UnknownPlayer,
+                                format!("player {id} is not online"),
+                            )),
+                        };
+                        let _ = reply.send(result);
+                    }
+                    Command::Move { id, direction } => {
+                        let input = inputs.entry(id).or_default();
+                        for (axis, delta) in input.iter_mut().zip(direction) {
+                            // NaN would get through the clamp and into the player's position.
+                            if delta.is_finite() {
+                                *axis = (*axis + delta).clamp(-1.0, 1.0);
+                            }
+                        }
+                    }
+                    Command::Leave { id } => {
+                        inputs.remove(&id);
+                        world.leave(&id);
+                    }
+                    Command::Save { reply } => {
+                        let _ = reply.send(world.save());
+                    }
+                    Command::Teleport { id, column, reply } => {
+                        let [x, y] = column;
+                        let result = match world.ground(column) {
+                            Some(position) if world.teleport(&id, position) => Ok(position),
+                            Some(_) => Err(RequestError::new(
+                                ErrorCode::UnknownPlayer,
+                                format!("player {id} is not online"),
+                            )),
+                            None => Err(RequestError::new(
+                                ErrorCode::InvalidArguments,
+                                format!("{x}, {y} is beyond the edge of the world"),
+                            )),
+                        };
+                        let _ = reply.send(result);
+                    }
+                    Command::Settings { reply } => {
+                        let _ = reply.send(*world.settings());
+                    }
+                }
+            }
+
+            _ = ticker.tick() => {
+                tick += 1;
+                for (id, direction) in inputs.drain() {
+                    world.step(&id, direction);
+                }
+                world.generate_near_players(NEARBY_CHUNK_RADIUS);
+                world.expire_parked(reconnect_grace);
+                snapshots.send_replace(Arc::new(world.snapshot(tick)));
+            }
+
+            _ = forgetting.tick() => world.forget_far_chunks(MAP_REACH),
+        }
+    }
+}
@@ -1,8 +1,245 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
 
-use shared::{Player, TileManager};
-use tokio::sync::RwLock;
+use shared::{
+    Chunk, ChunkCoord, Player, SnapshotState, TileManager, WorldGen, chunk_at, chunk_in_bounds,
+    chunk_of, chunk_within, chunks_around,
+};
 
-pub static PLAYERS: LazyLock<RwLock<HashMap<String, Player>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+use crate::save::WorldSave;
 
-pub static TILE_MANAGER: LazyLock<RwLock<TileManager>> = LazyLock::new(|| RwLock::new(TileManager::new([0,0])));
\ No newline at end of file
+/// How far a player moves per step, in tiles, when no speed is configured.
+pub const DEFAULT_PLAYER_SPEED: f32 = 0.025;
+
+/// Everything the simulation owns: the map and every connected player.
+pub struct World {
+    pub players: BTreeMap<String, Player>,
+    /// The chunks generated near players so far, and every chunk changed since it was
+    /// generated.
+    pub map: TileManager,
+    /// The settings `map` is generated with.
+    settings: WorldGen,
+    /// Players who left recently, with when they left, kept so they can resume where they
+    /// were.
+    parked: HashMap<String, (Player, Instant)>,
+    /// Players loaded from a save who have not come back since. Unlike parked players they
+    /// are kept until they do.
+    offline: HashMap<String, Player>,
+    /// Speed of players joining for the first time.
+    player_speed: f32,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    /// A world generated with the default settings.
+    pub fn new() -> Self {
+        Self::generate(&WorldGen::default(), DEFAULT_PLAYER_SPEED)
+    }
+
+    /// A new world with terrain from `settings`, where new players move `player_speed` tiles
+    /// per step. Chunks are generated as they are needed.
+    pub fn generate(settings: &WorldGen, player_speed: f32) -> Self {
+        Self {
+            players: BTreeMap::new(),
+            map: TileManager::new(),
+            settings: *settings,
+            parked: HashMap::new(),
+            offline: HashMap::new(),
+            player_speed,
+        }
+    }
+
+    /// Picks a saved world back up. Every saved player is offline until its client joins and
+    /// keeps its saved speed; new players get `player_speed`.
+    pub fn restore(save: WorldSave, player_speed: f32) -> Self {
+        Self {
+            players: BTreeMap::new(),
+            map: save.map,
+            settings: save.settings,
+            parked: HashMap::new(),
+            offline: save.players.into_iter().collect(),
+            player_speed,
+        }
+    }
+
+    /// Everything needed to `restore` this world later, including players who are away.
+    /// Chunks just as the settings generate them are left out, since they come back the same.
+    pub fn save(&self) -> WorldSave {
+        let away = self
+            .parked
+            .values()
+            .map(|(player, _)| player)
+            .chain(self.offline.values());
+        let players = away
+            .chain(self.players.values())
+            .map(|player| (player.id.clone(), player.clone()))
+            .collect();
+        let mut map = TileManager::new();
+        for chunk in self.map.chunks.values() {
+            if !self.is_generated(chunk) {
+                map.insert(chunk.clone());
+            }
+        }
+        WorldSave {
+            settings: self.settings,
+            map,
+            players,
+        }
+    }
+
+    /// Returns the player for `id`: the one already in the world, the one parked when it last
+    /// left or saved before a restart, or a new one at the origin column. Either way it goes
+    /// by `name` from now on.
+    pub fn join(&mut self, id: &str, name: &str) -> Player {
+        if let Some((player, _)) = self.parked.remove(id) {
+            self.players.insert(id.to_string(), player);
+        } else if let Some(player) = self.offline.remove(id) {
+            self.players.insert(id.to_string(), player);
+        }
+
+        let pos = self.ground([0, 0]).unwrap_or([0.0, 0.0, 0.0]);
+        let player = self.players.entry(id.to_string()).or_insert(Player {
+            id: id.to_string(),
+            name: name.to_string(),
+            position: pos,
+            speed: self.player_speed,
+        });
+        player.name = name.to_string();
+        player.clone()
+    }
+
+    pub fn settings(&self) -> &WorldGen {
+        &self.settings
+    }
+
+    /// Where a player standing on the tile column `column` would be, or `None` beyond the
+    /// edge of the world.
+    pub fn ground(&mut self, column: [i64; 2]) -> Option<[f32; 3]> {
+        let [x, y] = column;
+        let z = self.map.ground(column, &self.settings)?;
+        Some([x as f32, y as f32, z as f32])
+    }
+
+    /// The chunks at `coords` that are inside the world, generating any not needed before.
+    pub fn chunks(&mut self, coords: &[ChunkCoord]) -> Vec<Chunk> {
+        coords
+            .iter()
+            .filter_map(|&coord| self.map.chunk(coord, &self.settings).cloned())
+            .collect()
+    }
+
+    /// Generates the chunks within `radius` chunks of every player, so they are ready before
+    /// anyone walks into them or asks for them.
+    pub fn generate_near_players(&mut self, radius: i64) {
+        let centers: Vec<ChunkCoord> = self
+            .players
+            .values()
+            .map(|player| chunk_at(player.position))
+            .collect();
+        for center in centers {
+            for coord in chunks_around(center, radius) {
+                self.map.chunk(coord, &self.settings);
+            }
+        }
+    }
+
+    /// Drops the chunks further than `reach` chunks from every player online, along either
+    /// axis, unless they changed since they were generated. They are generated again if
+    /// needed.
+    pub fn forget_far_chunks(&mut self, reach: i64) {
+        let centers: Vec<ChunkCoord> = self
+            .players
+            .values()
+            .map(|player| chunk_at(player.position))
+            .collect();
+        let far: Vec<ChunkCoord> = self
+            .map
+            .chunks
+            .values()
+            .filter(|chunk| {
+                let near = centers
+                    .iter()
+                    .any(|&center| chunk_within(center, reach, chunk.coord));
+                !near && self.is_generated(chunk)
+            })
+            .map(|chunk| chunk.coord)
+            .collect();
+        for coord in far {
+            self.map.chunks.remove(&coord);
+        }
+    }
+
+    /// Whether `chunk` is just as the settings generate it.
+    fn is_generated(&self, chunk: &Chunk) -> bool {
+        *chunk == Chunk::generate(chunk.coord, &self.settings)
+    }
+
+    /// Puts the online player `id` at `position`. Returns false if it is not online.
+    pub fn teleport(&mut self, id: &str, position: [f32; 3]) -> bool {
+        match self.players.get_mut(id) {
+            Some(player) => {
+                player.position = position;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes the player `id` out of the world, parking it in case it comes back.
+    pub fn leave(&mut self, id: &str) {
+        if let Some(player) = self.players.remove(id) {
+            self.parked.insert(id.to_string(), (player, Instant::now()));
+        }
+    }
+
+    /// Forgets players parked for longer than `grace`; they start over if they come back.
+    pub fn expire_parked(&mut self, grace: Duration) {
+        self.parked.retain(|_, (_, left)| left.elapsed() < grace);
+    }
+
+    /// Moves a player one step in `direction`, climbing or dropping a level when the tile
+    /// ahead is higher or lower.
+    pub fn step(&mut self, id: &str, direction: [f32; 3]) {
+        let Some(player) = self.players.get_mut(id) else {
+            return;
+        };
+
+        let new_x = player.position[0] + direction[0] * player.speed;
+        let new_y = player.position[1] + direction[1] * player.speed;
+        let current_z = player.position[2];
+
+        let tx = new_x.floor() as i64;
+        let ty = new_y.floor() as i64;
+        let tz = current_z.floor() as i64;
+
+        // The edge of the world is a wall.
+        if !chunk_in_bounds(chunk_of([tx, ty])) {
+            return;
+        }
+        let should_jump = self.map.has_tile([tx, ty, tz + 1], &self.settings);
+        let should_fall = self.map.has_tile([tx, ty, tz - 1], &self.settings);
+
+        if should_jump {
+            player.position[2] += 1.0;
+        } else if should_fall {
+            player.position[2] -= 1.0;
+        } else {
+            player.position[0] = new_x;
+            player.position[1] = new_y;
+        }
+    }
+
+    pub fn snapshot(&self, tick: u32) -> SnapshotState {
+        SnapshotState {
+            tick,
+            players: self.players.clone(),
+        }
+    }
+}
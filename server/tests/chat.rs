@@ -0,0 +1,156 @@
+mod common;
+
+use std::{collections::BTreeMap, iter};
+
+use common::{TcpClient, start_server};
+use server::{Audience, TransportKind, route};
+use shared::{
+    ChatScope, ClientMessage, ErrorCode, MAX_MESSAGE_LEN, Player, PlayerMessage, ServerMessage,
+    SnapshotState,
+};
+
+fn world(players: &[(&str, [f32; 3])]) -> SnapshotState {
+    SnapshotState {
+        tick: 1,
+        players: players
+            .iter()
+            .map(|&(id, position)| {
+                let player = Player {
+                    id: id.into(),
+                    name: id.into(),
+                    position,
+                    speed: 0.025,
+                };
+                (id.to_string(), player)
+            })
+            .collect::<BTreeMap<_, _>>(),
+    }
+}
+
+fn say(scope: ChatScope, message: &str) -> PlayerMessage {
+    PlayerMessage {
+        id: String::new(),
+        scope,
+        message: message.into(),
+    }
+}
+
+#[test]
+fn messages_are_relayed_as_coming_from_the_sender() {
+    let world = world(&[("alice", [0.0; 3])]);
+    let mut message = say(ChatScope::Global, "hi");
+    message.id = "mallory".into();
+
+    let event = route(message, "alice", &world, 10.0).unwrap();
+
+    assert_eq!(event.audience, Audience::Everyone);
+    let ServerMessage::Message(relayed) = event.message else {
+        panic!("expected a chat message");
+    };
+    assert_eq!(relayed.id, "alice");
+}
+
+#[test]
+fn nearby_messages_only_reach_players_in_range() {
+    let world = world(&[
+        ("alice", [0.0, 0.0, 0.0]),
+        ("bob", [3.0, 4.0, 2.0]),
+        ("carol", [6.0, 8.0, 0.0]),
+    ]);
+
+    let event = route(say(ChatScope::Nearby, "psst"), "alice", &world, 5.0).unwrap();
+
+    assert_eq!(
+        event.audience,
+        Audience::Players(vec!["alice".into(), "bob".into()])
+    );
+}
+
+#[test]
+fn whispers_only_reach_the_target_and_the_sender() {
+    let world = world(&[("alice", [0.0; 3]), ("bob", [0.0; 3]), ("carol", [0.0; 3])]);
+
+    let event = route(
+        say(ChatScope::Whisper("bob".into()), "psst"),
+        "alice",
+        &world,
+        10.0,
+    )
+    .unwrap();
+    assert_eq!(
+        event.audience,
+        Audience::Players(vec!["alice".into(), "bob".into()])
+    );
+    assert!(!event.reaches(Some("carol")));
+    assert!(!event.reaches(None));
+
+    let error = route(
+        say(ChatScope::Whisper("dave".into()), "psst"),
+        "alice",
+        &world,
+        10.0,
+    )
+    .unwrap_err();
+    assert_eq!(error.code, ErrorCode::UnknownPlayer);
+}
+
+#[test]
+fn unshowable_messages_are_refused() {
+    let world = world(&[("alice", [0.0; 3])]);
+    let too_long: String = iter::repeat_n('a', MAX_MESSAGE_LEN + 1).collect();
+
+    for message in ["", "  ", "bell\u{7}", &too_long] {
+        let error = route(say(ChatScope::Global, message), "alice", &world, 10.0).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidMessage, "{message:?}");
+    }
+}
+
+#[tokio::test]
+async fn chat_reaches_everyone_it_is_meant_for() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut alice = TcpClient::tcp(addr).await;
+    let mut bob = TcpClient::tcp(addr).await;
+    let mut carol = TcpClient::tcp(addr).await;
+    let (alice_player, _, _) = alice.join_as(None, "Alice").await;
+    let (bob_player, _, _) = bob.join_as(None, "Bob").await;
+    carol.join_as(None, "Carol").await;
+    // Whispers go to players in the latest tick, so wait until everyone is in one.
+    alice.snapshot_where(|state| state.players.len() == 3).await;
+
+    alice
+        .send(ClientMessage::MessageRequest(say(
+            ChatScope::Whisper(bob_player.id.clone()),
+            "just between us",
+        )))
+        .await;
+    alice
+        .send(ClientMessage::MessageRequest(say(
+            ChatScope::Global,
+            "hello all",
+        )))
+        .await;
+
+    for client in [&mut alice, &mut bob] {
+        let whisper = client.chat().await;
+        assert_eq!(whisper.id, alice_player.id);
+        assert_eq!(whisper.message, "just between us");
+    }
+    // Events arrive in the order they were sent, so the whisper would have come first.
+    for client in [&mut alice, &mut bob, &mut carol] {
+        assert_eq!(client.chat().await.message, "hello all");
+    }
+}
+
+#[tokio::test]
+async fn chatting_needs_a_player() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    let request = client
+        .send(ClientMessage::MessageRequest(say(ChatScope::Global, "hi")))
+        .await;
+    let ServerMessage::Error { code, .. } = client.reply_to(request).await else {
+        panic!("expected the message to be refused");
+    };
+    assert_eq!(code, ErrorCode::NotConnected);
+}
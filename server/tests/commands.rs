@@ -0,0 +1,223 @@
+mod common;
+
+use std::{env, fs, io, net::SocketAddr};
+
+use common::{TcpClient, WAIT, test_config};
+use server::{
+    Caller, CommandSpec, Permission, Server, ServerConfig, ServerState, TransportKind, World,
+};
+use shared::{
+    ChatScope, ClientMessage, ErrorCode, PlayerMessage, RequestError, ServerMessage, SessionToken,
+};
+use tokio::{task::JoinHandle, time::timeout};
+use uuid::Uuid;
+
+/// Starts a server and returns its address and state alongside the task running it.
+async fn start(config: ServerConfig) -> (SocketAddr, ServerState, JoinHandle<io::Result<()>>) {
+    let server = Server::bind(config, World::new()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let state = server.state().clone();
+    (addr, state, tokio::spawn(server.run()))
+}
+
+/// Sends `line` as chat and returns the reply.
+async fn command(client: &mut TcpClient, line: &str) -> Result<String, RequestError> {
+    let request = client
+        .send(ClientMessage::MessageRequest(PlayerMessage {
+            id: String::new(),
+            scope: ChatScope::Global,
+            message: line.into(),
+        }))
+        .await;
+    match client.reply_to(request).await {
+        ServerMessage::CommandOutput(output) => Ok(output),
+        ServerMessage::Error { code, message } => Err(RequestError { code, message }),
+        other => panic!("expected the command's output, got {other:?}"),
+    }
+}
+
+/// A server where the player joining with the returned token is an admin.
+async fn start_with_admin() -> (SocketAddr, ServerState, SessionToken) {
+    let sessions = env::temp_dir().join(format!("sessions-{}.txt", Uuid::new_v4()));
+    fs::write(&sessions, "admin-token admin\n").unwrap();
+    let (addr, state, _) = start(ServerConfig {
+        sessions_file: Some(sessions),
+        admins: vec!["admin".into()],
+        ..test_config(TransportKind::Tcp)
+    })
+    .await;
+    (addr, state, SessionToken("admin-token".into()))
+}
+
+#[tokio::test]
+async fn anyone_can_list_the_players_online() {
+    let (addr, _, _) = start(test_config(TransportKind::Tcp)).await;
+    let mut alice = TcpClient::tcp(addr).await;
+    let mut bob = TcpClient::tcp(addr).await;
+    let (alice_player, _, _) = alice.join_as(None, "Alice").await;
+    bob.join_as(None, "Bob").await;
+    alice.snapshot_where(|state| state.players.len() == 2).await;
+
+    let output = command(&mut alice, "/list").await.unwrap();
+
+    assert!(output.starts_with("2 players online"), "{output}");
+    assert!(
+        output.contains(&format!("Alice ({})", alice_player.id)),
+        "{output}"
+    );
+    assert!(output.contains("Bob ("), "{output}");
+}
+
+#[tokio::test]
+async fn the_seed_is_the_one_the_world_was_generated_from() {
+    let (addr, _, _) = start(test_config(TransportKind::Tcp)).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    assert_eq!(
+        command(&mut client, "/seed").await.unwrap(),
+        "World seed: 0"
+    );
+}
+
+#[tokio::test]
+async fn unknown_commands_are_refused() {
+    let (addr, _, _) = start(test_config(TransportKind::Tcp)).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    let error = command(&mut client, "/fly away").await.unwrap_err();
+    assert_eq!(error.code, ErrorCode::UnknownCommand);
+}
+
+#[tokio::test]
+async fn players_cannot_run_admin_commands() {
+    let (addr, _, _) = start(test_config(TransportKind::Tcp)).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    for line in ["/kick tester", "/tp 1 1", "/save", "/stop"] {
+        let error = command(&mut client, line).await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::NotPermitted, "{line}");
+    }
+    let help = command(&mut client, "/help").await.unwrap();
+    assert!(help.contains("/list"), "{help}");
+    assert!(!help.contains("/kick"), "{help}");
+}
+
+#[tokio::test]
+async fn admins_can_kick_players() {
+    let (addr, _, token) = start_with_admin().await;
+    let mut admin = TcpClient::tcp(addr).await;
+    let mut target = TcpClient::tcp(addr).await;
+    admin.join_with(Some(token)).await;
+    target.join_as(None, "Mallory").await;
+    admin.snapshot_where(|state| state.players.len() == 2).await;
+
+    let output = command(&mut admin, "/kick Mallory spamming").await.unwrap();
+    assert_eq!(output, "Kicked Mallory");
+
+    loop {
+        if let ServerMessage::Error { code, message } = target.recv().await.message {
+            assert_eq!(code, ErrorCode::Kicked);
+            assert_eq!(message, "spamming");
+            break;
+        }
+    }
+    assert!(target.closed().await);
+}
+
+#[tokio::test]
+async fn admins_can_teleport_themselves() {
+    let (addr, _, token) = start_with_admin().await;
+    let mut admin = TcpClient::tcp(addr).await;
+    let (player, _) = admin.join_with(Some(token)).await;
+
+    let output = command(&mut admin, "/tp 2 -1").await.unwrap();
+    assert!(
+        output.starts_with("Teleported admin to 2, -1, "),
+        "{output}"
+    );
+
+    let state = admin
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&player.id)
+                .is_some_and(|p| p.position[..2] == [2.0, -1.0])
+        })
+        .await;
+    assert!(state.players.contains_key(&player.id));
+
+    let error = command(&mut admin, "/tp 100000 0").await.unwrap_err();
+    assert_eq!(error.code, ErrorCode::InvalidArguments);
+    let error = command(&mut admin, "/tp north").await.unwrap_err();
+    assert_eq!(error.code, ErrorCode::InvalidArguments);
+}
+
+#[tokio::test]
+async fn the_console_can_run_admin_commands_but_has_no_player() {
+    let (addr, state, _) = start(test_config(TransportKind::Tcp)).await;
+    let mut client = TcpClient::tcp(addr).await;
+    let player = client.join().await;
+    client
+        .snapshot_where(|state| state.players.len() == 1)
+        .await;
+
+    let error = state
+        .run_command(Caller::Console, "tp 1 1")
+        .await
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::InvalidArguments);
+    let output = state
+        .run_command(Caller::Console, &format!("tp {} 1 1", player.id))
+        .await
+        .unwrap();
+    assert!(output.starts_with("Teleported"), "{output}");
+}
+
+#[tokio::test]
+async fn commands_can_be_registered_from_rust() {
+    let (addr, state, _) = start(test_config(TransportKind::Tcp)).await;
+    state.register_command(
+        CommandSpec {
+            name: "echo",
+            usage: "<words>",
+            help: "says the words back",
+            permission: Permission::Anyone,
+        },
+        |ctx| Box::pin(async move { Ok(format!("{}: {}", ctx.caller, ctx.args.join(" "))) }),
+    );
+    let mut client = TcpClient::tcp(addr).await;
+    let player = client.join().await;
+
+    assert_eq!(
+        command(&mut client, "/echo hello  there").await.unwrap(),
+        format!("player {}: hello there", player.id)
+    );
+    let help = command(&mut client, "/help").await.unwrap();
+    assert!(
+        help.contains("/echo <words> - says the words back"),
+        "{help}"
+    );
+}
+
+#[tokio::test]
+async fn stop_shuts_the_server_down() {
+    let (addr, state, running) = start(test_config(TransportKind::Tcp)).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    state
+        .run_command(Caller::Console, "/stop back soon")
+        .await
+        .unwrap();
+
+    loop {
+        if let ServerMessage::Shutdown(reason) = client.recv().await.message {
+            assert_eq!(reason, "back soon");
+            break;
+        }
+    }
+    timeout(WAIT, running).await.unwrap().unwrap().unwrap();
+}
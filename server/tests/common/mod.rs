@@ -0,0 +1,220 @@
+#![allow(dead_code)]
+
+use std::{io, net::SocketAddr, time::Duration};
+
+use server::{Server, ServerConfig, TransportKind, World};
+use shared::{
+    Capabilities, ClientEnvelope, ClientHello, ClientMessage, FrameReader, FrameWriter,
+    MessageReader, MessageWriter, Player, PlayerMessage, RequestId, ServerEnvelope, ServerMessage,
+    SessionToken, SnapshotReceiver, SnapshotState, UdpConfig, UdpConnection, UdpReader, UdpWriter,
+    client_handshake,
+};
+use tokio::{
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::oneshot,
+    task::JoinHandle,
+    time::timeout,
+};
+
+/// How long a test waits for any one message before failing.
+pub const WAIT: Duration = Duration::from_secs(5);
+
+/// Name test clients join under unless a test picks one.
+pub const NAME: &str = "tester";
+
+/// Settings for a fast in-process server on an ephemeral port.
+pub fn test_config(transport: TransportKind) -> ServerConfig {
+    ServerConfig {
+        bind: "127.0.0.1:0".into(),
+        transport,
+        tick_rate: 100,
+        ..ServerConfig::default()
+    }
+}
+
+/// Starts an in-process server on an ephemeral port and returns its address.
+pub async fn start_server(transport: TransportKind) -> SocketAddr {
+    start_server_with(test_config(transport)).await
+}
+
+pub async fn start_server_with(config: ServerConfig) -> SocketAddr {
+    let server = Server::bind(config, World::new()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+    addr
+}
+
+/// A server that shuts down when `stop` is sent a reason. `running` finishes once it has.
+pub struct StoppableServer {
+    pub addr: SocketAddr,
+    pub stop: oneshot::Sender<String>,
+    pub running: JoinHandle<io::Result<()>>,
+}
+
+pub async fn start_stoppable(config: ServerConfig) -> StoppableServer {
+    let server = Server::bind(config, World::new()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let (stop, reason) = oneshot::channel();
+    let running = tokio::spawn(server.run_until(async move { reason.await.unwrap_or_default() }));
+    StoppableServer {
+        addr,
+        stop,
+        running,
+    }
+}
+
+/// A client speaking the protocol directly, without the game around it.
+pub struct TestClient<R, W> {
+    reader: R,
+    writer: W,
+    next_id: u32,
+    snapshots: SnapshotReceiver,
+}
+
+pub type TcpClient = TestClient<FrameReader<OwnedReadHalf>, FrameWriter<OwnedWriteHalf>>;
+pub type UdpClient = TestClient<UdpReader, UdpWriter>;
+
+impl TcpClient {
+    pub async fn tcp(addr: SocketAddr) -> Self {
+        let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        Self::handshake(FrameReader::new(reader), FrameWriter::new(writer)).await
+    }
+}
+
+impl UdpClient {
+    pub async fn udp(addr: SocketAddr) -> Self {
+        let connection = UdpConnection::connect(addr, UdpConfig::default())
+            .await
+            .unwrap();
+        let (reader, writer) = connection.into_split();
+        Self::handshake(reader, writer).await
+    }
+}
+
+impl<R: MessageReader, W: MessageWriter> TestClient<R, W> {
+    async fn handshake(mut reader: R, mut writer: W) -> Self {
+        client_handshake(
+            &mut reader,
+            &mut writer,
+            ClientHello::new(Capabilities::ALL),
+        )
+        .await
+        .unwrap();
+        Self {
+            reader,
+            writer,
+            next_id: 0,
+            snapshots: SnapshotReceiver::default(),
+        }
+    }
+
+    pub async fn send(&mut self, message: ClientMessage) -> RequestId {
+        let id = RequestId(self.next_id);
+        self.next_id += 1;
+        self.writer
+            .send(&ClientEnvelope { id, message })
+            .await
+            .unwrap();
+        id
+    }
+
+    /// The next message from the server, applying and acking any snapshots on the way.
+    pub async fn recv(&mut self) -> ServerEnvelope {
+        let envelope = timeout(WAIT, self.reader.recv::<ServerEnvelope>())
+            .await
+            .expect("timed out waiting for the server")
+            .unwrap();
+        if let ServerMessage::Snapshot(snapshot) = &envelope.message
+            && let Some(state) = self.snapshots.receive(snapshot)
+        {
+            // The server may already be closing the connection, so the ack is best effort.
+            let message = ClientMessage::SnapshotAck { tick: state.tick };
+            let id = RequestId(self.next_id);
+            self.next_id += 1;
+            let _ = self.writer.send(&ClientEnvelope { id, message }).await;
+        }
+        envelope
+    }
+
+    /// True once the server closes the connection, skipping anything it sends before that.
+    pub async fn closed(&mut self) -> bool {
+        timeout(WAIT, async {
+            while self.reader.recv::<ServerEnvelope>().await.is_ok() {}
+        })
+        .await
+        .is_ok()
+    }
+
+    /// The first reply to `request`, skipping anything else.
+    pub async fn reply_to(&mut self, request: RequestId) -> ServerMessage {
+        loop {
+            let envelope = self.recv().await;
+            if envelope.reply_to == Some(request) {
+                return envelope.message;
+            }
+        }
+    }
+
+    /// The next chat message pushed by the server, skipping anything else.
+    pub async fn chat(&mut self) -> PlayerMessage {
+        loop {
+            if let ServerMessage::Message(message) = self.recv().await.message {
+                return message;
+            }
+        }
+    }
+
+    /// Connects a new player and returns it, consuming everything sent along with it.
+    pub async fn join(&mut self) -> Player {
+        self.join_with(None).await.0
+    }
+
+    /// Connects the player owning `token`, or a new one, and returns it with its token.
+    pub async fn join_with(&mut self, token: Option<SessionToken>) -> (Player, SessionToken) {
+        let (player, token, _) = self.join_as(token, NAME).await;
+        (player, token)
+    }
+
+    /// Like `join_with`, under the display name `name`. Also returns everyone else already
+    /// in the game.
+    pub async fn join_as(
+        &mut self,
+        token: Option<SessionToken>,
+        name: &str,
+    ) -> (Player, SessionToken, Vec<Player>) {
+        let request = self
+            .send(ClientMessage::ConnectionRequest {
+                token,
+                name: name.to_string(),
+            })
+            .await;
+        let ServerMessage::Player(player) = self.reply_to(request).await else {
+            panic!("expected the player in reply to the connection request");
+        };
+        let ServerMessage::Session(token) = self.reply_to(request).await else {
+            panic!("expected the session token after the player");
+        };
+        let ServerMessage::Map(_) = self.reply_to(request).await else {
+            panic!("expected the map after the player");
+        };
+        let ServerMessage::Roster(others) = self.reply_to(request).await else {
+            panic!("expected the roster after the map");
+        };
+        (player, token, others)
+    }
+
+    /// Waits for a snapshot in which `done` holds, and returns that state.
+    pub async fn snapshot_where(&mut self, done: impl Fn(&SnapshotState) -> bool) -> SnapshotState {
+        loop {
+            if let Some(state) = self.snapshots.latest()
+                && done(state)
+            {
+                return state.clone();
+            }
+            self.recv().await;
+        }
+    }
+}
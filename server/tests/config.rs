@@ -0,0 +1,116 @@
+use std::{path::Path, time::Duration};
+
+use server::{ConfigError, ServerConfig, TransportKind, World};
+use shared::{CHUNK_SIZE, WorldGen};
+
+#[test]
+fn the_example_config_spells_out_the_defaults() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("server.example.toml");
+    let config = ServerConfig::load(&path).unwrap();
+
+    assert_eq!(config, ServerConfig::default());
+    config.validate().unwrap();
+}
+
+#[test]
+fn settings_left_out_keep_their_defaults() {
+    let config = ServerConfig::from_toml(
+        r#"
+        [network]
+        transport = "udp"
+        link = "latency=75ms"
+
+        [worldgen]
+        seed = 7
+
+        [gameplay]
+        reconnect_grace = 5
+
+        [permissions]
+        admins = ["alice"]
+        "#,
+    )
+    .unwrap();
+
+    let defaults = ServerConfig::default();
+    assert_eq!(config.transport, TransportKind::Udp);
+    assert_eq!(config.link.latency, Duration::from_millis(75));
+    assert_eq!(config.world.seed, 7);
+    assert_eq!(config.world.noise_scale, defaults.world.noise_scale);
+    assert_eq!(config.reconnect_grace, Duration::from_secs(5));
+    assert_eq!(config.tick_rate, defaults.tick_rate);
+    assert_eq!(config.bind, defaults.bind);
+    assert_eq!(config.admins, ["alice"]);
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    let error = ServerConfig::from_toml("[gameplay]\ntick_rte = 60\n").unwrap_err();
+    assert!(error.to_string().contains("tick_rte"), "{error}");
+}
+
+#[test]
+fn bad_link_conditions_point_at_the_setting() {
+    let error = ServerConfig::from_toml("[network]\nlink = \"loss=2\"\n").unwrap_err();
+    assert!(error.to_string().contains("link"), "{error}");
+}
+
+#[test]
+fn validation_lists_every_problem() {
+    let config = ServerConfig {
+        bind: "5250".into(),
+        tick_rate: 0,
+        player_speed: 2.0,
+        save_interval: Duration::ZERO,
+        ..ServerConfig::default()
+    };
+
+    let Err(ConfigError::Invalid(problems)) = config.validate() else {
+        panic!("expected the config to be refused");
+    };
+    let settings: Vec<_> = problems
+        .iter()
+        .map(|problem| problem.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(
+        settings,
+        [
+            "network.bind",
+            "gameplay.tick_rate",
+            "gameplay.player_speed",
+            "persistence.save_interval"
+        ]
+    );
+}
+
+#[test]
+fn worlds_are_generated_from_the_settings() {
+    let settings = WorldGen {
+        seed: 42,
+        ..WorldGen::default()
+    };
+    let mut world = World::generate(&settings, 0.5);
+    assert!(
+        world.map.chunks.is_empty(),
+        "chunks are only generated once needed"
+    );
+
+    assert_eq!(world.join("someone", "someone").speed, 0.5);
+    assert!(world.map.chunks.contains_key(&[0, 0]));
+    assert_eq!(
+        World::generate(&settings, 0.5).chunks(&[[3, -2]]),
+        world.chunks(&[[3, -2]])
+    );
+}
+
+#[test]
+fn chunks_are_generated_ahead_of_players() {
+    let mut world = World::new();
+    world.join("walker", "walker");
+    world.teleport("walker", [CHUNK_SIZE as f32 * 4.5, 0.5, 0.0]);
+    assert!(!world.map.chunks.contains_key(&[5, 0]));
+
+    world.generate_near_players(1);
+    assert!(world.map.chunks.contains_key(&[5, 0]));
+    assert!(world.map.chunks.contains_key(&[3, -1]));
+}
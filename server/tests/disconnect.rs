@@ -0,0 +1,62 @@
+mod common;
+
+use common::{TcpClient, start_server};
+use server::TransportKind;
+use shared::{ClientMessage, ServerMessage};
+
+/// Waits until `client` is told that the player `id` left.
+async fn wait_for_disconnect(client: &mut TcpClient, id: &str) {
+    loop {
+        if let ServerMessage::Disconnect(left) = client.recv().await.message
+            && left == id
+        {
+            return;
+        }
+    }
+}
+
+async fn two_players() -> (TcpClient, TcpClient, String) {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut stays = TcpClient::tcp(addr).await;
+    let mut leaves = TcpClient::tcp(addr).await;
+    stays.join().await;
+    let id = leaves.join().await.id;
+
+    stays
+        .snapshot_where(|state| state.players.contains_key(&id))
+        .await;
+    (stays, leaves, id)
+}
+
+#[tokio::test]
+async fn graceful_disconnect_removes_the_player() {
+    let (mut stays, mut leaves, id) = two_players().await;
+
+    leaves.send(ClientMessage::Disconnect).await;
+
+    wait_for_disconnect(&mut stays, &id).await;
+    stays
+        .snapshot_where(|state| !state.players.contains_key(&id))
+        .await;
+}
+
+#[tokio::test]
+async fn dropped_connection_removes_the_player() {
+    let (mut stays, leaves, id) = two_players().await;
+
+    drop(leaves);
+
+    wait_for_disconnect(&mut stays, &id).await;
+    stays
+        .snapshot_where(|state| !state.players.contains_key(&id))
+        .await;
+}
+
+#[tokio::test]
+async fn server_closes_the_connection_after_a_disconnect() {
+    let (_stays, mut leaves, _) = two_players().await;
+
+    leaves.send(ClientMessage::Disconnect).await;
+
+    assert!(leaves.closed().await);
+}
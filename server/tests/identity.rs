@@ -0,0 +1,47 @@
+mod common;
+
+use common::{TcpClient, start_server};
+use server::TransportKind;
+use shared::ClientMessage;
+
+#[tokio::test]
+async fn moves_only_ever_move_the_senders_own_player() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut mover = TcpClient::tcp(addr).await;
+    let mut bystander = TcpClient::tcp(addr).await;
+    let moving = mover.join().await;
+    let standing = bystander.join().await;
+
+    for _ in 0..5 {
+        mover
+            .send(ClientMessage::MoveRequest {
+                direction: [1.0, 1.0, 0.0],
+            })
+            .await;
+    }
+
+    let state = bystander
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&moving.id)
+                .is_some_and(|p| p.position != moving.position)
+        })
+        .await;
+    assert_eq!(state.players[&standing.id], standing);
+}
+
+#[tokio::test]
+async fn connections_get_distinct_players() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut a = TcpClient::tcp(addr).await;
+    let mut b = TcpClient::tcp(addr).await;
+
+    let first = a.join().await;
+    let second = b.join().await;
+    // Joining again on the same connection finds the same player.
+    let again = a.join().await;
+
+    assert_ne!(first.id, second.id);
+    assert_eq!(again.id, first.id);
+}
@@ -0,0 +1,261 @@
+mod common;
+
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use common::{TcpClient, start_server_with, test_config};
+use server::{
+    Caller, ChatFilter, RateLimit, Server, ServerConfig, TransportKind, WordFilter, World,
+};
+use shared::{
+    ChatScope, ClientMessage, ErrorCode, PlayerMessage, RequestError, RequestId, ServerMessage,
+    SessionToken,
+};
+use uuid::Uuid;
+
+/// Sends `text` as global chat.
+async fn say(client: &mut TcpClient, text: &str) -> RequestId {
+    client
+        .send(ClientMessage::MessageRequest(PlayerMessage {
+            id: String::new(),
+            scope: ChatScope::Global,
+            message: text.into(),
+        }))
+        .await
+}
+
+/// The error `request` was refused with.
+async fn refusal(client: &mut TcpClient, request: RequestId) -> RequestError {
+    match client.reply_to(request).await {
+        ServerMessage::Error { code, message } => RequestError { code, message },
+        other => panic!("expected the request to be refused, got {other:?}"),
+    }
+}
+
+/// Config for a server whose sessions file makes `token` the session of the player `id`.
+fn with_account(id: &str, token: &str) -> ServerConfig {
+    let sessions = env::temp_dir().join(format!("sessions-{}.txt", Uuid::new_v4()));
+    fs::write(&sessions, format!("{token} {id}\n")).unwrap();
+    ServerConfig {
+        sessions_file: Some(sessions),
+        ..test_config(TransportKind::Tcp)
+    }
+}
+
+#[test]
+fn the_rate_limit_allows_a_burst_then_refills() {
+    let mut limit = RateLimit::new(2.0, 3);
+    let start = Instant::now();
+
+    for _ in 0..3 {
+        assert!(limit.allow_at(start));
+    }
+    assert!(!limit.allow_at(start));
+    assert!(!limit.allow_at(start + Duration::from_millis(400)));
+    assert!(limit.allow_at(start + Duration::from_millis(500)));
+    assert!(!limit.allow_at(start + Duration::from_millis(500)));
+
+    // A long quiet spell only ever refills the burst.
+    let later = start + Duration::from_secs(60);
+    for _ in 0..3 {
+        assert!(limit.allow_at(later));
+    }
+    assert!(!limit.allow_at(later));
+}
+
+#[test]
+fn the_word_filter_masks_whole_words_whatever_their_case() {
+    let filter = WordFilter::new(["heck", "darn"]);
+
+    assert_eq!(
+        filter.filter("alice", "Heck, darn it, what the HECK!"),
+        Ok("****, **** it, what the ****!".to_string())
+    );
+    assert_eq!(
+        filter.filter("alice", "checkered darning"),
+        Ok("checkered darning".to_string())
+    );
+}
+
+#[tokio::test]
+async fn messages_beyond_the_rate_limit_are_refused() {
+    let addr = start_server_with(ServerConfig {
+        message_rate: 0.01,
+        message_burst: 2,
+        ..test_config(TransportKind::Tcp)
+    })
+    .await;
+    let mut spammer = TcpClient::tcp(addr).await;
+    spammer.join().await;
+
+    say(&mut spammer, "one").await;
+    say(&mut spammer, "two").await;
+    assert_eq!(spammer.chat().await.message, "one");
+    assert_eq!(spammer.chat().await.message, "two");
+
+    let third = say(&mut spammer, "three").await;
+    assert_eq!(
+        refusal(&mut spammer, third).await.code,
+        ErrorCode::RateLimited
+    );
+}
+
+#[tokio::test]
+async fn muted_players_are_refused_until_unmuted() {
+    let server = Server::bind(
+        ServerConfig {
+            muted: vec!["quiet".into()],
+            ..with_account("quiet", "quiet-token")
+        },
+        World::new(),
+    )
+    .await
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    let state = server.state().clone();
+    tokio::spawn(server.run());
+
+    let mut muted = TcpClient::tcp(addr).await;
+    muted
+        .join_with(Some(SessionToken("quiet-token".into())))
+        .await;
+    let request = say(&mut muted, "can anyone hear me").await;
+    assert_eq!(refusal(&mut muted, request).await.code, ErrorCode::Muted);
+
+    state
+        .run_command(Caller::Console, "/unmute quiet")
+        .await
+        .unwrap();
+    say(&mut muted, "now?").await;
+    assert_eq!(muted.chat().await.message, "now?");
+}
+
+#[tokio::test]
+async fn banned_players_cannot_join() {
+    let addr = start_server_with(ServerConfig {
+        banned: vec!["troll".into()],
+        ..with_account("troll", "troll-token")
+    })
+    .await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    let request = client
+        .send(ClientMessage::ConnectionRequest {
+            token: Some(SessionToken("troll-token".into())),
+            name: "Troll".into(),
+        })
+        .await;
+    assert_eq!(refusal(&mut client, request).await.code, ErrorCode::Banned);
+}
+
+#[tokio::test]
+async fn banning_an_online_player_disconnects_it() {
+    let config = with_account("troll", "troll-token");
+    let server = Server::bind(config, World::new()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let state = server.state().clone();
+    tokio::spawn(server.run());
+    let token = SessionToken("troll-token".into());
+    let mut troll = TcpClient::tcp(addr).await;
+    troll.join_with(Some(token.clone())).await;
+    troll.snapshot_where(|state| state.players.len() == 1).await;
+
+    state
+        .run_command(Caller::Console, "/ban troll trolling")
+        .await
+        .unwrap();
+    loop {
+        if let ServerMessage::Error { code, message } = troll.recv().await.message {
+            assert_eq!(code, ErrorCode::Banned);
+            assert_eq!(message, "trolling");
+            break;
+        }
+    }
+    assert!(troll.closed().await);
+
+    let mut again = TcpClient::tcp(addr).await;
+    let request = again
+        .send(ClientMessage::ConnectionRequest {
+            token: Some(token),
+            name: "Troll".into(),
+        })
+        .await;
+    assert_eq!(refusal(&mut again, request).await.code, ErrorCode::Banned);
+}
+
+#[tokio::test]
+async fn a_ban_covers_the_address_until_lifted() {
+    let config = with_account("troll", "troll-token");
+    let server = Server::bind(config, World::new()).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let state = server.state().clone();
+    tokio::spawn(server.run());
+    let mut troll = TcpClient::tcp(addr).await;
+    troll
+        .join_with(Some(SessionToken("troll-token".into())))
+        .await;
+    troll.snapshot_where(|state| state.players.len() == 1).await;
+
+    state
+        .run_command(Caller::Console, "/ban troll")
+        .await
+        .unwrap();
+    assert!(troll.closed().await);
+
+    // Without the token the troll would be a new player, but comes from the same address.
+    let mut again = TcpClient::tcp(addr).await;
+    let request = again
+        .send(ClientMessage::ConnectionRequest {
+            token: None,
+            name: "Troll".into(),
+        })
+        .await;
+    assert_eq!(refusal(&mut again, request).await.code, ErrorCode::Banned);
+
+    state
+        .run_command(Caller::Console, "/unban troll")
+        .await
+        .unwrap();
+    let mut forgiven = TcpClient::tcp(addr).await;
+    forgiven.join_with(None).await;
+}
+
+struct NoShouting;
+
+impl ChatFilter for NoShouting {
+    fn filter(&self, _sender: &str, message: &str) -> Result<String, String> {
+        if message.chars().any(char::is_lowercase) {
+            Ok(message.to_string())
+        } else {
+            Err("please do not shout".into())
+        }
+    }
+}
+
+#[tokio::test]
+async fn chat_goes_through_every_filter_before_it_is_relayed() {
+    let server = Server::bind(
+        ServerConfig {
+            blocked_words: vec!["heck".into()],
+            ..test_config(TransportKind::Tcp)
+        },
+        World::new(),
+    )
+    .await
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    server.state().add_chat_filter(NoShouting);
+    tokio::spawn(server.run());
+    let mut alice = TcpClient::tcp(addr).await;
+    let mut bob = TcpClient::tcp(addr).await;
+    alice.join().await;
+    bob.join().await;
+
+    let shout = say(&mut alice, "HELLO").await;
+    assert_eq!(refusal(&mut alice, shout).await.code, ErrorCode::Blocked);
+
+    say(&mut alice, "what the heck").await;
+    assert_eq!(bob.chat().await.message, "what the ****");
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use std::{env, fs, path::PathBuf, time::Duration};
+
+use common::{TcpClient, start_server_with, test_config};
+use server::{
+    DEFAULT_PLAYER_SPEED, MAP_REACH, NEARBY_CHUNK_RADIUS, SAVE_VERSION, SaveDir, ServerConfig,
+    TransportKind, World,
+};
+use shared::{ClientMessage, Tile, TileType};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+fn temp_dir(prefix: &str) -> PathBuf {
+    env::temp_dir().join(format!("{prefix}-{}", Uuid::new_v4()))
+}
+
+#[test]
+fn a_modified_world_round_trips() {
+    let mut world = World::new();
+    let start = world.join("walker", "walker").position;
+    world.step("walker", [1.0, 0.0, 0.0]);
+    world.join("away", "away");
+    world.leave("away");
+    let mut chunk = world.chunks(&[[0, 0]]).remove(0);
+    chunk
+        .tiles
+        .insert((9, 9, 3), Tile::new([9, 9, 3], TileType::GrassSlopeL, 0.25));
+    world.map.insert(chunk);
+
+    let dir = temp_dir("world");
+    let saves = SaveDir::create(dir.clone()).unwrap();
+    saves.store(&world.save()).unwrap();
+    let loaded = saves.load().unwrap().expect("a save was just written");
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded, world.save());
+    let mut restored = World::restore(loaded, DEFAULT_PLAYER_SPEED);
+    assert_eq!(restored.map, world.map);
+    assert!(restored.players.is_empty(), "nobody is online after a load");
+    assert_ne!(restored.join("walker", "walker").position, start);
+    assert_eq!(restored.join("away", "away"), world.join("away", "away"));
+}
+
+#[test]
+fn saves_leave_out_chunks_as_generated() {
+    let mut world = World::new();
+    world.join("walker", "walker");
+    world.generate_near_players(NEARBY_CHUNK_RADIUS);
+    let mut chunk = world.chunks(&[[1, 1]]).remove(0);
+    chunk.tiles.insert(
+        (20, 20, 9),
+        Tile::new([20, 20, 9], TileType::GrassSlopeL, 0.25),
+    );
+    world.map.insert(chunk.clone());
+
+    let saved: Vec<_> = world.save().map.chunks.into_values().collect();
+    assert_eq!(saved, [chunk]);
+}
+
+#[test]
+fn chunks_out_of_reach_are_forgotten_unless_changed() {
+    let mut world = World::new();
+    world.join("walker", "walker");
+    world.chunks(&[[0, 0], [MAP_REACH, 0], [MAP_REACH + 1, 0]]);
+    let mut changed = world.chunks(&[[0, MAP_REACH + 1]]).remove(0);
+    changed.tiles.clear();
+    world.map.insert(changed);
+
+    world.forget_far_chunks(MAP_REACH);
+
+    let kept: Vec<_> = world.map.chunks.keys().copied().collect();
+    assert_eq!(kept, [[0, 0], [0, MAP_REACH + 1], [MAP_REACH, 0]]);
+}
+
+#[test]
+fn an_empty_directory_has_no_save() {
+    let dir = temp_dir("world");
+    let saves = SaveDir::create(dir.clone()).unwrap();
+    let loaded = saves.load().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(loaded.is_none());
+}
+
+#[test]
+fn saves_in_another_format_are_refused() {
+    let dir = temp_dir("world");
+    let saves = SaveDir::create(dir.clone()).unwrap();
+    saves.store(&World::new().save()).unwrap();
+
+    let file = dir.join("world.sav");
+    let mut bytes = fs::read(&file).unwrap();
+    bytes[4..8].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+    fs::write(&file, bytes).unwrap();
+    let error = saves.load().unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(error.to_string().contains("format"), "{error}");
+}
+
+#[tokio::test]
+async fn players_keep_their_place_across_a_restart() {
+    let dir = temp_dir("world");
+    let config = ServerConfig {
+        sessions_file: Some(dir.join("sessions.txt")),
+        save_dir: Some(dir.clone()),
+        save_interval: Duration::from_millis(50),
+        ..test_config(TransportKind::Tcp)
+    };
+
+    let before = start_server_with(config.clone()).await;
+    let mut client = TcpClient::tcp(before).await;
+    let (player, token) = client.join_with(None).await;
+    // A single move is a single step, so the player stops right where this snapshot has it.
+    client
+        .send(ClientMessage::MoveRequest {
+            direction: [1.0, 1.0, 0.0],
+        })
+        .await;
+    let moved = client
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&player.id)
+                .is_some_and(|p| p.position != player.position)
+        })
+        .await
+        .players[&player.id]
+        .clone();
+    sleep(Duration::from_millis(300)).await;
+
+    let after = start_server_with(config).await;
+    let mut client = TcpClient::tcp(after).await;
+    let (resumed, _) = client.join_with(Some(token)).await;
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(resumed, moved);
+}
@@ -0,0 +1,319 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{NAME, TcpClient, UdpClient, WAIT, start_server, start_server_with, test_config};
+use server::{MAP_REACH, MAP_REQUEST_BURST, ServerConfig, TransportKind};
+use shared::{
+    ClientMessage, ErrorCode, MAX_CHUNK_COORD, MAX_CHUNKS_PER_REQUEST, MAX_NAME_LEN, ServerMessage,
+    chunk_at,
+};
+use tokio::{io::AsyncReadExt, net::TcpStream, time::timeout};
+
+#[tokio::test]
+async fn joining_returns_the_player_and_the_map() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    let request = client
+        .send(ClientMessage::ConnectionRequest {
+            token: None,
+            name: NAME.into(),
+        })
+        .await;
+    let ServerMessage::Player(player) = client.reply_to(request).await else {
+        panic!("expected the player first");
+    };
+    let ServerMessage::Session(_) = client.reply_to(request).await else {
+        panic!("expected the session token second");
+    };
+    let ServerMessage::Map(map) = client.reply_to(request).await else {
+        panic!("expected the map third");
+    };
+
+    let ServerMessage::Roster(others) = client.reply_to(request).await else {
+        panic!("expected the roster last");
+    };
+
+    assert!(!player.id.is_empty());
+    assert_eq!(map.len(), 9, "the chunks around the spawn");
+    assert!(map.iter().all(|chunk| !chunk.tiles.is_empty()));
+    assert!(others.is_empty());
+}
+
+#[tokio::test]
+async fn clients_that_never_say_hello_are_dropped() {
+    let addr = start_server_with(ServerConfig {
+        handshake_timeout: Duration::from_millis(200),
+        ..test_config(TransportKind::Tcp)
+    })
+    .await;
+    let mut silent = TcpStream::connect(addr).await.unwrap();
+
+    let read = timeout(WAIT, silent.read(&mut [0; 64]))
+        .await
+        .expect("the server should give up on the handshake");
+    assert_eq!(read.unwrap(), 0, "the connection should be closed");
+
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+}
+
+#[tokio::test]
+async fn joiners_get_everyone_already_online() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut first = TcpClient::tcp(addr).await;
+    let mut second = TcpClient::tcp(addr).await;
+    let mut third = TcpClient::tcp(addr).await;
+    let first_player = first.join().await;
+    let second_player = second.join().await;
+
+    let request = third
+        .send(ClientMessage::ConnectionRequest {
+            token: None,
+            name: NAME.into(),
+        })
+        .await;
+    let ServerMessage::Player(own) = third.reply_to(request).await else {
+        panic!("expected the player first");
+    };
+    // The session token and the map.
+    third.reply_to(request).await;
+    third.reply_to(request).await;
+    let ServerMessage::Roster(others) = third.reply_to(request).await else {
+        panic!("expected the roster after the map");
+    };
+
+    let mut ids: Vec<_> = others.into_iter().map(|p| p.id).collect();
+    ids.sort();
+    let mut expected = vec![first_player.id, second_player.id];
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert!(!ids.contains(&own.id));
+}
+
+#[tokio::test]
+async fn others_see_the_name_a_player_joined_under() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut alice = TcpClient::tcp(addr).await;
+    let mut bob = TcpClient::tcp(addr).await;
+
+    let (alice_player, _, _) = alice.join_as(None, "Alice").await;
+    let (bob_player, _, roster) = bob.join_as(None, "Bob").await;
+    assert_eq!(alice_player.name, "Alice");
+    assert_eq!(roster[0].name, "Alice");
+
+    let state = alice
+        .snapshot_where(|state| state.players.contains_key(&bob_player.id))
+        .await;
+    assert_eq!(state.players[&bob_player.id].name, "Bob");
+}
+
+#[tokio::test]
+async fn unshowable_names_are_refused() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    for name in ["", "   ", "a\nb", &"x".repeat(MAX_NAME_LEN + 1)] {
+        let request = client
+            .send(ClientMessage::ConnectionRequest {
+                token: None,
+                name: name.into(),
+            })
+            .await;
+        let ServerMessage::Error { code, .. } = client.reply_to(request).await else {
+            panic!("expected {name:?} to be refused");
+        };
+        assert_eq!(code, ErrorCode::InvalidName);
+    }
+
+    let player = client.join().await;
+    assert_eq!(player.name, NAME);
+}
+
+#[tokio::test]
+async fn servers_in_one_process_do_not_share_players() {
+    let a = start_server(TransportKind::Tcp).await;
+    let b = start_server(TransportKind::Tcp).await;
+    let mut on_a = TcpClient::tcp(a).await;
+    let mut on_b = TcpClient::tcp(b).await;
+
+    let player_a = on_a.join().await;
+    let player_b = on_b.join().await;
+
+    let state = on_b
+        .snapshot_where(|state| state.players.contains_key(&player_b.id))
+        .await;
+    assert!(!state.players.contains_key(&player_a.id));
+    assert_eq!(state.players.len(), 1);
+}
+
+#[tokio::test]
+async fn moves_show_up_in_snapshots() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+    let player = client.join().await;
+
+    client
+        .send(ClientMessage::MoveRequest {
+            direction: [1.0, 0.0, 0.0],
+        })
+        .await;
+
+    client
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&player.id)
+                .is_some_and(|p| p.position != player.position)
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn non_finite_move_components_are_ignored() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+    let player = client.join().await;
+
+    client
+        .send(ClientMessage::MoveRequest {
+            direction: [f32::NAN, 1.0, f32::INFINITY],
+        })
+        .await;
+
+    let state = client
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&player.id)
+                .is_some_and(|p| p.position != player.position)
+        })
+        .await;
+    let position = state.players[&player.id].position;
+    assert!(position.iter().all(|c| c.is_finite()));
+    assert_eq!(position[0], player.position[0]);
+}
+
+#[tokio::test]
+async fn moving_before_joining_is_refused() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    let request = client
+        .send(ClientMessage::MoveRequest {
+            direction: [1.0, 0.0, 0.0],
+        })
+        .await;
+
+    let ServerMessage::Error { code, .. } = client.reply_to(request).await else {
+        panic!("expected an error");
+    };
+    assert_eq!(code, ErrorCode::NotConnected);
+}
+
+#[tokio::test]
+async fn map_requests_return_the_chunks_asked_for() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    let request = client
+        .send(ClientMessage::MapRequest(vec![[5, -3], [-MAP_REACH, 2]]))
+        .await;
+    let ServerMessage::Map(chunks) = client.reply_to(request).await else {
+        panic!("expected the chunks");
+    };
+    let coords: Vec<_> = chunks.iter().map(|chunk| chunk.coord).collect();
+    assert_eq!(coords, [[5, -3], [-MAP_REACH, 2]]);
+    assert!(chunks.iter().all(|chunk| !chunk.tiles.is_empty()));
+}
+
+#[tokio::test]
+async fn map_requests_for_too_many_or_unreachable_chunks_are_refused() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    let too_many = (0..=MAX_CHUNKS_PER_REQUEST as i64)
+        .map(|x| [x, 0])
+        .collect();
+    let beyond = vec![[0, 0], [MAX_CHUNK_COORD + 1, 0]];
+    let too_far = vec![[0, 0], [0, MAP_REACH + 1]];
+    for coords in [too_many, beyond, too_far] {
+        let request = client.send(ClientMessage::MapRequest(coords)).await;
+        let ServerMessage::Error { code, .. } = client.reply_to(request).await else {
+            panic!("expected an error");
+        };
+        assert_eq!(code, ErrorCode::InvalidChunks);
+    }
+}
+
+#[tokio::test]
+async fn map_requests_before_joining_are_refused() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    let request = client.send(ClientMessage::MapRequest(vec![[0, 0]])).await;
+    let ServerMessage::Error { code, .. } = client.reply_to(request).await else {
+        panic!("expected an error");
+    };
+    assert_eq!(code, ErrorCode::NotConnected);
+}
+
+#[tokio::test]
+async fn map_requests_beyond_the_burst_are_rate_limited() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+    client.join().await;
+
+    let mut requests = Vec::new();
+    for _ in 0..MAP_REQUEST_BURST * 2 {
+        requests.push(client.send(ClientMessage::MapRequest(vec![[0, 0]])).await);
+    }
+    let mut limited = 0;
+    for (i, request) in requests.into_iter().enumerate() {
+        match client.reply_to(request).await {
+            ServerMessage::Map(_) => {}
+            ServerMessage::Error { code, .. } => {
+                assert!(i >= MAP_REQUEST_BURST as usize, "the burst was refused");
+                assert_eq!(code, ErrorCode::RateLimited);
+                limited += 1;
+            }
+            other => panic!("unexpected reply {other:?}"),
+        }
+    }
+    assert!(limited > 0, "nothing was rate limited");
+}
+
+#[tokio::test]
+async fn players_join_over_udp() {
+    let addr = start_server(TransportKind::Udp).await;
+    let mut client = UdpClient::udp(addr).await;
+
+    let player = client.join().await;
+
+    client
+        .snapshot_where(|state| state.players.contains_key(&player.id))
+        .await;
+}
+
+#[tokio::test]
+async fn full_map_requests_reach_udp_clients() {
+    let addr = start_server(TransportKind::Udp).await;
+    let mut client = UdpClient::udp(addr).await;
+    let player = client.join().await;
+
+    // The reply is far bigger than anything a client may send.
+    let [cx, cy] = chunk_at(player.position);
+    let side = (MAX_CHUNKS_PER_REQUEST as f64).sqrt() as i64;
+    let coords: Vec<_> = (0..MAX_CHUNKS_PER_REQUEST as i64)
+        .map(|i| [cx + i % side - side / 2, cy + i / side - side / 2])
+        .collect();
+    let request = client.send(ClientMessage::MapRequest(coords.clone())).await;
+    let ServerMessage::Map(chunks) = client.reply_to(request).await else {
+        panic!("expected the chunks");
+    };
+    let received: Vec<_> = chunks.iter().map(|chunk| chunk.coord).collect();
+    assert_eq!(received, coords);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::{env, fs, time::Duration};
+
+use common::{TcpClient, start_server, start_server_with, test_config};
+use server::TransportKind;
+use shared::{ClientMessage, ErrorCode, ServerMessage, SessionToken};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+/// Joins with `mover`, takes a few steps and returns the token and where the player ended up.
+async fn move_away(mover: &mut TcpClient) -> (SessionToken, String, [f32; 3]) {
+    let (player, token) = mover.join_with(None).await;
+    for _ in 0..5 {
+        mover
+            .send(ClientMessage::MoveRequest {
+                direction: [1.0, 1.0, 0.0],
+            })
+            .await;
+    }
+    let state = mover
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&player.id)
+                .is_some_and(|p| p.position != player.position)
+        })
+        .await;
+    (token, player.id.clone(), state.players[&player.id].position)
+}
+
+#[tokio::test]
+async fn reconnecting_with_the_token_resumes_the_player() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut first = TcpClient::tcp(addr).await;
+    let (token, id, position) = move_away(&mut first).await;
+    drop(first);
+
+    let mut second = TcpClient::tcp(addr).await;
+    let (player, resumed) = second.join_with(Some(token.clone())).await;
+
+    assert_eq!(player.id, id);
+    assert_eq!(player.position, position);
+    assert_eq!(resumed, token);
+}
+
+#[tokio::test]
+async fn players_start_over_after_the_grace_period() {
+    let config = test_config(TransportKind::Tcp);
+    let addr = start_server_with(server::ServerConfig {
+        reconnect_grace: Duration::from_millis(50),
+        ..config
+    })
+    .await;
+    let mut first = TcpClient::tcp(addr).await;
+    let spawn = first.join_with(None).await.0.position;
+    drop(first);
+    let mut first = TcpClient::tcp(addr).await;
+    let (token, id, _) = move_away(&mut first).await;
+    drop(first);
+
+    sleep(Duration::from_millis(300)).await;
+
+    let mut second = TcpClient::tcp(addr).await;
+    let (player, _) = second.join_with(Some(token)).await;
+    assert_eq!(player.id, id);
+    assert_eq!(player.position, spawn);
+}
+
+#[tokio::test]
+async fn unknown_tokens_get_a_new_player() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut client = TcpClient::tcp(addr).await;
+
+    let stale = SessionToken("not-a-token".into());
+    let (_, token) = client.join_with(Some(stale.clone())).await;
+
+    assert_ne!(token, stale);
+}
+
+#[tokio::test]
+async fn a_second_connection_takes_the_player_over() {
+    let addr = start_server(TransportKind::Tcp).await;
+    let mut first = TcpClient::tcp(addr).await;
+    let (player, token) = first.join_with(None).await;
+
+    let mut second = TcpClient::tcp(addr).await;
+    let (resumed, _) = second.join_with(Some(token)).await;
+    assert_eq!(resumed.id, player.id);
+
+    let code = loop {
+        if let ServerMessage::Error { code, .. } = first.recv().await.message {
+            break code;
+        }
+    };
+    assert_eq!(code, ErrorCode::TakenOver);
+    assert!(first.closed().await);
+
+    // The old connection going away must not take the player with it.
+    for _ in 0..5 {
+        second
+            .send(ClientMessage::MoveRequest {
+                direction: [1.0, 1.0, 0.0],
+            })
+            .await;
+    }
+    second
+        .snapshot_where(|state| {
+            state
+                .players
+                .get(&player.id)
+                .is_some_and(|p| p.position != player.position)
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn accounts_survive_a_restart() {
+    let path = env::temp_dir().join(format!("sessions-{}.txt", Uuid::new_v4()));
+    let config = server::ServerConfig {
+        sessions_file: Some(path.clone()),
+        ..test_config(TransportKind::Tcp)
+    };
+
+    let before = start_server_with(config.clone()).await;
+    let mut client = TcpClient::tcp(before).await;
+    let (player, token) = client.join_with(None).await;
+
+    let after = start_server_with(config).await;
+    let mut client = TcpClient::tcp(after).await;
+    let (resumed, _) = client.join_with(Some(token)).await;
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(resumed.id, player.id);
+}
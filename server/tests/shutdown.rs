@@ -0,0 +1,98 @@
+mod common;
+
+use std::{env, fs, io, time::Duration};
+
+use common::{
+    StoppableServer, TcpClient, TestClient, UdpClient, WAIT, start_stoppable, test_config,
+};
+use server::{SaveDir, ServerConfig, TransportKind};
+use shared::{MessageReader, MessageWriter, ServerMessage};
+use tokio::{net::TcpStream, task::JoinHandle, time::timeout};
+use uuid::Uuid;
+
+/// Skips everything up to the shutdown notice and returns its reason.
+async fn shutdown_reason<R: MessageReader, W: MessageWriter>(
+    client: &mut TestClient<R, W>,
+) -> String {
+    loop {
+        if let ServerMessage::Shutdown(reason) = client.recv().await.message {
+            return reason;
+        }
+    }
+}
+
+fn stop(server: StoppableServer, reason: &str) -> JoinHandle<io::Result<()>> {
+    server.stop.send(reason.to_string()).unwrap();
+    server.running
+}
+
+#[tokio::test]
+async fn tcp_clients_are_told_why_the_server_stops() {
+    let server = start_stoppable(test_config(TransportKind::Tcp)).await;
+    let mut client = TcpClient::tcp(server.addr).await;
+    client.join().await;
+
+    let running = stop(server, "maintenance");
+
+    assert_eq!(shutdown_reason(&mut client).await, "maintenance");
+    assert!(client.closed().await);
+    timeout(WAIT, running).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn udp_clients_are_told_why_the_server_stops() {
+    let server = start_stoppable(test_config(TransportKind::Udp)).await;
+    let mut client = UdpClient::udp(server.addr).await;
+    client.join().await;
+
+    let running = stop(server, "maintenance");
+
+    assert_eq!(shutdown_reason(&mut client).await, "maintenance");
+    assert!(client.closed().await);
+    timeout(WAIT, running).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn the_world_is_saved_on_shutdown() {
+    let dir = env::temp_dir().join(format!("world-{}", Uuid::new_v4()));
+    let server = start_stoppable(ServerConfig {
+        save_dir: Some(dir.clone()),
+        save_interval: Duration::from_secs(3600),
+        ..test_config(TransportKind::Tcp)
+    })
+    .await;
+    let mut client = TcpClient::tcp(server.addr).await;
+    let player = client.join().await;
+
+    timeout(WAIT, stop(server, "restart"))
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let save = SaveDir::create(dir.clone()).unwrap().load().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let save = save.expect("shutting down should have saved the world");
+    assert_eq!(save.players[&player.id], player);
+}
+
+#[tokio::test]
+async fn shutdown_does_not_wait_forever_for_stuck_clients() {
+    let server = start_stoppable(ServerConfig {
+        shutdown_timeout: Duration::from_millis(200),
+        ..test_config(TransportKind::Tcp)
+    })
+    .await;
+    // Connects but never finishes the handshake, so it cannot be told to leave.
+    let _stuck = TcpStream::connect(server.addr).await.unwrap();
+    let mut client = TcpClient::tcp(server.addr).await;
+    client.join().await;
+
+    let running = stop(server, "maintenance");
+
+    timeout(WAIT, running)
+        .await
+        .expect("shutdown should give up on the stuck client")
+        .unwrap()
+        .unwrap();
+}
@@ -0,0 +1,84 @@
+//! Compares raw and compressed frame sizes, and the time taken to send and receive them, for
+//! `ServerMessage::Map` snapshots of growing size.
+//!
+//! Run with `cargo bench -p shared --bench compression`.
+
+use std::{hint::black_box, time::Instant};
+
+use shared::{
+    Chunk, DEFAULT_COMPRESSION_THRESHOLD, FrameReader, FrameWriter, MessageReader, MessageWriter,
+    ServerMessage, WorldGen, chunks_around,
+};
+
+const ITERATIONS: u32 = 200;
+
+/// The chunks `radius` chunks in every direction around the origin, sent in one piece.
+fn snapshot(radius: i64) -> ServerMessage {
+    let chunks = chunks_around([0, 0], radius)
+        .into_iter()
+        .map(|coord| Chunk::generate(coord, &WorldGen::default()))
+        .collect();
+    ServerMessage::Map(chunks)
+}
+
+/// Sends `msg` `ITERATIONS` times and reads it back, returning the wire size of one frame and
+/// the average microseconds spent writing and reading it.
+async fn measure(msg: &ServerMessage, threshold: Option<usize>) -> (usize, f64, f64) {
+    let mut wire = Vec::new();
+    let mut writer = FrameWriter::new(&mut wire);
+    writer.set_compression_threshold(threshold);
+    writer.send(msg).await.unwrap();
+    let frame_len = wire.len();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut sink = Vec::with_capacity(frame_len);
+        let mut writer = FrameWriter::new(&mut sink);
+        writer.set_compression_threshold(threshold);
+        writer.send(black_box(msg)).await.unwrap();
+    }
+    let write_us = start.elapsed().as_secs_f64() * 1e6 / ITERATIONS as f64;
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut reader = FrameReader::new(wire.as_slice());
+        black_box(reader.recv::<ServerMessage>().await.unwrap());
+    }
+    let read_us = start.elapsed().as_secs_f64() * 1e6 / ITERATIONS as f64;
+
+    (frame_len, write_us, read_us)
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    println!(
+        "{:>7} {:>10} {:>12} {:>7} {:>11} {:>11} {:>11} {:>11}",
+        "tiles",
+        "raw bytes",
+        "lz4 bytes",
+        "ratio",
+        "raw write",
+        "lz4 write",
+        "raw read",
+        "lz4 read"
+    );
+    for radius in [0, 1, 3, 6] {
+        let msg = snapshot(radius);
+        let ServerMessage::Map(chunks) = &msg else {
+            unreachable!();
+        };
+        let tiles: usize = chunks.iter().map(|chunk| chunk.tiles.len()).sum();
+
+        let (raw, raw_write, raw_read) = runtime.block_on(measure(&msg, None));
+        let (lz4, lz4_write, lz4_read) =
+            runtime.block_on(measure(&msg, Some(DEFAULT_COMPRESSION_THRESHOLD)));
+
+        println!(
+            "{tiles:>7} {raw:>10} {lz4:>12} {:>6.1}% {raw_write:>9.1}us {lz4_write:>9.1}us {raw_read:>9.1}us {lz4_read:>9.1}us",
+            lz4 as f64 / raw as f64 * 100.0
+        );
+    }
+}
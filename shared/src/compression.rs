@@ -0,0 +1,39 @@
+use lz4_flex::block::{DecompressError, compress_prepend_size, decompress_into, uncompressed_size};
+
+use crate::{FrameConfig, FrameError, check_frame_len};
+
+/// Frames shorter than this are sent as they are; compressing them saves too little to be
+/// worth the time.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// LZ4-compresses `frame` if compression is enabled, the frame reaches `threshold` bytes and
+/// compressing actually makes it smaller. Returns whether the result is compressed.
+pub(crate) fn compress_frame(frame: Vec<u8>, threshold: Option<usize>) -> (bool, Vec<u8>) {
+    match threshold {
+        Some(threshold) if frame.len() >= threshold => {
+            let compressed = compress_prepend_size(&frame);
+            if compressed.len() < frame.len() {
+                (true, compressed)
+            } else {
+                (false, frame)
+            }
+        }
+        _ => (false, frame),
+    }
+}
+
+/// Reverses `compress_frame`. The decompressed size is checked against the frame limit
+/// before anything is allocated, so a tiny frame cannot expand into a huge one.
+pub(crate) fn decompress_frame(bytes: &[u8], config: &FrameConfig) -> Result<Vec<u8>, FrameError> {
+    let (len, block) = uncompressed_size(bytes)?;
+    check_frame_len(len, config)?;
+
+    let mut frame = vec![0u8; len];
+    let written = decompress_into(block, &mut frame)?;
+    if written != len {
+        // The block ended before producing the size it announced.
+        return Err(DecompressError::ExpectedAnotherByte.into());
+    }
+
+    Ok(frame)
+}
@@ -0,0 +1,364 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tokio::{
+    sync::{
+        mpsc::{UnboundedSender, unbounded_channel},
+        oneshot,
+    },
+    time::sleep_until,
+};
+
+use crate::{Channel, FrameError, MessageWriter};
+
+/// How long a "lost" reliable frame is held back on a stream transport, standing in for the
+/// retransmission timeout a real network would add.
+const RETRANSMIT_PENALTY: Duration = Duration::from_millis(200);
+
+/// Simulated network conditions. All values are one-way, applied by the sender.
+///
+/// Parses from a comma separated list such as `latency=75ms,jitter=10ms,loss=0.02`, with
+/// durations in milliseconds and `bandwidth` in bytes per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    /// Each frame's latency varies uniformly by up to this much either way.
+    pub jitter: Duration,
+    /// Probability that a frame is lost.
+    pub loss: f64,
+    /// Probability that a frame is held back long enough for later frames to overtake it.
+    pub reorder: f64,
+    /// Bytes per second; `None` is unlimited.
+    pub bandwidth: Option<u32>,
+}
+
+impl LinkConditions {
+    /// True when the conditions would change nothing, so no conditioning is needed.
+    pub fn is_perfect(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl FromStr for LinkConditions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut conditions = Self::default();
+
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected key=value, got `{setting}`"))?;
+            let value = value.trim();
+
+            match key.trim() {
+                "latency" => conditions.latency = parse_millis(value)?,
+                "jitter" => conditions.jitter = parse_millis(value)?,
+                "loss" => conditions.loss = parse_probability(value)?,
+                "reorder" => conditions.reorder = parse_probability(value)?,
+                "bandwidth" => {
+                    let bytes: u32 = value
+                        .parse()
+                        .with_context(|| format!("invalid bandwidth `{value}`"))?;
+                    if bytes == 0 {
+                        bail!("bandwidth must be greater than zero");
+                    }
+                    conditions.bandwidth = Some(bytes);
+                }
+                other => bail!(
+                    "unknown link setting `{other}`, expected latency, jitter, loss, reorder or bandwidth"
+                ),
+            }
+        }
+
+        Ok(conditions)
+    }
+}
+
+impl fmt::Display for LinkConditions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "latency={}ms,jitter={}ms,loss={},reorder={}",
+            self.latency.as_millis(),
+            self.jitter.as_millis(),
+            self.loss,
+            self.reorder
+        )?;
+        if let Some(bandwidth) = self.bandwidth {
+            write!(f, ",bandwidth={bandwidth}")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_millis(value: &str) -> anyhow::Result<Duration> {
+    let millis: u64 = value
+        .strip_suffix("ms")
+        .unwrap_or(value)
+        .parse()
+        .with_context(|| format!("invalid duration `{value}`, expected milliseconds"))?;
+    Ok(Duration::from_millis(millis))
+}
+
+fn parse_probability(value: &str) -> anyhow::Result<f64> {
+    let p: f64 = value
+        .parse()
+        .with_context(|| format!("invalid probability `{value}`"))?;
+    if !(0.0..=1.0).contains(&p) {
+        bail!("probability `{value}` must be between 0 and 1");
+    }
+    Ok(p)
+}
+
+/// Decides when, if ever, each frame sent over a conditioned link arrives.
+pub struct LinkConditioner {
+    conditions: LinkConditions,
+    rng: StdRng,
+    /// When the simulated link finishes sending what it has already been given.
+    link_free_at: Instant,
+    /// Arrival of the last ordered frame; ordered frames never overtake each other.
+    last_ordered_arrival: Instant,
+    sent: u64,
+}
+
+impl LinkConditioner {
+    pub fn new(conditions: LinkConditions) -> Self {
+        Self::with_rng(conditions, StdRng::from_os_rng())
+    }
+
+    /// A conditioner whose random choices repeat for the same seed.
+    pub fn seeded(conditions: LinkConditions, seed: u64) -> Self {
+        Self::with_rng(conditions, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(conditions: LinkConditions, rng: StdRng) -> Self {
+        let now = Instant::now();
+        Self {
+            conditions,
+            rng,
+            link_free_at: now,
+            last_ordered_arrival: now,
+            sent: 0,
+        }
+    }
+
+    /// Sequence number breaking ties between frames that arrive at the same instant.
+    fn next_order(&mut self) -> u64 {
+        self.sent += 1;
+        self.sent
+    }
+
+    pub fn conditions(&self) -> LinkConditions {
+        self.conditions
+    }
+
+    /// Returns when a frame of `len` bytes sent now arrives, or `None` if it is lost.
+    ///
+    /// `ordered` frames ride on something that already guarantees delivery and order, like a
+    /// TCP stream: they are never dropped or reordered, and loss only delays them.
+    pub fn schedule(&mut self, len: usize, ordered: bool) -> Option<Instant> {
+        let LinkConditions {
+            latency,
+            jitter,
+            loss,
+            reorder,
+            bandwidth,
+        } = self.conditions;
+        let now = Instant::now();
+
+        let start = self.link_free_at.max(now);
+        let transmit = bandwidth
+            .map(|bytes_per_sec| Duration::from_secs_f64(len as f64 / bytes_per_sec as f64))
+            .unwrap_or_default();
+        self.link_free_at = start + transmit;
+
+        let mut delay = latency;
+        if !jitter.is_zero() {
+            let offset = self.rng.random_range(-1.0..=1.0) * jitter.as_secs_f64();
+            delay = Duration::from_secs_f64((delay.as_secs_f64() + offset).max(0.0));
+        }
+        let mut arrival = start + transmit + delay;
+
+        let lost = loss > 0.0 && self.rng.random_bool(loss);
+        if ordered {
+            if lost {
+                arrival += latency * 2 + RETRANSMIT_PENALTY;
+            }
+            arrival = arrival.max(self.last_ordered_arrival);
+            self.last_ordered_arrival = arrival;
+        } else {
+            if lost {
+                return None;
+            }
+            if reorder > 0.0 && self.rng.random_bool(reorder) {
+                arrival += latency + jitter * 2 + Duration::from_millis(20);
+            }
+        }
+
+        Some(arrival)
+    }
+}
+
+/// Wraps a `MessageWriter`, delaying and dropping its frames according to `LinkConditions`.
+///
+/// Reliable frames are only ever delayed; unreliable frames may also be lost or reordered.
+/// With perfect conditions frames go straight to the inner writer.
+pub struct ConditionedWriter<W> {
+    inner: Conditioned<W>,
+}
+
+enum Conditioned<W> {
+    Direct(W),
+    Delayed {
+        conditioner: Box<LinkConditioner>,
+        frames: UnboundedSender<Queued>,
+        /// Resolves, with an error, once the delaying task has closed the inner writer.
+        finished: oneshot::Receiver<()>,
+    },
+}
+
+/// What the delaying task is handed by a `ConditionedWriter`.
+enum Queued {
+    Frame(Pending),
+    /// Passed straight on to the inner writer. Frames already waiting may still pick up the
+    /// new setting, which is harmless because readers accept both kinds.
+    CompressionThreshold(Option<usize>),
+}
+
+/// A frame waiting for its arrival time. Ordered by arrival, then by send order.
+struct Pending {
+    arrival: Instant,
+    order: u64,
+    channel: Channel,
+    frame: Vec<u8>,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        (self.arrival, self.order) == (other.arrival, other.order)
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.arrival, self.order).cmp(&(other.arrival, other.order))
+    }
+}
+
+impl<W: MessageWriter + 'static> ConditionedWriter<W> {
+    pub fn new(inner: W, conditions: LinkConditions) -> Self {
+        Self::with_conditioner(inner, LinkConditioner::new(conditions))
+    }
+
+    pub fn with_conditioner(inner: W, conditioner: LinkConditioner) -> Self {
+        if conditioner.conditions().is_perfect() {
+            return Self {
+                inner: Conditioned::Direct(inner),
+            };
+        }
+
+        let (frames, mut rx) = unbounded_channel::<Queued>();
+        let (finished_tx, finished) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let _finished = finished_tx;
+            let mut inner = inner;
+            let mut queue: BinaryHeap<Reverse<Pending>> = BinaryHeap::new();
+            let mut closed = false;
+
+            loop {
+                let next = queue.peek().map(|Reverse(pending)| pending.arrival);
+                tokio::select! {
+                    queued = rx.recv(), if !closed => match queued {
+                        Some(Queued::Frame(pending)) => queue.push(Reverse(pending)),
+                        Some(Queued::CompressionThreshold(threshold)) => {
+                            inner.set_compression_threshold(threshold);
+                        }
+                        None => closed = true,
+                    },
+                    _ = sleep_until(next.unwrap_or_else(Instant::now).into()), if next.is_some() => {
+                        if let Some(Reverse(pending)) = queue.pop()
+                            && inner.send_frame(pending.channel, pending.frame).await.is_err()
+                        {
+                            break;
+                        }
+                    }
+                    else => break,
+                }
+            }
+            let _ = inner.close().await;
+        });
+
+        Self {
+            inner: Conditioned::Delayed {
+                conditioner: Box::new(conditioner),
+                frames,
+                finished,
+            },
+        }
+    }
+}
+
+impl<W: MessageWriter + 'static> MessageWriter for ConditionedWriter<W> {
+    async fn send_frame(&mut self, channel: Channel, frame: Vec<u8>) -> Result<(), FrameError> {
+        match &mut self.inner {
+            Conditioned::Direct(inner) => inner.send_frame(channel, frame).await,
+            Conditioned::Delayed {
+                conditioner,
+                frames,
+                ..
+            } => {
+                let ordered = channel == Channel::ReliableOrdered;
+                let Some(arrival) = conditioner.schedule(frame.len(), ordered) else {
+                    return Ok(());
+                };
+                frames
+                    .send(Queued::Frame(Pending {
+                        arrival,
+                        order: conditioner.next_order(),
+                        channel,
+                        frame,
+                    }))
+                    .map_err(|_| FrameError::Closed)
+            }
+        }
+    }
+
+    fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        match &mut self.inner {
+            Conditioned::Direct(inner) => inner.set_compression_threshold(threshold),
+            Conditioned::Delayed { frames, .. } => {
+                let _ = frames.send(Queued::CompressionThreshold(threshold));
+            }
+        }
+    }
+
+    /// Waits for every frame still held back to be delivered, then closes the inner writer.
+    async fn close(self) -> Result<(), FrameError> {
+        match self.inner {
+            Conditioned::Direct(inner) => inner.close().await,
+            Conditioned::Delayed {
+                frames, finished, ..
+            } => {
+                drop(frames);
+                let _ = finished.await;
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::ServerMessage;
+
+/// Why the server could not do what a client asked, sent in `ServerMessage::Error`.
+///
+/// Variants are only ever appended, so a code keeps its meaning across protocol versions.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The request names a player that is not on the server.
+    UnknownPlayer,
+    /// Reserved for a request the server does not handle. No server sends it yet; it stays
+    /// so the codes after it keep their index.
+    Unsupported,
+    /// Reserved for a request naming a player that belongs to another connection. No server
+    /// sends it yet; it stays so the codes after it keep their index.
+    NotYourPlayer,
+    /// The request needs a player, but this connection has not sent a `ConnectionRequest`.
+    NotConnected,
+    /// The client fell behind and some updates about other players were never sent to it.
+    /// Not a reply to any request.
+    MissedUpdates,
+    /// The client sent a frame the server could not decode; the connection is closed after
+    /// this error.
+    Malformed,
+    /// Something went wrong on the server that the client could not have prevented.
+    Internal,
+    /// Another connection resumed this player with its session token. Not a reply to any
+    /// request; the connection is closed after this error.
+    TakenOver,
+    /// The name in a `ConnectionRequest` cannot be shown to other players.
+    InvalidName,
+    /// A chat message is empty, too long or holds characters that cannot be shown.
+    InvalidMessage,
+    /// A chat message started with `/` but named no command the server has.
+    UnknownCommand,
+    /// The command is for admins only.
+    NotPermitted,
+    /// The command's arguments are missing or make no sense.
+    InvalidArguments,
+    /// An admin removed this player from the server. Not a reply to any request; the
+    /// connection is closed after this error.
+    Kicked,
+    /// The connection sent chat messages, commands or map requests faster than the server
+    /// allows.
+    RateLimited,
+    /// An admin took away this player's right to chat.
+    Muted,
+    /// This player may not join the server. Also sent, before the connection is closed, to a
+    /// player banned while online.
+    Banned,
+    /// The server's chat filter refused the message.
+    Blocked,
+    /// A `MapRequest` asked for too many chunks at once, for chunks beyond the edge of the
+    /// world, or for chunks too far from the player.
+    InvalidChunks,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorCode::UnknownPlayer => "unknown player",
+            ErrorCode::Unsupported => "unsupported request",
+            ErrorCode::NotYourPlayer => "not your player",
+            ErrorCode::NotConnected => "not connected",
+            ErrorCode::MissedUpdates => "missed updates",
+            ErrorCode::Malformed => "malformed message",
+            ErrorCode::Internal => "internal server error",
+            ErrorCode::TakenOver => "taken over by another connection",
+            ErrorCode::InvalidName => "invalid name",
+            ErrorCode::InvalidMessage => "invalid message",
+            ErrorCode::UnknownCommand => "unknown command",
+            ErrorCode::NotPermitted => "not permitted",
+            ErrorCode::InvalidArguments => "invalid arguments",
+            ErrorCode::Kicked => "kicked",
+            ErrorCode::RateLimited => "rate limited",
+            ErrorCode::Muted => "muted",
+            ErrorCode::Banned => "banned",
+            ErrorCode::Blocked => "blocked",
+            ErrorCode::InvalidChunks => "invalid chunks",
+        })
+    }
+}
+
+/// A failed request as the server reports it and the client receives it.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+#[error("{code}: {message}")]
+pub struct RequestError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RequestError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<RequestError> for ServerMessage {
+    fn from(error: RequestError) -> Self {
+        ServerMessage::Error {
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::io;
+
+use bincode::error::{DecodeError, EncodeError};
+use lz4_flex::block::DecompressError;
+use serde::{Serialize, de::DeserializeOwned};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{Channel, MessageReader, MessageWriter, compress_frame, decompress_frame};
+
+/// Largest frame accepted when no explicit `FrameConfig` is given.
+pub const DEFAULT_MAX_FRAME_LEN: u32 = 8 * 1024 * 1024;
+
+/// Most memory decoding one message may claim. Strings and lists declare their own length,
+/// so without this a tiny frame could ask for terabytes. Decoded messages take several times
+/// the room of their encoding, so this is well above `DEFAULT_MAX_FRAME_LEN`.
+const DECODE_LIMIT: usize = 8 * DEFAULT_MAX_FRAME_LEN as usize;
+
+/// Set in a stream frame's length prefix when the payload is compressed, see
+/// `MessageWriter::set_compression_threshold`.
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameConfig {
+    /// Frames whose length prefix exceeds this are rejected before anything is allocated.
+    /// Applies to compressed frames both before and after decompression. The top bit of the
+    /// prefix is the compression flag, so stream frames can never exceed 2 GiB regardless.
+    pub max_frame_len: u32,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FrameError {
+    #[error("connection closed")]
+    Closed,
+    #[error("frame of {len} bytes exceeds the {max} byte limit")]
+    TooLarge { len: u64, max: u32 },
+    #[error("undecodable frame: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("frame has {0} trailing bytes after the message")]
+    TrailingBytes(usize),
+    #[error("could not decompress frame: {0}")]
+    Decompress(#[from] DecompressError),
+    #[error("could not encode frame: {0}")]
+    Encode(#[from] EncodeError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl FrameError {
+    /// True when the peer sent something that is not a valid frame. The stream can no longer
+    /// be trusted to be aligned on a frame boundary, so the connection should be dropped.
+    pub fn is_malformed(&self) -> bool {
+        matches!(
+            self,
+            FrameError::TooLarge { .. }
+                | FrameError::Decode(_)
+                | FrameError::TrailingBytes(_)
+                | FrameError::Decompress(_)
+        )
+    }
+}
+
+pub async fn send_message<T: Serialize>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    msg: &T,
+) -> Result<(), FrameError> {
+    send_message_with(writer, msg, &FrameConfig::default()).await
+}
+
+pub async fn send_message_with<T: Serialize>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    msg: &T,
+    config: &FrameConfig,
+) -> Result<(), FrameError> {
+    write_frame(writer, &encode_message(msg)?, false, config).await
+}
+
+pub async fn read_message<T: DeserializeOwned>(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<T, FrameError> {
+    read_message_with(reader, &FrameConfig::default()).await
+}
+
+pub async fn read_message_with<T: DeserializeOwned>(
+    reader: &mut (impl AsyncRead + Unpin),
+    config: &FrameConfig,
+) -> Result<T, FrameError> {
+    decode_message(&read_frame(reader, config).await?)
+}
+
+async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    frame: &[u8],
+    compressed: bool,
+    config: &FrameConfig,
+) -> Result<(), FrameError> {
+    check_frame_len(frame.len(), config)?;
+    let mut len = frame.len() as u32;
+    if compressed {
+        len |= COMPRESSED_FLAG;
+    }
+
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(frame).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+    config: &FrameConfig,
+) -> Result<Vec<u8>, FrameError> {
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf).await.map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            FrameError::Closed
+        } else {
+            FrameError::Io(e)
+        }
+    })?;
+
+    let prefix = u32::from_be_bytes(len_buf);
+    let msg_len = prefix & !COMPRESSED_FLAG;
+    check_frame_len(msg_len as usize, config)?;
+
+    let mut msg_buf = vec![0u8; msg_len as usize];
+    reader.read_exact(&mut msg_buf).await?;
+
+    if prefix & COMPRESSED_FLAG != 0 {
+        return decompress_frame(&msg_buf, config);
+    }
+    Ok(msg_buf)
+}
+
+pub(crate) fn check_frame_len(len: usize, config: &FrameConfig) -> Result<(), FrameError> {
+    if len as u64 > config.max_frame_len as u64 {
+        return Err(FrameError::TooLarge {
+            len: len as u64,
+            max: config.max_frame_len,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn encode_message<T: Serialize>(msg: &T) -> Result<Vec<u8>, FrameError> {
+    Ok(bincode::serde::encode_to_vec(
+        msg,
+        bincode::config::standard(),
+    )?)
+}
+
+pub(crate) fn decode_message<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FrameError> {
+    let config = bincode::config::standard().with_limit::<DECODE_LIMIT>();
+    let (msg, read): (T, usize) = bincode::serde::decode_from_slice(bytes, config)?;
+    if read != bytes.len() {
+        return Err(FrameError::TrailingBytes(bytes.len() - read));
+    }
+
+    Ok(msg)
+}
+
+/// Reading half of a length-prefixed byte stream, see `MessageReader`.
+pub struct FrameReader<R> {
+    inner: R,
+    config: FrameConfig,
+}
+
+impl<R: AsyncRead + Unpin + Send> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_config(inner, FrameConfig::default())
+    }
+
+    pub fn with_config(inner: R, config: FrameConfig) -> Self {
+        Self { inner, config }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> MessageReader for FrameReader<R> {
+    async fn recv_frame(&mut self) -> Result<Vec<u8>, FrameError> {
+        read_frame(&mut self.inner, &self.config).await
+    }
+}
+
+/// Writing half of a length-prefixed byte stream, see `MessageWriter`. Streams are already
+/// reliable and ordered, so the frame's `Channel` is ignored.
+pub struct FrameWriter<W> {
+    inner: W,
+    config: FrameConfig,
+    compression_threshold: Option<usize>,
+}
+
+impl<W: AsyncWrite + Unpin + Send> FrameWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_config(inner, FrameConfig::default())
+    }
+
+    pub fn with_config(inner: W, config: FrameConfig) -> Self {
+        Self {
+            inner,
+            config,
+            compression_threshold: None,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> MessageWriter for FrameWriter<W> {
+    async fn send_frame(&mut self, _channel: Channel, frame: Vec<u8>) -> Result<(), FrameError> {
+        let (compressed, frame) = compress_frame(frame, self.compression_threshold);
+        write_frame(&mut self.inner, &frame, compressed, &self.config).await
+    }
+
+    fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Shuts down the write side of the stream, so the peer reads `FrameError::Closed`.
+    async fn close(mut self) -> Result<(), FrameError> {
+        self.inner.shutdown().await?;
+        Ok(())
+    }
+}
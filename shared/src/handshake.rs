@@ -0,0 +1,198 @@
+use std::fmt;
+
+use crate::{
+    Channel, DEFAULT_COMPRESSION_THRESHOLD, FrameError, MessageChannel, MessageReader,
+    MessageWriter,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Version of the `ClientMessage`/`ServerMessage` wire format. Bump this whenever a change
+/// would make an older peer mis-decode frames; `tests/fixtures/README.md` lists which
+/// changes do.
+pub const PROTOCOL_VERSION: u32 = 12;
+
+/// Leading bytes of every `ClientHello` so the server can tell a handshake apart from a
+/// pre-handshake client that starts straight away with a `ClientMessage`.
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"ISOG";
+
+/// Optional protocol features a peer supports. Both sides advertise their set and the
+/// connection uses the intersection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Frames above `DEFAULT_COMPRESSION_THRESHOLD` bytes may be LZ4-compressed.
+    pub const COMPRESSION: Self = Self(1 << 0);
+    /// Everything this build supports.
+    pub const ALL: Self = Self::COMPRESSION;
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+/// First frame sent by the client on a new connection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ClientHello {
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub capabilities: Capabilities,
+}
+
+impl ClientHello {
+    pub fn new(capabilities: Capabilities) -> Self {
+        Self {
+            magic: PROTOCOL_MAGIC,
+            version: PROTOCOL_VERSION,
+            capabilities,
+        }
+    }
+}
+
+/// The server's answer to a `ClientHello`. No `ServerMessage` is sent before an `Accepted`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ServerHello {
+    Accepted {
+        version: u32,
+        capabilities: Capabilities,
+    },
+    Rejected(HandshakeRejection),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeRejection {
+    VersionMismatch { server: u32, client: u32 },
+    BadMagic,
+}
+
+impl fmt::Display for HandshakeRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeRejection::VersionMismatch { server, client } if client < server => write!(
+                f,
+                "this client speaks protocol v{client} but the server requires v{server}, please update the game"
+            ),
+            HandshakeRejection::VersionMismatch { server, client } => write!(
+                f,
+                "this client speaks protocol v{client} but the server is still on v{server}"
+            ),
+            HandshakeRejection::BadMagic => write!(f, "peer did not start with a game handshake"),
+        }
+    }
+}
+
+impl MessageChannel for ClientHello {
+    fn channel(&self) -> Channel {
+        Channel::ReliableOrdered
+    }
+}
+
+impl MessageChannel for ServerHello {
+    fn channel(&self) -> Channel {
+        Channel::ReliableOrdered
+    }
+}
+
+/// What both sides agreed on once the handshake succeeds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub capabilities: Capabilities,
+}
+
+impl Negotiated {
+    /// Switches on whatever the writer needs for the agreed capabilities.
+    fn apply(&self, writer: &mut impl MessageWriter) {
+        if self.capabilities.contains(Capabilities::COMPRESSION) {
+            writer.set_compression_threshold(Some(DEFAULT_COMPRESSION_THRESHOLD));
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HandshakeError {
+    /// The server refused us; the rejection is meant to be shown to the player.
+    #[error("server refused the connection: {0}")]
+    Rejected(HandshakeRejection),
+    /// We refused the peer (server side) after sending it the rejection.
+    #[error("refused client: {0}")]
+    Refused(HandshakeRejection),
+    #[error("handshake failed: {0}")]
+    Frame(#[from] FrameError),
+}
+
+pub async fn client_handshake(
+    reader: &mut impl MessageReader,
+    writer: &mut impl MessageWriter,
+    hello: ClientHello,
+) -> Result<Negotiated, HandshakeError> {
+    writer.send(&hello).await?;
+
+    match reader.recv::<ServerHello>().await? {
+        ServerHello::Accepted {
+            version,
+            capabilities,
+        } => {
+            let negotiated = Negotiated {
+                version,
+                capabilities: capabilities.intersection(hello.capabilities),
+            };
+            negotiated.apply(writer);
+            Ok(negotiated)
+        }
+        ServerHello::Rejected(rejection) => Err(HandshakeError::Rejected(rejection)),
+    }
+}
+
+pub async fn server_handshake(
+    reader: &mut impl MessageReader,
+    writer: &mut impl MessageWriter,
+    capabilities: Capabilities,
+) -> Result<Negotiated, HandshakeError> {
+    let rejection = match reader.recv::<ClientHello>().await {
+        Ok(hello) if hello.magic != PROTOCOL_MAGIC => HandshakeRejection::BadMagic,
+        Ok(hello) if hello.version != PROTOCOL_VERSION => HandshakeRejection::VersionMismatch {
+            server: PROTOCOL_VERSION,
+            client: hello.version,
+        },
+        Ok(hello) => {
+            let negotiated = Negotiated {
+                version: PROTOCOL_VERSION,
+                capabilities: capabilities.intersection(hello.capabilities),
+            };
+            writer
+                .send(&ServerHello::Accepted {
+                    version: negotiated.version,
+                    capabilities: negotiated.capabilities,
+                })
+                .await?;
+            negotiated.apply(writer);
+            return Ok(negotiated);
+        }
+        Err(e) if e.is_malformed() => HandshakeRejection::BadMagic,
+        Err(e) => return Err(e.into()),
+    };
+
+    writer
+        .send(&ServerHello::Rejected(rejection.clone()))
+        .await?;
+    Err(HandshakeError::Refused(rejection))
+}
@@ -1,55 +1,115 @@
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize};
 mod map;
 pub use map::*;
 mod player;
 pub use player::*;
-use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::tcp::{OwnedReadHalf, OwnedWriteHalf}};
-use anyhow::{Result};
+mod handshake;
+pub use handshake::*;
+mod frame;
+pub use frame::*;
+mod compression;
+pub use compression::*;
+mod transport;
+pub use transport::*;
+mod udp;
+pub use udp::*;
+mod conditioner;
+pub use conditioner::*;
+mod request;
+pub use request::*;
+mod error;
+pub use error::*;
+mod snapshot;
+pub use snapshot::*;
+mod session;
+pub use session::*;
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum ClientMessage {
+    /// Sends a chat message. The server only replies if it refuses it; otherwise the
+    /// message comes back as a `ServerMessage::Message` like everyone else's.
+    ///
+    /// A message starting with `/` runs a server command instead, answered with
+    /// `ServerMessage::CommandOutput`.
     MessageRequest(PlayerMessage),
-    MapRequest(String),
-    ConnectionRequest,
-    MoveRequest{
-        player: String,
-        direction: [f32;3],
+    /// Asks for the terrain of these chunks, at most `MAX_CHUNKS_PER_REQUEST` of them and all
+    /// inside `MAX_CHUNK_COORD`. Answered with `ServerMessage::Map`.
+    MapRequest(Vec<ChunkCoord>),
+    /// Joins the game. With the token from an earlier `ServerMessage::Session` the server
+    /// resumes that player, otherwise it creates a new one.
+    /// `name` is shown to other players and must pass `check_name`.
+    ConnectionRequest {
+        token: Option<SessionToken>,
+        name: String,
+    },
+    /// Moves the sender's own player; the server knows which one from the connection.
+    MoveRequest {
+        direction: [f32; 3],
     },
     Disconnect,
+    /// The client has applied the `ServerMessage::Snapshot` for this tick.
+    SnapshotAck {
+        tick: u32,
+    },
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum ServerMessage {
-    Map(TileManager),
+    /// Chunks of terrain, in answer to a `MapRequest`, or the chunks around the player in
+    /// answer to a `ConnectionRequest`.
+    Map(Vec<Chunk>),
+    /// The client's own player, in answer to its `ConnectionRequest`. Later changes arrive
+    /// in snapshots.
     Player(Player),
-    OtherPlayer(Player),
+    /// Changes to every player since a snapshot the client acknowledged.
+    Snapshot(Snapshot),
+    /// A chat message relayed from a player.
     Message(PlayerMessage),
+    /// The player with this id left the game.
     Disconnect(String),
+    /// The request this replies to could not be carried out.
+    Error { code: ErrorCode, message: String },
+    /// Every other player already in the game, sent after `Map` in answer to a
+    /// `ConnectionRequest`. Later changes arrive in snapshots.
+    Roster(Vec<Player>),
+    /// The token to present in a later `ConnectionRequest` to get the same player back, sent
+    /// right after `Player`.
+    Session(SessionToken),
+    /// The server is going down for the given reason. It closes the connection right after.
+    Shutdown(String),
+    /// What a command run from chat printed, in reply to its `MessageRequest`.
+    CommandOutput(String),
 }
 
-
-pub async fn send_message<T: Serialize>(writer: &mut OwnedWriteHalf, msg: &T) -> Result<()> {
-    let bytes = bincode::serde::encode_to_vec(msg, bincode::config::standard())?;
-    let len = (bytes.len() as u32).to_be_bytes();
-
-    writer.write_all(&len).await?;
-    writer.write_all(&bytes).await?;
-    writer.flush().await?;
-
-    Ok(())
+impl ClientMessage {
+    /// True for requests the server always answers, so the client can wait for the reply and
+    /// retry when none comes. Moves are superseded by the next one and never retried.
+    pub fn expects_reply(&self) -> bool {
+        matches!(
+            self,
+            ClientMessage::ConnectionRequest { .. } | ClientMessage::MapRequest(_)
+        )
+    }
 }
 
-pub async fn read_message<T: DeserializeOwned>(reader: &mut OwnedReadHalf) -> Result<T> {
-    let mut len_buf = [0u8;4];
-    reader.read_exact(&mut len_buf).await?;
-
-    let msg_len = u32::from_be_bytes(len_buf) as usize;
-
-    let mut msg_buf = vec![0u8; msg_len];
-    reader.read_exact(&mut msg_buf).await?;
-
-    let (msg, _): (T, usize) = 
-        bincode::serde::decode_from_slice(&msg_buf, bincode::config::standard())?; 
+impl MessageChannel for ClientMessage {
+    fn channel(&self) -> Channel {
+        match self {
+            ClientMessage::MoveRequest { .. } | ClientMessage::SnapshotAck { .. } => {
+                Channel::UnreliableSequenced
+            }
+            _ => Channel::ReliableOrdered,
+        }
+    }
+}
 
-    Ok(msg)
-}
\ No newline at end of file
+impl MessageChannel for ServerMessage {
+    fn channel(&self) -> Channel {
+        match self {
+            // Lost snapshots are made up for by the next one, which is encoded against whatever
+            // the client last acknowledged.
+            ServerMessage::Snapshot(_) => Channel::UnreliableSequenced,
+            _ => Channel::ReliableOrdered,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Tile, TileType, WorldGen, generate_heightmap};
+
+/// Width and depth of a chunk, in tiles.
+pub const CHUNK_SIZE: i64 = 16;
+
+/// Chunks further than this from the origin along either axis are beyond the edge of the
+/// world. Positions are `f32`, which stops resolving a player's steps not far past it.
+pub const MAX_CHUNK_COORD: i64 = 1024;
+
+/// The most chunks one `ClientMessage::MapRequest` may ask for.
+pub const MAX_CHUNKS_PER_REQUEST: usize = 64;
+
+/// A chunk's place in the grid of chunks. The chunk `[cx, cy]` holds the tile columns from
+/// `[cx, cy] * CHUNK_SIZE` up to, but not including, `[cx + 1, cy + 1] * CHUNK_SIZE`.
+pub type ChunkCoord = [i64; 2];
+
+/// The chunk holding the tile column `column`.
+pub fn chunk_of(column: [i64; 2]) -> ChunkCoord {
+    column.map(|c| c.div_euclid(CHUNK_SIZE))
+}
+
+/// The chunk holding `position`.
+pub fn chunk_at(position: [f32; 3]) -> ChunkCoord {
+    chunk_of([position[0].floor() as i64, position[1].floor() as i64])
+}
+
+/// Whether `coord` is inside the edge of the world.
+pub fn chunk_in_bounds(coord: ChunkCoord) -> bool {
+    coord.iter().all(|c| c.abs() <= MAX_CHUNK_COORD)
+}
+
+/// Whether `coord` is at most `radius` chunks from `center` along both axes.
+pub fn chunk_within(center: ChunkCoord, radius: i64, coord: ChunkCoord) -> bool {
+    (coord[0] - center[0]).abs() <= radius && (coord[1] - center[1]).abs() <= radius
+}
+
+/// Every chunk inside the world within `radius` chunks of `center` along both axes, nearest
+/// first.
+pub fn chunks_around(center: ChunkCoord, radius: i64) -> Vec<ChunkCoord> {
+    let mut coords: Vec<ChunkCoord> = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| [center[0] + dx, center[1] + dy]))
+        .filter(|&coord| chunk_in_bounds(coord))
+        .collect();
+    coords.sort_by_key(|&[x, y]| {
+        let (dx, dy) = (x - center[0], y - center[1]);
+        (dx * dx + dy * dy, y, x)
+    });
+    coords
+}
+
+/// One square of terrain, `CHUNK_SIZE` tiles on a side.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub coord: ChunkCoord,
+    pub tiles: BTreeMap<(i64, i64, i64), Tile>,
+}
+
+impl Chunk {
+    /// The terrain of `coord` as `settings` describe it. The same settings always give the
+    /// same chunk.
+    pub fn generate(coord: ChunkCoord, settings: &WorldGen) -> Self {
+        let scale = 0.25;
+        let origin = coord.map(|c| c * CHUNK_SIZE);
+        let tiles = generate_heightmap(&origin, CHUNK_SIZE, settings)
+            .into_iter()
+            .map(|((x, y), z)| ((x, y, z), Tile::new([x, y, z], TileType::GrassBlock, scale)))
+            .collect();
+        Self { coord, tiles }
+    }
+}
@@ -1,27 +1,52 @@
-use std::{
-    collections::HashMap,
-    sync::{LazyLock, RwLock},
-};
+use std::collections::HashMap;
 
-use anyhow::{Result, anyhow};
 use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
 
-static PERLIN: LazyLock<RwLock<Perlin>> = LazyLock::new(|| RwLock::new(Perlin::new(0)));
+/// Settings terrain is generated from. The same settings always give the same terrain.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WorldGen {
+    /// Seed of the Perlin noise the heights are taken from.
+    pub seed: u32,
+    /// How far apart neighbouring tiles sample the noise. Smaller values give smoother
+    /// terrain.
+    pub noise_scale: f64,
+    /// Number of height levels, so tiles are between 0 and `levels - 1` high.
+    pub levels: u8,
+}
+
+impl Default for WorldGen {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            noise_scale: 0.025,
+            levels: 5,
+        }
+    }
+}
 
-pub fn generate_heightmap(postion: &[i64; 2], size: u8) -> Result<HashMap<(i64, i64), i64>> {
-    let scale = 0.025;
+/// The height of every tile column in the `size` by `size` square whose lowest corner is
+/// `position`.
+pub fn generate_heightmap(
+    position: &[i64; 2],
+    size: i64,
+    settings: &WorldGen,
+) -> HashMap<(i64, i64), i64> {
+    let perlin = Perlin::new(settings.seed);
+    let top = settings.levels.saturating_sub(1) as i64;
 
     let mut height_map: HashMap<(i64, i64), i64> = HashMap::new();
-    for y in -(size as i64)..=size as i64 {
-        for x in -(size as i64)..=size as i64 {
-            let pos = [(x + postion[0]), (y + postion[1])];
-            let noise = PERLIN
-                .read()
-                .map_err(|e| anyhow!("Could not get PERLIN to read: {e}"))?
-                .get([pos[0] as f64 * scale, pos[1] as f64 * scale]);
-            height_map.insert((pos[0], pos[1]), (((noise + 1.0) * 0.5) * 5.0) as i64);
+    for y in 0..size {
+        for x in 0..size {
+            let pos = [(x + position[0]), (y + position[1])];
+            let noise = perlin.get([
+                pos[0] as f64 * settings.noise_scale,
+                pos[1] as f64 * settings.noise_scale,
+            ]);
+            let height = (((noise + 1.0) * 0.5) * settings.levels as f64) as i64;
+            height_map.insert((pos[0], pos[1]), height.clamp(0, top));
         }
     }
 
-    Ok(height_map)
+    height_map
 }
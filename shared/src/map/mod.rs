@@ -1,3 +1,9 @@
+mod chunk;
+pub use chunk::*;
+
+mod streaming;
+pub use streaming::*;
+
 mod tile_manager;
 pub use tile_manager::*;
 
@@ -0,0 +1,169 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+use crate::{Chunk, ChunkCoord, MAX_CHUNKS_PER_REQUEST, chunk_at, chunk_within, chunks_around};
+
+/// Chunks a client keeps in every direction around the player, when no radius is configured.
+pub const DEFAULT_VIEW_RADIUS: i64 = 2;
+
+/// The largest view radius a client may use. A radius of `r` keeps `(2r + 1)²` chunks loaded.
+pub const MAX_VIEW_RADIUS: i64 = 8;
+
+/// How long a forgotten chunk waits before it is asked for again, so a failing request is
+/// not repeated every frame.
+pub const CHUNK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Decides which chunks a client should have as its player moves: which to ask the server for,
+/// which received chunk to build next, and which to drop once they fall out of view.
+///
+/// It only deals in chunks and coordinates, so whatever draws the chunks keeps its own
+/// resources for the ones `next_to_build` hands out and frees them for the ones `move_to`
+/// unloads.
+#[derive(Debug)]
+pub struct ChunkStreamer {
+    view_radius: i64,
+    center: Option<ChunkCoord>,
+    /// Asked for, but not received yet.
+    requested: BTreeSet<ChunkCoord>,
+    /// Forgotten, and not to be asked for again before the given time.
+    retry_at: BTreeMap<ChunkCoord, Instant>,
+    /// Received, but not built yet.
+    received: BTreeMap<ChunkCoord, Chunk>,
+    /// Handed out by `next_to_build`, and not unloaded since.
+    loaded: BTreeSet<ChunkCoord>,
+}
+
+impl ChunkStreamer {
+    /// Keeps the chunks within `view_radius` chunks of the player along both axes, clamped to
+    /// `0..=MAX_VIEW_RADIUS`.
+    pub fn new(view_radius: i64) -> Self {
+        Self {
+            view_radius: view_radius.clamp(0, MAX_VIEW_RADIUS),
+            center: None,
+            requested: BTreeSet::new(),
+            retry_at: BTreeMap::new(),
+            received: BTreeMap::new(),
+            loaded: BTreeSet::new(),
+        }
+    }
+
+    pub fn view_radius(&self) -> i64 {
+        self.view_radius
+    }
+
+    /// The chunk the view is centred on, once `move_to` has been called.
+    pub fn center(&self) -> Option<ChunkCoord> {
+        self.center
+    }
+
+    /// Whether `coord` is within the view radius of the centre. Before the first `move_to`
+    /// nothing is.
+    pub fn in_view(&self, coord: ChunkCoord) -> bool {
+        self.center
+            .is_some_and(|center| chunk_within(center, self.view_radius, coord))
+    }
+
+    /// Centres the view on the chunk holding `position`. Returns the loaded chunks that are now
+    /// out of view, which the caller should free; chunks out of view that were only requested
+    /// or received are forgotten.
+    pub fn move_to(&mut self, position: [f32; 3]) -> Vec<ChunkCoord> {
+        let center = chunk_at(position);
+        if self.center == Some(center) {
+            return Vec::new();
+        }
+        self.center = Some(center);
+
+        let radius = self.view_radius;
+        self.requested
+            .retain(|&coord| chunk_within(center, radius, coord));
+        self.retry_at
+            .retain(|&coord, _| chunk_within(center, radius, coord));
+        self.received
+            .retain(|&coord, _| chunk_within(center, radius, coord));
+
+        let unloaded: Vec<ChunkCoord> = self
+            .loaded
+            .iter()
+            .copied()
+            .filter(|&coord| !chunk_within(center, radius, coord))
+            .collect();
+        self.loaded
+            .retain(|&coord| chunk_within(center, radius, coord));
+        unloaded
+    }
+
+    /// The chunks in view that are not loaded, received or asked for yet, nearest first and at
+    /// most `MAX_CHUNKS_PER_REQUEST` of them. They count as asked for from then on.
+    pub fn to_request(&mut self) -> Vec<ChunkCoord> {
+        self.to_request_at(Instant::now())
+    }
+
+    /// Like `to_request`, as if it were `now`. Forgotten chunks are left out until their
+    /// `CHUNK_RETRY_DELAY` is over.
+    pub fn to_request_at(&mut self, now: Instant) -> Vec<ChunkCoord> {
+        let Some(center) = self.center else {
+            return Vec::new();
+        };
+        self.retry_at.retain(|_, &mut at| at > now);
+        let missing: Vec<ChunkCoord> = chunks_around(center, self.view_radius)
+            .into_iter()
+            .filter(|coord| {
+                !self.loaded.contains(coord)
+                    && !self.received.contains_key(coord)
+                    && !self.requested.contains(coord)
+                    && !self.retry_at.contains_key(coord)
+            })
+            .take(MAX_CHUNKS_PER_REQUEST)
+            .collect();
+        self.requested.extend(&missing);
+        missing
+    }
+
+    /// Takes chunks the server sent, whether asked for or not. Chunks out of view, or already
+    /// loaded, are dropped. Until the first `move_to` every chunk is kept, since the server
+    /// sends the chunks around a player before the client knows where it is.
+    pub fn receive(&mut self, chunks: impl IntoIterator<Item = Chunk>) {
+        for chunk in chunks {
+            let coord = chunk.coord;
+            self.requested.remove(&coord);
+            if self.loaded.contains(&coord) || (self.center.is_some() && !self.in_view(coord)) {
+                continue;
+            }
+            self.received.insert(coord, chunk);
+        }
+    }
+
+    /// Forgets that `coords` were asked for at `now`, so `to_request` asks for them again
+    /// once `CHUNK_RETRY_DELAY` has passed. For requests that failed or went unanswered.
+    pub fn forget(&mut self, coords: &[ChunkCoord], now: Instant) {
+        for &coord in coords {
+            if self.requested.remove(&coord) {
+                self.retry_at.insert(coord, now + CHUNK_RETRY_DELAY);
+            }
+        }
+    }
+
+    /// The received chunk nearest the centre, which counts as loaded from then on. Building
+    /// a few of these per frame spreads the work of a new stretch of terrain over frames.
+    pub fn next_to_build(&mut self) -> Option<Chunk> {
+        let center = self.center.unwrap_or_default();
+        let coord = *self.received.keys().min_by_key(|&&[x, y]| {
+            let (dx, dy) = (x - center[0], y - center[1]);
+            (dx * dx + dy * dy, y, x)
+        })?;
+        let chunk = self.received.remove(&coord)?;
+        self.loaded.insert(coord);
+        Some(chunk)
+    }
+
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.loaded.contains(&coord)
+    }
+
+    /// Every loaded chunk.
+    pub fn loaded(&self) -> impl Iterator<Item = ChunkCoord> + '_ {
+        self.loaded.iter().copied()
+    }
+}
@@ -1,14 +1,14 @@
 use serde::{Deserialize, Serialize};
 
 
-#[derive(Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum TileType {
     GrassBlock,
     GrassSlopeL,
     GrassSlopeR,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Tile {
     tile_type: TileType,
     world_position: [i64; 3],
@@ -2,40 +2,65 @@ use std::collections::BTreeMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{TileType, generate_heightmap, map::Tile};
+use crate::{Chunk, ChunkCoord, Tile, WorldGen, chunk_in_bounds, chunk_of};
 
-
-#[derive(Serialize, Deserialize, Clone)]
+/// The chunks of terrain known so far. The world has no end short of `MAX_CHUNK_COORD`, so
+/// chunks are only generated, or received, once they are needed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct TileManager {
-    pub tiles: BTreeMap<(i64, i64, i64), Tile>,
-    pub position: [i64; 2],
-    pub size: u8,
+    pub chunks: BTreeMap<ChunkCoord, Chunk>,
 }
 
 impl TileManager {
-    pub fn new(position: [i64; 2]) -> Self {
-        let scale = 0.25;
-        let size: u8 = 4;
-        let mut tiles:BTreeMap<(i64, i64, i64), Tile> = BTreeMap::new();
-        
-        if let Ok(height_map) = generate_heightmap(&position, size) {
-            height_map.iter().for_each(|((x,y), z)| {
-                let tile = Tile::new([*x, *y, *z], TileType::GrassBlock, scale);
-                tiles.insert((*x, *y, *z), tile);
-            });
-        } else {
-            for y in -(size as i64)..=size as i64 {
-                for x in -(size as i64)..=size as i64 {
-                    let tile = Tile::new([x, y, 0], TileType::GrassBlock, scale);
-                    tiles.insert((x, y, 0), tile);
-                }
-            }
-        }
-        
-        Self {
-            tiles: tiles,
-            position,
-            size,
+    /// A map with no chunks yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The chunk at `coord`, generated from `settings` first if this is the first time it is
+    /// needed. `None` beyond the edge of the world.
+    pub fn chunk(&mut self, coord: ChunkCoord, settings: &WorldGen) -> Option<&Chunk> {
+        if !chunk_in_bounds(coord) {
+            return None;
         }
+        Some(
+            self.chunks
+                .entry(coord)
+                .or_insert_with(|| Chunk::generate(coord, settings)),
+        )
+    }
+
+    /// Keeps `chunk`, replacing any chunk already at its coordinate.
+    pub fn insert(&mut self, chunk: Chunk) {
+        self.chunks.insert(chunk.coord, chunk);
+    }
+
+    /// The tile at `position` among the chunks known so far.
+    pub fn tile(&self, position: [i64; 3]) -> Option<&Tile> {
+        let [x, y, z] = position;
+        self.chunks.get(&chunk_of([x, y]))?.tiles.get(&(x, y, z))
     }
-}
\ No newline at end of file
+
+    /// Whether there is a tile at `position`, generating its chunk if needed.
+    pub fn has_tile(&mut self, position: [i64; 3], settings: &WorldGen) -> bool {
+        let [x, y, z] = position;
+        self.chunk(chunk_of([x, y]), settings)
+            .is_some_and(|chunk| chunk.tiles.contains_key(&(x, y, z)))
+    }
+
+    /// The height of the highest tile in the column `column`, generating its chunk if needed.
+    pub fn ground(&mut self, column: [i64; 2], settings: &WorldGen) -> Option<i64> {
+        let [x, y] = column;
+        let chunk = self.chunk(chunk_of(column), settings)?;
+        chunk
+            .tiles
+            .range((x, y, i64::MIN)..=(x, y, i64::MAX))
+            .map(|(&(_, _, z), _)| z)
+            .next_back()
+    }
+
+    /// Every tile known so far.
+    pub fn tiles(&self) -> impl Iterator<Item = (&(i64, i64, i64), &Tile)> {
+        self.chunks.values().flat_map(|chunk| &chunk.tiles)
+    }
+}
@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 mod player;
 pub use player::*;
\ No newline at end of file
@@ -1,15 +1,72 @@
 use serde::{Deserialize, Serialize};
 
+/// Longest display name a player may choose, in characters.
+pub const MAX_NAME_LEN: usize = 24;
 
-#[derive(Serialize, Deserialize, Clone)]
+/// Longest chat message a player may send, in characters.
+pub const MAX_MESSAGE_LEN: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Player {
     pub id: String,
+    /// Display name chosen by the player's client, see `check_name`.
+    pub name: String,
     pub position: [f32; 3],
     pub speed: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/// Checks `name` can be shown to other players: 1 to `MAX_NAME_LEN` characters, none of
+/// them control characters and not only whitespace. Returns what is wrong with it otherwise.
+pub fn check_name(name: &str) -> Result<(), String> {
+    let len = name.chars().count();
+    if name.trim().is_empty() {
+        Err("a name cannot be empty".to_string())
+    } else if len > MAX_NAME_LEN {
+        Err(format!(
+            "a name can be at most {MAX_NAME_LEN} characters, `{name}` has {len}"
+        ))
+    } else if name.chars().any(char::is_control) {
+        Err("a name cannot contain control characters".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// A chat message. Clients send it in `ClientMessage::MessageRequest` and the server relays
+/// it in `ServerMessage::Message` to everyone `scope` reaches.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PlayerMessage {
-    pub id: String, 
-    pub message: String
-}
\ No newline at end of file
+    /// The sender. The server fills this in from the connection, whatever the client sent.
+    pub id: String,
+    pub scope: ChatScope,
+    pub message: String,
+}
+
+/// Who a chat message reaches. The sender always gets its own message back.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ChatScope {
+    /// Everyone on the server.
+    Global,
+    /// Players within the server's chat radius of the sender.
+    Nearby,
+    /// Only the player with this id.
+    Whisper(String),
+}
+
+/// Checks `message` can be relayed to other players: 1 to `MAX_MESSAGE_LEN` characters, none
+/// of them control characters and not only whitespace. Returns what is wrong with it
+/// otherwise.
+pub fn check_message(message: &str) -> Result<(), String> {
+    let len = message.chars().count();
+    if message.trim().is_empty() {
+        Err("a message cannot be empty".to_string())
+    } else if len > MAX_MESSAGE_LEN {
+        Err(format!(
+            "a message can be at most {MAX_MESSAGE_LEN} characters, this one has {len}"
+        ))
+    } else if message.chars().any(char::is_control) {
+        Err("a message cannot contain control characters".to_string())
+    } else {
+        Ok(())
+    }
+}
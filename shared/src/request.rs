@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Channel, ClientMessage, MessageChannel, ServerMessage};
+
+/// Identifies one client request so the replies to it can be matched up.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequestId(pub u32);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// What a client actually sends: a `ClientMessage` tagged with its request id.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClientEnvelope {
+    pub id: RequestId,
+    pub message: ClientMessage,
+}
+
+/// What a server actually sends. `reply_to` names the request a message answers, and is
+/// `None` for anything the server pushes on its own, like other players moving.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServerEnvelope {
+    pub reply_to: Option<RequestId>,
+    pub message: ServerMessage,
+}
+
+impl ServerEnvelope {
+    pub fn reply(to: RequestId, message: ServerMessage) -> Self {
+        Self {
+            reply_to: Some(to),
+            message,
+        }
+    }
+
+    pub fn push(message: ServerMessage) -> Self {
+        Self {
+            reply_to: None,
+            message,
+        }
+    }
+}
+
+impl MessageChannel for ClientEnvelope {
+    fn channel(&self) -> Channel {
+        self.message.channel()
+    }
+}
+
+impl MessageChannel for ServerEnvelope {
+    fn channel(&self) -> Channel {
+        self.message.channel()
+    }
+}
+
+/// How long the client waits for the first reply to a request before resending it.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How many times a request is sent in total before the client gives up on it.
+pub const DEFAULT_REQUEST_ATTEMPTS: u32 = 3;
+
+/// What `RequestTracker::poll` wants done about a request that went unanswered.
+pub enum Timeout {
+    /// Send this again; it keeps its id, so a late reply to an earlier attempt still counts.
+    Retry(ClientEnvelope),
+    /// Every attempt timed out.
+    GaveUp(RequestId, ClientMessage),
+}
+
+struct PendingRequest {
+    message: ClientMessage,
+    sent: Instant,
+    attempts: u32,
+}
+
+/// Client side bookkeeping for request ids: numbers outgoing messages and remembers the ones
+/// that expect a reply until one arrives, resending them when it does not.
+pub struct RequestTracker {
+    next_id: u32,
+    pending: BTreeMap<RequestId, PendingRequest>,
+    timeout: Duration,
+    max_attempts: u32,
+}
+
+impl Default for RequestTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUEST_TIMEOUT, DEFAULT_REQUEST_ATTEMPTS)
+    }
+}
+
+impl RequestTracker {
+    pub fn new(timeout: Duration, max_attempts: u32) -> Self {
+        Self {
+            next_id: 1,
+            pending: BTreeMap::new(),
+            timeout,
+            max_attempts: max_attempts.max(1),
+        }
+    }
+
+    /// Gives `message` the next id. Messages that expect a reply are tracked until `resolve`
+    /// is called with that id.
+    pub fn send(&mut self, message: ClientMessage, now: Instant) -> ClientEnvelope {
+        let id = RequestId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        if message.expects_reply() {
+            self.pending.insert(
+                id,
+                PendingRequest {
+                    message: message.clone(),
+                    sent: now,
+                    attempts: 1,
+                },
+            );
+        }
+
+        ClientEnvelope { id, message }
+    }
+
+    /// Marks `id` as answered. Returns false if it was not waiting for a reply, for example
+    /// because an earlier reply already resolved it.
+    pub fn resolve(&mut self, id: RequestId) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+
+    pub fn is_pending(&self, id: RequestId) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    /// Checks every unanswered request against the timeout. Call this regularly.
+    pub fn poll(&mut self, now: Instant) -> Vec<Timeout> {
+        let expired: Vec<RequestId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.sent) >= self.timeout)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut timeouts = Vec::new();
+        for id in expired {
+            let Some(pending) = self.pending.get_mut(&id) else {
+                continue;
+            };
+            if pending.attempts >= self.max_attempts {
+                if let Some(pending) = self.pending.remove(&id) {
+                    timeouts.push(Timeout::GaveUp(id, pending.message));
+                }
+            } else {
+                pending.attempts += 1;
+                pending.sent = now;
+                timeouts.push(Timeout::Retry(ClientEnvelope {
+                    id,
+                    message: pending.message.clone(),
+                }));
+            }
+        }
+        timeouts
+    }
+}
@@ -0,0 +1,6 @@
+use serde::{Deserialize, Serialize};
+
+/// Proves a client owns an account. The server hands one out in `ServerMessage::Session` and a
+/// client presenting it in a later `ConnectionRequest` gets the same player back.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SessionToken(pub String);
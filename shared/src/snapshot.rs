@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Player;
+
+/// How many unacknowledged snapshots the server remembers per client. A client that has not
+/// acked any of them gets a full snapshot again.
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+/// Every player's replicated state at one server tick.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SnapshotState {
+    pub tick: u32,
+    pub players: BTreeMap<String, Player>,
+}
+
+/// The fields of one player that changed since the baseline. `None` means unchanged; a
+/// player missing from the baseline has every field set.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlayerDelta {
+    pub id: String,
+    pub name: Option<String>,
+    pub position: Option<[f32; 3]>,
+    pub speed: Option<f32>,
+}
+
+/// A `SnapshotState` encoded against an older one the client already has, or against
+/// nothing when `baseline` is `None`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub baseline: Option<u32>,
+    pub changed: Vec<PlayerDelta>,
+    pub removed: Vec<String>,
+}
+
+impl SnapshotState {
+    /// Encodes `self` as the changes from `baseline`.
+    pub fn delta_from(&self, baseline: Option<&SnapshotState>) -> Snapshot {
+        let empty = BTreeMap::new();
+        let old = baseline.map_or(&empty, |baseline| &baseline.players);
+
+        let changed = self
+            .players
+            .values()
+            .filter_map(|player| {
+                let before = old.get(&player.id);
+                let delta = PlayerDelta {
+                    id: player.id.clone(),
+                    name: before
+                        .is_none_or(|b| b.name != player.name)
+                        .then(|| player.name.clone()),
+                    position: before
+                        .is_none_or(|b| b.position != player.position)
+                        .then_some(player.position),
+                    speed: before
+                        .is_none_or(|b| b.speed != player.speed)
+                        .then_some(player.speed),
+                };
+                let changed =
+                    delta.name.is_some() || delta.position.is_some() || delta.speed.is_some();
+                (before.is_none() || changed).then_some(delta)
+            })
+            .collect();
+        let removed = old
+            .keys()
+            .filter(|id| !self.players.contains_key(*id))
+            .cloned()
+            .collect();
+
+        Snapshot {
+            tick: self.tick,
+            baseline: baseline.map(|baseline| baseline.tick),
+            changed,
+            removed,
+        }
+    }
+}
+
+impl Snapshot {
+    /// True when applying this to its baseline would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Rebuilds the full state from the baseline the snapshot was encoded against. Returns
+    /// `None` if a new player is missing fields, which a well-behaved server never sends.
+    pub fn apply(&self, baseline: Option<&SnapshotState>) -> Option<SnapshotState> {
+        let mut players = baseline.map(|b| b.players.clone()).unwrap_or_default();
+        for id in &self.removed {
+            players.remove(id);
+        }
+        for delta in &self.changed {
+            match players.get_mut(&delta.id) {
+                Some(player) => {
+                    if let Some(name) = &delta.name {
+                        player.name = name.clone();
+                    }
+                    if let Some(position) = delta.position {
+                        player.position = position;
+                    }
+                    if let Some(speed) = delta.speed {
+                        player.speed = speed;
+                    }
+                }
+                None => {
+                    players.insert(
+                        delta.id.clone(),
+                        Player {
+                            id: delta.id.clone(),
+                            name: delta.name.clone()?,
+                            position: delta.position?,
+                            speed: delta.speed?,
+                        },
+                    );
+                }
+            }
+        }
+        Some(SnapshotState {
+            tick: self.tick,
+            players,
+        })
+    }
+}
+
+/// Server side of snapshot replication for one client: remembers what was sent and what the
+/// client acknowledged, and encodes each new state against the newest acked one.
+#[derive(Default)]
+pub struct SnapshotSender {
+    sent: BTreeMap<u32, Arc<SnapshotState>>,
+    acked: Option<Arc<SnapshotState>>,
+}
+
+impl SnapshotSender {
+    /// Records that the client has the snapshot for `tick`. Older or unknown acks are ignored.
+    pub fn ack(&mut self, tick: u32) {
+        if self.acked.as_ref().is_some_and(|acked| acked.tick >= tick) {
+            return;
+        }
+        if let Some(state) = self.sent.remove(&tick) {
+            self.sent = self.sent.split_off(&tick);
+            self.acked = Some(state);
+        }
+    }
+
+    /// The snapshot to send for `state`, or `None` if the client's acked state already matches.
+    pub fn next(&mut self, state: &Arc<SnapshotState>) -> Option<Snapshot> {
+        let snapshot = state.delta_from(self.acked.as_deref());
+        if snapshot.is_empty() && self.acked.is_some() {
+            return None;
+        }
+
+        self.sent.insert(state.tick, state.clone());
+        while self.sent.len() > SNAPSHOT_HISTORY {
+            self.sent.pop_first();
+        }
+        Some(snapshot)
+    }
+}
+
+/// Client side of snapshot replication: applies snapshots to the baselines they were encoded
+/// against and yields the newest state.
+#[derive(Default)]
+pub struct SnapshotReceiver {
+    received: BTreeMap<u32, SnapshotState>,
+}
+
+impl SnapshotReceiver {
+    /// Applies `snapshot`, returning the new state to show and ack. Returns `None` for
+    /// snapshots older than the newest one applied, or whose baseline is unknown.
+    pub fn receive(&mut self, snapshot: &Snapshot) -> Option<&SnapshotState> {
+        if self
+            .received
+            .last_key_value()
+            .is_some_and(|(&latest, _)| latest >= snapshot.tick)
+        {
+            return None;
+        }
+
+        let baseline = match snapshot.baseline {
+            Some(tick) => Some(self.received.get(&tick)?),
+            None => None,
+        };
+        let state = snapshot.apply(baseline)?;
+
+        // The server only moves its baseline forward, so older states are never needed again.
+        if let Some(tick) = snapshot.baseline {
+            self.received = self.received.split_off(&tick);
+        }
+        while self.received.len() >= SNAPSHOT_HISTORY {
+            self.received.pop_first();
+        }
+        Some(self.received.entry(state.tick).or_insert(state))
+    }
+
+    pub fn latest(&self) -> Option<&SnapshotState> {
+        self.received.last_key_value().map(|(_, state)| state)
+    }
+}
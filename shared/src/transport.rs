@@ -0,0 +1,73 @@
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{FrameError, decode_message, encode_message};
+
+/// A bidirectional byte stream the game protocol can run over: a `TcpStream`, a
+/// `UnixStream`, one end of a `tokio::io::duplex` pipe, and so on.
+///
+/// Split it with `tokio::io::split` and wrap the halves in `FrameReader`/`FrameWriter`, or
+/// use `send_message`/`read_message` on them directly.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+/// Delivery guarantee a message needs. Stream transports are always reliable and ordered;
+/// datagram transports honour the distinction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Delivered exactly once and in send order, resent until acknowledged.
+    ReliableOrdered,
+    /// May be lost; anything older than the newest message already delivered is dropped.
+    UnreliableSequenced,
+}
+
+pub trait MessageChannel {
+    fn channel(&self) -> Channel;
+}
+
+/// Receiving side of a connection, independent of the underlying transport. Implementors
+/// only deliver whole encoded frames; `recv` decodes them.
+pub trait MessageReader: Send {
+    fn recv_frame(&mut self) -> impl Future<Output = Result<Vec<u8>, FrameError>> + Send;
+
+    fn recv<T: DeserializeOwned>(&mut self) -> impl Future<Output = Result<T, FrameError>> + Send {
+        async move { decode_message(&self.recv_frame().await?) }
+    }
+}
+
+/// Sending side of a connection, independent of the underlying transport. Implementors only
+/// move encoded frames; `send` encodes a message and picks its `Channel`.
+pub trait MessageWriter: Send {
+    fn send_frame(
+        &mut self,
+        channel: Channel,
+        frame: Vec<u8>,
+    ) -> impl Future<Output = Result<(), FrameError>> + Send;
+
+    fn send<T: Serialize + MessageChannel>(
+        &mut self,
+        msg: &T,
+    ) -> impl Future<Output = Result<(), FrameError>> + Send {
+        let channel = msg.channel();
+        let frame = encode_message(msg);
+        async move { self.send_frame(channel, frame?).await }
+    }
+
+    /// Compresses frames of at least `threshold` bytes from now on; `None` turns compression
+    /// off. Only enable this once the peer has agreed to `Capabilities::COMPRESSION`, which
+    /// the handshake does automatically. Readers accept compressed frames either way.
+    fn set_compression_threshold(&mut self, threshold: Option<usize>);
+
+    /// Closes the connection once everything already sent has left, and waits for that.
+    /// Dropping a writer closes it as well, but without a way to know when it is done.
+    fn close(self) -> impl Future<Output = Result<(), FrameError>> + Send
+    where
+        Self: Sized,
+    {
+        async move {
+            drop(self);
+            Ok(())
+        }
+    }
+}
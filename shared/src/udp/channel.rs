@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{FrameError, udp::packet::MAX_FRAGMENT_LEN};
+
+/// How far ahead of the next expected sequence number fragments are buffered.
+const RECEIVE_WINDOW: u32 = 4096;
+
+/// Fragments are only sent within this many of the oldest unacked one. Well inside
+/// `RECEIVE_WINDOW`, so none arrives too far ahead to be kept, and small enough that a burst of
+/// resends fits in a socket's receive buffer.
+const SEND_WINDOW: u32 = 64;
+
+struct Unacked {
+    fragment: Fragment,
+    first_sent: Instant,
+    last_sent: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Fragment {
+    pub seq: u32,
+    pub last: bool,
+    pub compressed: bool,
+    pub payload: Vec<u8>,
+}
+
+/// Sending half of the reliable-ordered channel: numbers fragments and keeps them until acked.
+#[derive(Default)]
+pub(crate) struct ReliableSender {
+    next_seq: u32,
+    /// The oldest fragment sent and not yet acked, or the next to be sent if there is none.
+    window_start: u32,
+    unacked: BTreeMap<u32, Unacked>,
+    /// Fragments waiting for the window to move on before they are first sent.
+    queued: VecDeque<Fragment>,
+}
+
+impl ReliableSender {
+    /// Splits `message` into fragments and returns those that fit in the window for sending,
+    /// recording them as in flight. The rest are returned by `ack` once there is room.
+    pub(crate) fn push(&mut self, message: &[u8], compressed: bool, now: Instant) -> Vec<Fragment> {
+        let mut chunks: Vec<&[u8]> = message.chunks(MAX_FRAGMENT_LEN).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let count = chunks.len();
+
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            self.queued.push_back(Fragment {
+                seq: self.next_seq,
+                last: i + 1 == count,
+                compressed,
+                payload: chunk.to_vec(),
+            });
+            self.next_seq = self.next_seq.wrapping_add(1);
+        }
+        self.release(now)
+    }
+
+    /// Records that the peer has `seq` and returns the queued fragments this makes room for.
+    pub(crate) fn ack(&mut self, seq: u32, now: Instant) -> Vec<Fragment> {
+        if self.unacked.remove(&seq).is_none() {
+            return Vec::new();
+        }
+        let next_unsent = self
+            .queued
+            .front()
+            .map_or(self.next_seq, |fragment| fragment.seq);
+        while self.window_start != next_unsent && !self.unacked.contains_key(&self.window_start) {
+            self.window_start = self.window_start.wrapping_add(1);
+        }
+        self.release(now)
+    }
+
+    /// Moves the queued fragments within `SEND_WINDOW` of `window_start` in flight.
+    fn release(&mut self, now: Instant) -> Vec<Fragment> {
+        let mut released = Vec::new();
+        while let Some(fragment) = self.queued.pop_front() {
+            if fragment.seq.wrapping_sub(self.window_start) >= SEND_WINDOW {
+                self.queued.push_front(fragment);
+                break;
+            }
+            self.unacked.insert(
+                fragment.seq,
+                Unacked {
+                    fragment: fragment.clone(),
+                    first_sent: now,
+                    last_sent: now,
+                },
+            );
+            released.push(fragment);
+        }
+        released
+    }
+
+    /// Fragments that have waited `resend_interval` without an ack.
+    pub(crate) fn due(&mut self, now: Instant, resend_interval: Duration) -> Vec<Fragment> {
+        self.unacked
+            .values_mut()
+            .filter(|unacked| now.duration_since(unacked.last_sent) >= resend_interval)
+            .map(|unacked| {
+                unacked.last_sent = now;
+                unacked.fragment.clone()
+            })
+            .collect()
+    }
+
+    /// When the oldest fragment still waiting for an ack was first sent. Fragments are only
+    /// queued while others are in flight, so `None` means everything was delivered.
+    pub(crate) fn oldest_unacked(&self) -> Option<Instant> {
+        self.unacked
+            .values()
+            .map(|unacked| unacked.first_sent)
+            .min()
+    }
+}
+
+/// Receiving half of the reliable-ordered channel: drops duplicates, reorders and reassembles.
+pub(crate) struct ReliableReceiver {
+    next_expected: u32,
+    pending: BTreeMap<u32, Fragment>,
+    partial: Vec<u8>,
+    max_message_len: usize,
+}
+
+impl ReliableReceiver {
+    pub(crate) fn new(max_message_len: usize) -> Self {
+        Self {
+            next_expected: 0,
+            pending: BTreeMap::new(),
+            partial: Vec::new(),
+            max_message_len,
+        }
+    }
+
+    /// Whether `receive` keeps the fragment `seq`, or already delivered it. Only those may be
+    /// acked: fragments too far ahead are dropped and have to be sent again.
+    pub(crate) fn should_ack(&self, seq: u32) -> bool {
+        let ahead = seq.wrapping_sub(self.next_expected);
+        // Sequence numbers wrap, so those more than halfway round are behind.
+        let delivered = ahead > u32::MAX / 2;
+        delivered || ahead < RECEIVE_WINDOW
+    }
+
+    /// Accepts one fragment and returns every message it completes, in order, each with its
+    /// compressed flag.
+    pub(crate) fn receive(
+        &mut self,
+        fragment: Fragment,
+    ) -> Result<Vec<(bool, Vec<u8>)>, FrameError> {
+        let ahead = fragment.seq.wrapping_sub(self.next_expected);
+        if ahead < RECEIVE_WINDOW {
+            self.pending.entry(fragment.seq).or_insert(fragment);
+        }
+
+        let mut messages = Vec::new();
+        while let Some(fragment) = self.pending.remove(&self.next_expected) {
+            self.next_expected = self.next_expected.wrapping_add(1);
+            self.partial.extend_from_slice(&fragment.payload);
+            if self.partial.len() > self.max_message_len {
+                return Err(FrameError::TooLarge {
+                    len: self.partial.len() as u64,
+                    max: self.max_message_len as u32,
+                });
+            }
+            if fragment.last {
+                messages.push((fragment.compressed, std::mem::take(&mut self.partial)));
+            }
+        }
+
+        Ok(messages)
+    }
+}
+
+/// Receiving half of the unreliable-sequenced channel.
+#[derive(Default)]
+pub(crate) struct SequencedReceiver {
+    latest: Option<u32>,
+}
+
+impl SequencedReceiver {
+    /// True if `seq` is newer than anything delivered so far.
+    pub(crate) fn accept(&mut self, seq: u32) -> bool {
+        let newer = match self.latest {
+            None => true,
+            Some(latest) => seq.wrapping_sub(latest).wrapping_sub(1) < u32::MAX / 2,
+        };
+        if newer {
+            self.latest = Some(seq);
+        }
+        newer
+    }
+}
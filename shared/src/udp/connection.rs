@@ -0,0 +1,529 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket, lookup_host},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        oneshot,
+    },
+    time::{MissedTickBehavior, interval, sleep_until, timeout},
+};
+
+use crate::{
+    Channel, FrameConfig, FrameError, LinkConditioner, LinkConditions, MessageReader,
+    MessageWriter, PROTOCOL_MAGIC, check_frame_len, compress_frame, decompress_frame,
+    udp::{
+        channel::{Fragment, ReliableReceiver, ReliableSender, SequencedReceiver},
+        packet::{MAX_DATAGRAM_LEN, MAX_FRAGMENT_LEN, Packet},
+    },
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct UdpConfig {
+    /// Limit on reassembled incoming messages, as for `FrameReader`.
+    pub frame: FrameConfig,
+    /// Limit on outgoing messages, as for `FrameWriter`. Kept apart from `frame` so that a peer
+    /// that only expects small messages can still send large ones.
+    pub send_frame: FrameConfig,
+    /// How long a reliable fragment waits for its ack before being sent again.
+    pub resend_interval: Duration,
+    /// An idle connection sends a heartbeat this often so the peer does not time it out.
+    pub heartbeat_interval: Duration,
+    /// The connection is closed when nothing is heard from the peer, or a reliable fragment
+    /// stays unacknowledged, for this long.
+    pub timeout: Duration,
+    /// How long `UdpConnection::connect` waits for the server to accept.
+    pub connect_timeout: Duration,
+    /// Simulated network conditions applied to every outgoing datagram, for testing.
+    pub conditions: LinkConditions,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            frame: FrameConfig::default(),
+            send_frame: FrameConfig::default(),
+            resend_interval: Duration::from_millis(100),
+            heartbeat_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            conditions: LinkConditions::default(),
+        }
+    }
+}
+
+/// One peer on a UDP socket, offering a reliable-ordered and an unreliable-sequenced channel.
+/// Each message picks its channel through `MessageChannel`.
+pub struct UdpConnection {
+    reader: UdpReader,
+    writer: UdpWriter,
+    peer: SocketAddr,
+}
+
+impl UdpConnection {
+    pub async fn connect(addr: impl ToSocketAddrs, config: UdpConfig) -> io::Result<Self> {
+        let peer = lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address did not resolve"))?;
+        let local: SocketAddr = if peer.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+
+        let socket = Arc::new(UdpSocket::bind(local).await?);
+        socket.connect(peer).await?;
+
+        let hello = Packet::Connect {
+            magic: PROTOCOL_MAGIC,
+        }
+        .encode()
+        .map_err(io::Error::other)?;
+        let deadline = Instant::now() + config.connect_timeout;
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        loop {
+            socket.send(&hello).await?;
+            match timeout(config.resend_interval * 2, socket.recv(&mut buf)).await {
+                Ok(Ok(len)) if Packet::decode(&buf[..len]) == Some(Packet::Accept) => break,
+                // Connection refused and the like are expected until the server is up.
+                Ok(_) | Err(_) => {}
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no answer from {peer}"),
+                ));
+            }
+        }
+
+        let (connection, datagrams) = spawn_connection(socket.clone(), peer, config);
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+            loop {
+                tokio::select! {
+                    received = socket.recv(&mut buf) => match received {
+                        Ok(len) => {
+                            if datagrams.send(buf[..len].to_vec()).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+                        Err(_) => break,
+                    },
+                    _ = datagrams.closed() => break,
+                }
+            }
+        });
+
+        Ok(connection)
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn into_split(self) -> (UdpReader, UdpWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+/// Accepts `UdpConnection`s from many peers sharing one server socket.
+pub struct UdpListener {
+    accepted: UnboundedReceiver<UdpConnection>,
+    local_addr: SocketAddr,
+}
+
+impl UdpListener {
+    pub async fn bind(addr: impl ToSocketAddrs, config: UdpConfig) -> io::Result<Self> {
+        let socket = Arc::new(UdpSocket::bind(addr).await?);
+        let local_addr = socket.local_addr()?;
+        let (accepted_tx, accepted) = unbounded_channel();
+
+        tokio::spawn(demultiplex(socket, config, accepted_tx));
+
+        Ok(Self {
+            accepted,
+            local_addr,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub async fn accept(&mut self) -> io::Result<(UdpConnection, SocketAddr)> {
+        match self.accepted.recv().await {
+            Some(connection) => {
+                let peer = connection.peer;
+                Ok((connection, peer))
+            }
+            None => Err(io::Error::other("UDP socket closed")),
+        }
+    }
+}
+
+/// Routes datagrams on the shared server socket to the connection for their source address,
+/// opening a new connection for every valid `Connect` from an unknown address.
+async fn demultiplex(
+    socket: Arc<UdpSocket>,
+    config: UdpConfig,
+    accepted: UnboundedSender<UdpConnection>,
+) {
+    let mut peers: HashMap<SocketAddr, UnboundedSender<Vec<u8>>> = HashMap::new();
+    let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+
+    loop {
+        let (len, addr) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                println!("UDP receive failed: {e}");
+                continue;
+            }
+        };
+        let mut datagram = buf[..len].to_vec();
+
+        if let Some(peer) = peers.get(&addr) {
+            match peer.send(datagram) {
+                Ok(()) => continue,
+                Err(returned) => {
+                    peers.remove(&addr);
+                    datagram = returned.0;
+                }
+            }
+        }
+
+        let is_connect = matches!(
+            Packet::decode(&datagram),
+            Some(Packet::Connect { magic }) if magic == PROTOCOL_MAGIC
+        );
+        if !is_connect {
+            continue;
+        }
+
+        let (connection, datagrams) = spawn_connection(socket.clone(), addr, config);
+        let _ = datagrams.send(datagram);
+        peers.insert(addr, datagrams);
+        if accepted.send(connection).is_err() {
+            break;
+        }
+    }
+}
+
+/// Starts the driver task for one peer. The returned sender feeds it raw datagrams.
+fn spawn_connection(
+    socket: Arc<UdpSocket>,
+    peer: SocketAddr,
+    config: UdpConfig,
+) -> (UdpConnection, UnboundedSender<Vec<u8>>) {
+    let (datagrams_tx, datagrams_rx) = unbounded_channel();
+    let (outgoing_tx, outgoing_rx) = unbounded_channel();
+    let (delivered_tx, delivered_rx) = unbounded_channel();
+    let (finished_tx, finished_rx) = oneshot::channel();
+
+    let driver = Driver {
+        socket,
+        peer,
+        config,
+        reliable_out: ReliableSender::default(),
+        reliable_in: ReliableReceiver::new(config.frame.max_frame_len as usize),
+        unreliable_seq: 0,
+        unreliable_in: SequencedReceiver::default(),
+        last_heard: Instant::now(),
+        last_sent: Instant::now(),
+        conditioner: LinkConditioner::new(config.conditions),
+    };
+    tokio::spawn(driver.run(datagrams_rx, outgoing_rx, delivered_tx, finished_tx));
+
+    let connection = UdpConnection {
+        reader: UdpReader {
+            delivered: delivered_rx,
+        },
+        writer: UdpWriter {
+            outgoing: outgoing_tx,
+            frame: config.send_frame,
+            compression_threshold: None,
+            finished: finished_rx,
+        },
+        peer,
+    };
+    (connection, datagrams_tx)
+}
+
+pub struct UdpReader {
+    delivered: UnboundedReceiver<Result<Vec<u8>, FrameError>>,
+}
+
+impl MessageReader for UdpReader {
+    async fn recv_frame(&mut self) -> Result<Vec<u8>, FrameError> {
+        self.delivered
+            .recv()
+            .await
+            .unwrap_or(Err(FrameError::Closed))
+    }
+}
+
+/// Dropping the writer closes the connection and tells the peer, once everything sent on the
+/// reliable channel has been acknowledged.
+pub struct UdpWriter {
+    outgoing: UnboundedSender<Outgoing>,
+    frame: FrameConfig,
+    compression_threshold: Option<usize>,
+    /// Resolves, with an error, once the driver has stopped.
+    finished: oneshot::Receiver<()>,
+}
+
+impl MessageWriter for UdpWriter {
+    async fn send_frame(&mut self, channel: Channel, frame: Vec<u8>) -> Result<(), FrameError> {
+        check_frame_len(frame.len(), &self.frame)?;
+        let (compressed, frame) = compress_frame(frame, self.compression_threshold);
+        self.outgoing
+            .send(Outgoing {
+                channel,
+                compressed,
+                bytes: frame,
+            })
+            .map_err(|_| FrameError::Closed)
+    }
+
+    fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Waits until every reliable message is acknowledged and the peer was told, or until the
+    /// peer times out.
+    async fn close(self) -> Result<(), FrameError> {
+        drop(self.outgoing);
+        let _ = self.finished.await;
+        Ok(())
+    }
+}
+
+/// A message on its way from a `UdpWriter` to the driver.
+struct Outgoing {
+    channel: Channel,
+    compressed: bool,
+    bytes: Vec<u8>,
+}
+
+/// Owns the per-peer protocol state: sequence numbers, acks, resends and timeouts.
+struct Driver {
+    socket: Arc<UdpSocket>,
+    peer: SocketAddr,
+    config: UdpConfig,
+    reliable_out: ReliableSender,
+    reliable_in: ReliableReceiver,
+    unreliable_seq: u32,
+    unreliable_in: SequencedReceiver,
+    last_heard: Instant,
+    last_sent: Instant,
+    conditioner: LinkConditioner,
+}
+
+impl Driver {
+    async fn run(
+        mut self,
+        mut datagrams: UnboundedReceiver<Vec<u8>>,
+        mut outgoing: UnboundedReceiver<Outgoing>,
+        delivered: UnboundedSender<Result<Vec<u8>, FrameError>>,
+        // Dropped when the driver stops, which is what `UdpWriter::close` waits for.
+        _finished: oneshot::Sender<()>,
+    ) {
+        let mut tick = interval(self.config.resend_interval);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Set once the writer is dropped. Reliable messages already sent are still resent
+        // until acked, so closing right after a send does not lose it.
+        let mut closing = false;
+
+        loop {
+            if closing && self.reliable_out.oldest_unacked().is_none() {
+                self.transmit(&Packet::Disconnect).await;
+                break;
+            }
+
+            tokio::select! {
+                datagram = datagrams.recv() => {
+                    let Some(datagram) = datagram else {
+                        break;
+                    };
+                    if !self.handle_datagram(&datagram, &delivered).await {
+                        break;
+                    }
+                }
+                msg = outgoing.recv(), if !closing => {
+                    match msg {
+                        Some(msg) => self.send(msg).await,
+                        None => closing = true,
+                    }
+                }
+                _ = tick.tick() => {
+                    let now = Instant::now();
+                    let stalled = self
+                        .reliable_out
+                        .oldest_unacked()
+                        .is_some_and(|sent| now.duration_since(sent) > self.config.timeout);
+                    if stalled || now.duration_since(self.last_heard) > self.config.timeout {
+                        let _ = delivered.send(Err(FrameError::Io(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "peer stopped responding",
+                        ))));
+                        break;
+                    }
+
+                    for fragment in self.reliable_out.due(now, self.config.resend_interval) {
+                        self.transmit_fragment(fragment).await;
+                    }
+                    if now.duration_since(self.last_sent) >= self.config.heartbeat_interval {
+                        self.transmit(&Packet::Heartbeat).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns false once the connection is finished.
+    async fn handle_datagram(
+        &mut self,
+        datagram: &[u8],
+        delivered: &UnboundedSender<Result<Vec<u8>, FrameError>>,
+    ) -> bool {
+        let Some(packet) = Packet::decode(datagram) else {
+            return true;
+        };
+        self.last_heard = Instant::now();
+
+        match packet {
+            Packet::Connect { .. } => self.transmit(&Packet::Accept).await,
+            Packet::Reliable {
+                seq,
+                last,
+                compressed,
+                payload,
+            } => {
+                if self.reliable_in.should_ack(seq) {
+                    self.transmit(&Packet::Ack { seq }).await;
+                }
+                let fragment = Fragment {
+                    seq,
+                    last,
+                    compressed,
+                    payload,
+                };
+                let messages = self.reliable_in.receive(fragment).and_then(|messages| {
+                    messages
+                        .into_iter()
+                        .map(|(compressed, message)| self.unpack(compressed, message))
+                        .collect::<Result<Vec<_>, _>>()
+                });
+                match messages {
+                    Ok(messages) => {
+                        for message in messages {
+                            let _ = delivered.send(Ok(message));
+                        }
+                    }
+                    Err(e) => {
+                        let _ = delivered.send(Err(e));
+                        self.transmit(&Packet::Disconnect).await;
+                        return false;
+                    }
+                }
+            }
+            Packet::Unreliable {
+                seq,
+                compressed,
+                payload,
+            } => {
+                if self.unreliable_in.accept(seq) {
+                    let _ = delivered.send(self.unpack(compressed, payload));
+                }
+            }
+            Packet::Ack { seq } => {
+                for fragment in self.reliable_out.ack(seq, Instant::now()) {
+                    self.transmit_fragment(fragment).await;
+                }
+            }
+            Packet::Disconnect => return false,
+            Packet::Accept | Packet::Heartbeat => {}
+        }
+        true
+    }
+
+    /// Turns a received message back into the frame its sender passed to `send_frame`.
+    fn unpack(&self, compressed: bool, message: Vec<u8>) -> Result<Vec<u8>, FrameError> {
+        if compressed {
+            decompress_frame(&message, &self.config.frame)
+        } else {
+            Ok(message)
+        }
+    }
+
+    async fn send(&mut self, msg: Outgoing) {
+        let Outgoing {
+            channel,
+            compressed,
+            bytes,
+        } = msg;
+        match channel {
+            Channel::UnreliableSequenced if bytes.len() <= MAX_FRAGMENT_LEN => {
+                let seq = self.unreliable_seq;
+                self.unreliable_seq = self.unreliable_seq.wrapping_add(1);
+                self.transmit(&Packet::Unreliable {
+                    seq,
+                    compressed,
+                    payload: bytes,
+                })
+                .await;
+            }
+            // Unreliable messages too big for one datagram fall back to the reliable channel.
+            _ => {
+                for fragment in self.reliable_out.push(&bytes, compressed, Instant::now()) {
+                    self.transmit_fragment(fragment).await;
+                }
+            }
+        }
+    }
+
+    async fn transmit_fragment(&mut self, fragment: Fragment) {
+        self.transmit(&Packet::Reliable {
+            seq: fragment.seq,
+            last: fragment.last,
+            compressed: fragment.compressed,
+            payload: fragment.payload,
+        })
+        .await;
+    }
+
+    async fn transmit(&mut self, packet: &Packet) {
+        self.last_sent = Instant::now();
+
+        let bytes = match packet.encode() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Could not encode packet for {}: {e}", self.peer);
+                return;
+            }
+        };
+
+        if !self.config.conditions.is_perfect() {
+            let Some(arrival) = self.conditioner.schedule(bytes.len(), false) else {
+                return;
+            };
+            let (socket, peer) = (self.socket.clone(), self.peer);
+            tokio::spawn(async move {
+                sleep_until(arrival.into()).await;
+                let _ = socket.send_to(&bytes, peer).await;
+            });
+            return;
+        }
+
+        if let Err(e) = self.socket.send_to(&bytes, self.peer).await {
+            println!("UDP send to {} failed: {e}", self.peer);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+mod packet;
+
+mod channel;
+
+mod connection;
+pub use connection::*;
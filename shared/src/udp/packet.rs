@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::FrameError;
+
+/// Largest message slice carried by one datagram, keeping packets under a typical path MTU.
+pub(crate) const MAX_FRAGMENT_LEN: usize = 1024;
+
+/// Largest datagram we ever expect to receive.
+pub(crate) const MAX_DATAGRAM_LEN: usize = 2048;
+
+/// Everything that travels in a single UDP datagram.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub(crate) enum Packet {
+    /// Sent by the client until the server answers with `Accept`.
+    Connect {
+        magic: [u8; 4],
+    },
+    Accept,
+    /// One fragment of a reliable-ordered message. `last` marks the final fragment;
+    /// `compressed` is set on every fragment of a message that was compressed as a whole.
+    Reliable {
+        seq: u32,
+        last: bool,
+        compressed: bool,
+        payload: Vec<u8>,
+    },
+    /// A whole unreliable-sequenced message.
+    Unreliable {
+        seq: u32,
+        compressed: bool,
+        payload: Vec<u8>,
+    },
+    Ack {
+        seq: u32,
+    },
+    /// Keeps an idle connection from timing out.
+    Heartbeat,
+    Disconnect,
+}
+
+impl Packet {
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, FrameError> {
+        Ok(bincode::serde::encode_to_vec(
+            self,
+            bincode::config::standard(),
+        )?)
+    }
+
+    /// Returns `None` for anything that is not a well-formed packet; stray datagrams are ignored.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let config = bincode::config::standard().with_limit::<MAX_DATAGRAM_LEN>();
+        match bincode::serde::decode_from_slice(bytes, config) {
+            Ok((packet, read)) if read == bytes.len() => Some(packet),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use shared::{
+    CHUNK_SIZE, Chunk, MAX_CHUNK_COORD, TileManager, WorldGen, chunk_at, chunk_of, chunks_around,
+};
+
+#[test]
+fn columns_belong_to_the_chunk_below_them() {
+    assert_eq!(chunk_of([0, 0]), [0, 0]);
+    assert_eq!(chunk_of([CHUNK_SIZE - 1, CHUNK_SIZE]), [0, 1]);
+    assert_eq!(chunk_of([-1, -CHUNK_SIZE]), [-1, -1]);
+    assert_eq!(chunk_of([-CHUNK_SIZE - 1, 0]), [-2, 0]);
+    assert_eq!(chunk_at([-0.5, 15.9, 3.0]), [-1, 0]);
+}
+
+#[test]
+fn chunks_around_come_nearest_first_and_stop_at_the_edge() {
+    let around = chunks_around([0, 0], 1);
+    assert_eq!(around.len(), 9);
+    assert_eq!(around[0], [0, 0]);
+    assert!(around[1..5].iter().all(|[x, y]| x.abs() + y.abs() == 1));
+
+    let edge = chunks_around([MAX_CHUNK_COORD, 0], 1);
+    assert_eq!(edge.len(), 6);
+    assert!(edge.iter().all(|[x, _]| *x <= MAX_CHUNK_COORD));
+}
+
+#[test]
+fn chunks_hold_one_tile_per_column_of_their_square() {
+    let settings = WorldGen::default();
+    let chunk = Chunk::generate([-2, 3], &settings);
+
+    assert_eq!(chunk.tiles.len(), (CHUNK_SIZE * CHUNK_SIZE) as usize);
+    assert!(
+        chunk
+            .tiles
+            .keys()
+            .all(|&(x, y, _)| chunk_of([x, y]) == [-2, 3])
+    );
+    assert_eq!(chunk, Chunk::generate([-2, 3], &settings));
+}
+
+#[test]
+fn neighbouring_chunks_line_up_with_one_big_generation() {
+    // Chunks are cut out of the same noise, so the terrain runs on across their borders.
+    let settings = WorldGen {
+        seed: 9,
+        ..WorldGen::default()
+    };
+    let mut map = TileManager::new();
+    let left = map.ground([CHUNK_SIZE - 1, 4], &settings).unwrap();
+    let right = map.ground([CHUNK_SIZE, 4], &settings).unwrap();
+    assert_eq!(map.chunks.len(), 2);
+    assert!((left - right).abs() <= 1, "{left} next to {right}");
+}
+
+#[test]
+fn the_map_only_generates_chunks_inside_the_world() {
+    let settings = WorldGen::default();
+    let mut map = TileManager::new();
+
+    assert!(map.chunk([MAX_CHUNK_COORD + 1, 0], &settings).is_none());
+    assert!(
+        map.ground([(MAX_CHUNK_COORD + 1) * CHUNK_SIZE, 0], &settings)
+            .is_none()
+    );
+    assert!(map.chunks.is_empty());
+
+    let z = map.ground([5, 5], &settings).unwrap();
+    assert!(map.has_tile([5, 5, z], &settings));
+    assert!(map.tile([5, 5, z]).is_some());
+    assert_eq!(map.chunks.len(), 1);
+}
@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use shared::{
+    Capabilities, Chunk, ClientHello, ClientMessage, DEFAULT_COMPRESSION_THRESHOLD, FrameConfig,
+    FrameError, FrameReader, FrameWriter, MessageReader, MessageWriter, ServerMessage, UdpConfig,
+    UdpConnection, UdpListener, WorldGen, chunks_around, client_handshake, read_message,
+    server_handshake,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream, duplex, split},
+    time::timeout,
+};
+
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+fn map() -> ServerMessage {
+    let chunks = chunks_around([0, 0], 1)
+        .into_iter()
+        .map(|coord| Chunk::generate(coord, &WorldGen::default()))
+        .collect();
+    ServerMessage::Map(chunks)
+}
+
+/// Reads one raw frame off the wire, returning its length prefix and payload.
+async fn raw_frame(stream: &mut DuplexStream) -> (u32, Vec<u8>) {
+    let prefix = stream.read_u32().await.unwrap();
+    let mut payload = vec![0u8; (prefix & !COMPRESSED_FLAG) as usize];
+    stream.read_exact(&mut payload).await.unwrap();
+    (prefix, payload)
+}
+
+#[tokio::test]
+async fn large_frames_are_compressed_on_the_wire() {
+    let (a, mut b) = duplex(1024 * 1024);
+    let mut writer = FrameWriter::new(a);
+    writer.set_compression_threshold(Some(DEFAULT_COMPRESSION_THRESHOLD));
+
+    writer.send(&map()).await.unwrap();
+    let request = ClientMessage::ConnectionRequest {
+        token: None,
+        name: "tester".into(),
+    };
+    writer.send(&request).await.unwrap();
+
+    let (prefix, payload) = raw_frame(&mut b).await;
+    assert_ne!(prefix & COMPRESSED_FLAG, 0, "map was not compressed");
+    let raw = bincode::serde::encode_to_vec(map(), bincode::config::standard()).unwrap();
+    assert!(payload.len() < raw.len());
+
+    let (prefix, _) = raw_frame(&mut b).await;
+    assert_eq!(prefix & COMPRESSED_FLAG, 0, "tiny frame was compressed");
+}
+
+#[tokio::test]
+async fn compressed_frames_decode_to_the_original_message() {
+    let (a, b) = duplex(1024 * 1024);
+    let mut writer = FrameWriter::new(a);
+    let mut reader = FrameReader::new(b);
+    writer.set_compression_threshold(Some(0));
+
+    writer.send(&map()).await.unwrap();
+    writer
+        .send(&ClientMessage::MapRequest(vec![[0, 0]; 2_000]))
+        .await
+        .unwrap();
+
+    assert_eq!(reader.recv::<ServerMessage>().await.unwrap(), map());
+    match reader.recv::<ClientMessage>().await.unwrap() {
+        ClientMessage::MapRequest(chunks) => assert_eq!(chunks, vec![[0, 0]; 2_000]),
+        _ => panic!("unexpected message"),
+    }
+}
+
+#[tokio::test]
+async fn decompressed_size_is_checked_against_the_frame_limit() {
+    let (mut a, b) = duplex(1024);
+    let mut reader = FrameReader::with_config(
+        b,
+        FrameConfig {
+            max_frame_len: 1024,
+        },
+    );
+
+    // A tiny compressed frame announcing a 1 GiB payload.
+    let payload = [0x00, 0x00, 0x00, 0x40, 0x00];
+    a.write_u32(payload.len() as u32 | COMPRESSED_FLAG)
+        .await
+        .unwrap();
+    a.write_all(&payload).await.unwrap();
+
+    let err = reader.recv::<ClientMessage>().await.err().unwrap();
+    assert!(
+        matches!(err, FrameError::TooLarge { max: 1024, .. }),
+        "{err}"
+    );
+    assert!(err.is_malformed());
+}
+
+#[tokio::test]
+async fn corrupt_compressed_frame_is_malformed() {
+    let (mut a, b) = duplex(1024);
+    let mut reader = FrameReader::new(b);
+
+    let payload = [0x10, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF];
+    a.write_u32(payload.len() as u32 | COMPRESSED_FLAG)
+        .await
+        .unwrap();
+    a.write_all(&payload).await.unwrap();
+
+    let err = reader.recv::<ClientMessage>().await.err().unwrap();
+    assert!(err.is_malformed(), "{err}");
+}
+
+/// Runs the handshake over a duplex pipe, then sends a map from the server and returns its
+/// raw length prefix as seen by the client.
+async fn map_prefix_after_handshake(client: Capabilities, server: Capabilities) -> u32 {
+    let (client_end, server_end) = duplex(1024 * 1024);
+    let (server_read, server_write) = split(server_end);
+    let (mut client_read, client_write) = split(client_end);
+
+    let server = tokio::spawn(async move {
+        let mut reader = FrameReader::new(server_read);
+        let mut writer = FrameWriter::new(server_write);
+        server_handshake(&mut reader, &mut writer, server)
+            .await
+            .unwrap();
+        writer.send(&map()).await.unwrap();
+    });
+
+    let mut reader = FrameReader::new(&mut client_read);
+    let mut writer = FrameWriter::new(client_write);
+    client_handshake(&mut reader, &mut writer, ClientHello::new(client))
+        .await
+        .unwrap();
+    server.await.unwrap();
+
+    client_read.read_u32().await.unwrap()
+}
+
+#[tokio::test]
+async fn handshake_enables_compression_only_when_both_sides_agree() {
+    let both = map_prefix_after_handshake(Capabilities::ALL, Capabilities::ALL).await;
+    assert_ne!(both & COMPRESSED_FLAG, 0);
+
+    let old_client = map_prefix_after_handshake(Capabilities::NONE, Capabilities::ALL).await;
+    assert_eq!(old_client & COMPRESSED_FLAG, 0);
+
+    let old_server = map_prefix_after_handshake(Capabilities::ALL, Capabilities::NONE).await;
+    assert_eq!(old_server & COMPRESSED_FLAG, 0);
+}
+
+#[tokio::test]
+async fn compressed_messages_cross_udp() {
+    let mut listener = UdpListener::bind("127.0.0.1:0", UdpConfig::default())
+        .await
+        .unwrap();
+    let client = UdpConnection::connect(listener.local_addr(), UdpConfig::default())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    let (_, mut server_writer) = server.into_split();
+    let (mut client_reader, _client_writer) = client.into_split();
+
+    server_writer.set_compression_threshold(Some(0));
+    server_writer.send(&map()).await.unwrap();
+    server_writer
+        .send(&ServerMessage::Disconnect("bye".into()))
+        .await
+        .unwrap();
+
+    let recv = timeout(
+        Duration::from_secs(5),
+        client_reader.recv::<ServerMessage>(),
+    );
+    assert!(matches!(
+        recv.await.unwrap().unwrap(),
+        ServerMessage::Map(_)
+    ));
+    let recv = timeout(
+        Duration::from_secs(5),
+        client_reader.recv::<ServerMessage>(),
+    );
+    assert!(matches!(
+        recv.await.unwrap().unwrap(),
+        ServerMessage::Disconnect(_)
+    ));
+}
+
+#[tokio::test]
+async fn plain_stream_helpers_still_read_uncompressed_frames() {
+    let (a, mut b) = duplex(1024 * 1024);
+    let mut writer = FrameWriter::new(a);
+
+    writer.send(&map()).await.unwrap();
+
+    assert!(matches!(
+        read_message::<ServerMessage>(&mut b).await.unwrap(),
+        ServerMessage::Map(_)
+    ));
+}
@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+use shared::{
+    ClientMessage, ConditionedWriter, FrameError, FrameReader, FrameWriter, LinkConditioner,
+    LinkConditions, MessageReader, MessageWriter,
+};
+use tokio::{
+    io::{DuplexStream, ReadHalf, WriteHalf, duplex, split},
+    time::timeout,
+};
+
+type Reader = FrameReader<ReadHalf<DuplexStream>>;
+type Writer = ConditionedWriter<FrameWriter<WriteHalf<DuplexStream>>>;
+
+fn link(conditions: LinkConditions) -> (Reader, Writer) {
+    let (client, server) = duplex(1024 * 1024);
+    let (_, writer) = split(client);
+    let (reader, _) = split(server);
+    (
+        FrameReader::new(reader),
+        ConditionedWriter::with_conditioner(
+            FrameWriter::new(writer),
+            LinkConditioner::seeded(conditions, 7),
+        ),
+    )
+}
+
+fn move_request(n: usize) -> ClientMessage {
+    ClientMessage::MoveRequest {
+        direction: [n as f32, 0.0, 0.0],
+    }
+}
+
+#[test]
+fn conditions_parse_from_the_command_line_format() {
+    let conditions: LinkConditions =
+        "latency=150ms, jitter=20, loss=0.05,reorder=0.01,bandwidth=64000"
+            .parse()
+            .unwrap();
+
+    assert_eq!(
+        conditions,
+        LinkConditions {
+            latency: Duration::from_millis(150),
+            jitter: Duration::from_millis(20),
+            loss: 0.05,
+            reorder: 0.01,
+            bandwidth: Some(64_000),
+        }
+    );
+    assert_eq!(
+        conditions.to_string().parse::<LinkConditions>().unwrap(),
+        conditions
+    );
+    assert!("".parse::<LinkConditions>().unwrap().is_perfect());
+    assert!("loss=2".parse::<LinkConditions>().is_err());
+    assert!("ping=5".parse::<LinkConditions>().is_err());
+}
+
+#[tokio::test]
+async fn latency_delays_delivery() {
+    let (mut reader, mut writer) = link(LinkConditions {
+        latency: Duration::from_millis(100),
+        ..LinkConditions::default()
+    });
+
+    let sent = Instant::now();
+    writer.send(&ClientMessage::Disconnect).await.unwrap();
+    reader.recv::<ClientMessage>().await.unwrap();
+
+    assert!(sent.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn closing_waits_for_frames_still_held_back() {
+    let (mut reader, mut writer) = link(LinkConditions {
+        latency: Duration::from_millis(100),
+        ..LinkConditions::default()
+    });
+
+    let sent = Instant::now();
+    writer.send(&move_request(1)).await.unwrap();
+    writer.close().await.unwrap();
+
+    assert!(sent.elapsed() >= Duration::from_millis(100));
+    assert_eq!(
+        reader.recv::<ClientMessage>().await.unwrap(),
+        move_request(1)
+    );
+    assert!(matches!(
+        reader.recv::<ClientMessage>().await,
+        Err(FrameError::Closed)
+    ));
+}
+
+#[tokio::test]
+async fn reliable_messages_survive_loss_and_jitter_in_order() {
+    let (mut reader, mut writer) = link(LinkConditions {
+        latency: Duration::from_millis(5),
+        jitter: Duration::from_millis(5),
+        loss: 0.3,
+        reorder: 0.3,
+        ..LinkConditions::default()
+    });
+
+    for i in 0..50 {
+        writer
+            .send(&ClientMessage::MapRequest(vec![[i, 0]]))
+            .await
+            .unwrap();
+    }
+
+    for i in 0..50 {
+        match timeout(Duration::from_secs(10), reader.recv::<ClientMessage>())
+            .await
+            .unwrap()
+            .unwrap()
+        {
+            ClientMessage::MapRequest(n) => assert_eq!(n, vec![[i, 0]]),
+            _ => panic!("unexpected message"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn unreliable_messages_are_lost_and_reordered() {
+    let (mut reader, mut writer) = link(LinkConditions {
+        jitter: Duration::from_millis(2),
+        loss: 0.3,
+        reorder: 0.3,
+        ..LinkConditions::default()
+    });
+
+    for i in 0..200 {
+        writer.send(&move_request(i)).await.unwrap();
+    }
+
+    let mut received = Vec::new();
+    while let Ok(msg) = timeout(Duration::from_millis(300), reader.recv::<ClientMessage>()).await {
+        if let ClientMessage::MoveRequest { direction, .. } = msg.unwrap() {
+            received.push(direction[0]);
+        }
+    }
+
+    assert!(received.len() < 200, "nothing was lost");
+    assert!(
+        received.windows(2).any(|pair| pair[0] > pair[1]),
+        "nothing was reordered"
+    );
+}
+
+#[tokio::test]
+async fn bandwidth_cap_limits_throughput() {
+    let (mut reader, mut writer) = link(LinkConditions {
+        bandwidth: Some(20_000),
+        ..LinkConditions::default()
+    });
+
+    let sent = Instant::now();
+    for _ in 0..5 {
+        writer
+            .send(&ClientMessage::MapRequest(vec![[0, 0]; 500]))
+            .await
+            .unwrap();
+    }
+    for _ in 0..5 {
+        reader.recv::<ClientMessage>().await.unwrap();
+    }
+
+    // 5 KB at 20 KB/s takes at least a quarter of a second.
+    assert!(sent.elapsed() >= Duration::from_millis(240));
+}
+
+#[tokio::test]
+async fn perfect_conditions_pass_frames_straight_through() {
+    let (mut reader, mut writer) = link(LinkConditions::default());
+
+    writer.send(&move_request(1)).await.unwrap();
+
+    assert!(matches!(
+        reader.recv::<ClientMessage>().await.unwrap(),
+        ClientMessage::MoveRequest { .. }
+    ));
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use shared::{
+    ChatScope, ClientEnvelope, ClientMessage, FrameConfig, FrameError, PlayerMessage, RequestId,
+    read_message, read_message_with, send_message,
+};
+use tokio::{
+    io::{AsyncWriteExt, DuplexStream, duplex},
+    time::timeout,
+};
+
+/// Returns the reading end and the writing end of an in-memory pipe.
+fn pair() -> (DuplexStream, DuplexStream) {
+    duplex(64 * 1024)
+}
+
+/// Small xorshift generator so the fuzz cases are reproducible without extra dependencies.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn valid_frames_round_trip() {
+    let (mut reader, mut writer) = pair();
+
+    send_message(&mut writer, &ClientMessage::MapRequest(vec![[0, 0]]))
+        .await
+        .unwrap();
+
+    match read_message::<ClientMessage>(&mut reader).await.unwrap() {
+        ClientMessage::MapRequest(chunks) => assert_eq!(chunks, [[0, 0]]),
+        _ => panic!("wrong message decoded"),
+    }
+}
+
+#[tokio::test]
+async fn huge_length_prefix_is_rejected_before_allocating() {
+    let (mut reader, mut writer) = pair();
+
+    writer.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+
+    // The top bit is the compression flag, not part of the length.
+    match read_message::<ClientMessage>(&mut reader).await {
+        Err(FrameError::TooLarge { len, .. }) => assert_eq!(len, (u32::MAX >> 1) as u64),
+        other => panic!("expected TooLarge, got {:?}", other.err()),
+    }
+}
+
+#[tokio::test]
+async fn configured_limit_applies_to_valid_messages() {
+    let (mut reader, mut writer) = pair();
+    let config = FrameConfig { max_frame_len: 8 };
+
+    send_message(&mut writer, &ClientMessage::MapRequest(vec![[0, 0]; 16]))
+        .await
+        .unwrap();
+
+    let err = read_message_with::<ClientMessage>(&mut reader, &config)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, FrameError::TooLarge { max: 8, .. }));
+    assert!(err.is_malformed());
+}
+
+#[tokio::test]
+async fn undecodable_payload_is_malformed() {
+    let (mut reader, mut writer) = pair();
+
+    writer.write_all(&2u32.to_be_bytes()).await.unwrap();
+    writer.write_all(&[0xFF, 0xFF]).await.unwrap();
+
+    let err = read_message::<ClientMessage>(&mut reader)
+        .await
+        .err()
+        .unwrap();
+    assert!(err.is_malformed(), "{err}");
+}
+
+#[tokio::test]
+async fn huge_declared_lengths_inside_a_frame_are_refused() {
+    let mut frame = Vec::new();
+    let envelope = ClientEnvelope {
+        id: RequestId(0),
+        message: ClientMessage::MessageRequest(PlayerMessage {
+            id: "Q".into(),
+            scope: ChatScope::Global,
+            message: "hi".into(),
+        }),
+    };
+    send_message(&mut frame, &envelope).await.unwrap();
+
+    // Claim the one-byte sender id is 1 TiB long; the frame itself stays tiny.
+    let at = frame.windows(2).position(|w| w == [1, b'Q']).unwrap();
+    let mut huge = vec![253];
+    huge.extend_from_slice(&(1u64 << 40).to_le_bytes());
+    frame.splice(at..=at, huge);
+    let len = (frame.len() - 4) as u32;
+    frame[..4].copy_from_slice(&len.to_be_bytes());
+
+    let err = read_message::<ClientEnvelope>(&mut frame.as_slice())
+        .await
+        .err()
+        .unwrap();
+    assert!(err.is_malformed(), "{err}");
+}
+
+#[tokio::test]
+async fn closed_connection_is_reported_as_closed() {
+    let (mut reader, writer) = pair();
+    drop(writer);
+
+    assert!(matches!(
+        read_message::<ClientMessage>(&mut reader).await,
+        Err(FrameError::Closed)
+    ));
+}
+
+#[tokio::test]
+async fn random_bytes_never_panic_or_hang() {
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    let config = FrameConfig {
+        max_frame_len: 64 * 1024,
+    };
+
+    for _ in 0..200 {
+        let (mut reader, mut writer) = pair();
+
+        let len = (rng.next() % 512) as usize;
+        let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+        writer.write_all(&bytes).await.unwrap();
+        drop(writer);
+
+        let outcome = timeout(Duration::from_secs(5), async {
+            loop {
+                if let Err(e) = read_message_with::<ClientMessage>(&mut reader, &config).await {
+                    break e;
+                }
+            }
+        })
+        .await
+        .expect("reader hung on random input");
+
+        assert!(
+            outcome.is_malformed() || matches!(outcome, FrameError::Closed | FrameError::Io(_)),
+            "unexpected error {outcome}"
+        );
+    }
+}
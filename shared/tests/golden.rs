@@ -0,0 +1,372 @@
+//! Pins the bincode encoding of everything that goes over the wire against the fixtures in
+//! `tests/fixtures`. See `tests/fixtures/README.md` for when a fixture may change.
+//!
+//! Run with `UPDATE_FIXTURES=1` to rewrite the fixtures from the current encoding.
+
+use std::{collections::BTreeMap, env, fmt::Debug, fs, path::PathBuf};
+
+use bincode::{config, serde::decode_from_slice, serde::encode_to_vec};
+use serde::{Serialize, de::DeserializeOwned};
+use shared::{
+    Capabilities, ChatScope, Chunk, ClientEnvelope, ClientHello, ClientMessage, ErrorCode,
+    HandshakeRejection, PROTOCOL_MAGIC, PROTOCOL_VERSION, Player, PlayerDelta, PlayerMessage,
+    RequestId, ServerEnvelope, ServerHello, ServerMessage, SessionToken, Snapshot, Tile, TileType,
+};
+
+const BYTES_PER_LINE: usize = 32;
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn updating() -> bool {
+    env::var_os("UPDATE_FIXTURES").is_some()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .map(|line| line.iter().map(|b| format!("{b:02x}")).collect::<String>() + "\n")
+        .collect()
+}
+
+fn from_hex(text: &str) -> Vec<u8> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+/// Line diff of two pretty-printed values, with `-` for the fixture and `+` for the value.
+fn diff(fixture: &str, value: &str) -> String {
+    let old: Vec<&str> = fixture.lines().collect();
+    let new: Vec<&str> = value.lines().collect();
+
+    // Longest common subsequence, filled from the end so the walk below runs forwards.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out += &format!("  {}\n", old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("- {}\n", old[i]);
+            i += 1;
+        } else {
+            out += &format!("+ {}\n", new[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Checks that `value` encodes to exactly the bytes in the fixture `name` and that the fixture
+/// decodes back to `value`.
+fn check<T>(name: &str, value: &T)
+where
+    T: Serialize + DeserializeOwned + Debug + PartialEq,
+{
+    let path = fixture_path(&format!("{name}.hex"));
+    let encoded = encode_to_vec(value, config::standard()).unwrap();
+
+    if updating() {
+        fs::write(&path, to_hex(&encoded)).unwrap();
+        return;
+    }
+
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "missing fixture {}: {e}; run with UPDATE_FIXTURES=1 to create it",
+            path.display()
+        )
+    });
+    let fixture = from_hex(&text);
+    if fixture == encoded {
+        let (decoded, _): (T, usize) = decode_from_slice(&fixture, config::standard()).unwrap();
+        assert_eq!(
+            &decoded, value,
+            "fixture {name} decodes to a different value"
+        );
+        return;
+    }
+
+    let old = match decode_from_slice::<T, _>(&fixture, config::standard()) {
+        Ok((decoded, _)) => format!("{decoded:#?}"),
+        Err(e) => format!("<fixture no longer decodes: {e}>"),
+    };
+    panic!(
+        "encoding of {name} changed; older peers will mis-decode it.\n\
+         If this is intended, bump PROTOCOL_VERSION and rerun with UPDATE_FIXTURES=1.\n\n\
+         --- fixture\n+++ current\n{}\n\
+         fixture bytes:\n{}current bytes:\n{}",
+        diff(&old, &format!("{value:#?}")),
+        to_hex(&fixture),
+        to_hex(&encoded),
+    );
+}
+
+fn player() -> Player {
+    Player {
+        id: "3f2b8c1e".into(),
+        name: "Wanderer".into(),
+        position: [1.5, -2.0, 3.0],
+        speed: 0.025,
+    }
+}
+
+fn chunk() -> Chunk {
+    let tiles = [[16, 0, 1], [17, 0, 2], [16, 1, 0]]
+        .into_iter()
+        .map(|[x, y, z]| ((x, y, z), Tile::new([x, y, z], TileType::GrassBlock, 0.25)))
+        .collect::<BTreeMap<_, _>>();
+    Chunk {
+        coord: [1, 0],
+        tiles,
+    }
+}
+
+fn chat() -> PlayerMessage {
+    PlayerMessage {
+        id: "3f2b8c1e".into(),
+        scope: ChatScope::Global,
+        message: "hello".into(),
+    }
+}
+
+fn snapshot() -> Snapshot {
+    Snapshot {
+        tick: 42,
+        baseline: Some(40),
+        changed: vec![
+            PlayerDelta {
+                id: "3f2b8c1e".into(),
+                name: None,
+                position: Some([1.5, -2.0, 3.0]),
+                speed: None,
+            },
+            PlayerDelta {
+                id: "9a0d77e4".into(),
+                name: Some("Newcomer".into()),
+                position: Some([0.0, 0.0, 1.0]),
+                speed: Some(0.025),
+            },
+        ],
+        removed: vec!["51c6e0aa".into()],
+    }
+}
+
+#[test]
+fn fixtures_match_the_protocol_version() {
+    let path = fixture_path("PROTOCOL_VERSION");
+    if updating() {
+        fs::write(&path, format!("{PROTOCOL_VERSION}\n")).unwrap();
+        return;
+    }
+    let pinned: u32 = fs::read_to_string(&path).unwrap().trim().parse().unwrap();
+    assert_eq!(
+        pinned, PROTOCOL_VERSION,
+        "PROTOCOL_VERSION changed; rerun with UPDATE_FIXTURES=1 to re-pin the fixtures"
+    );
+}
+
+#[test]
+fn map_types() {
+    check("tile_type", &TileType::GrassSlopeR);
+    check("tile", &Tile::new([1, -2, 3], TileType::GrassSlopeL, 0.25));
+    check("chunk", &chunk());
+}
+
+#[test]
+fn player_types() {
+    check("player", &player());
+    check("player_message", &chat());
+    check(
+        "player_message_whisper",
+        &PlayerMessage {
+            scope: ChatScope::Whisper("9a0d77e4".into()),
+            ..chat()
+        },
+    );
+    check(
+        "player_message_nearby",
+        &PlayerMessage {
+            scope: ChatScope::Nearby,
+            ..chat()
+        },
+    );
+    check("snapshot", &snapshot());
+}
+
+#[test]
+fn client_messages() {
+    check(
+        "client_message_request",
+        &ClientMessage::MessageRequest(chat()),
+    );
+    check(
+        "client_map_request",
+        &ClientMessage::MapRequest(vec![[0, 0], [-1, 2]]),
+    );
+    check(
+        "client_connection_request",
+        &ClientMessage::ConnectionRequest {
+            token: None,
+            name: "Wanderer".into(),
+        },
+    );
+    check(
+        "client_connection_request_resume",
+        &ClientMessage::ConnectionRequest {
+            token: Some(SessionToken("5c1e0f6a9d2b4e7c".into())),
+            name: "Wanderer".into(),
+        },
+    );
+    check(
+        "client_move_request",
+        &ClientMessage::MoveRequest {
+            direction: [1.0, 0.0, 0.0],
+        },
+    );
+    check("client_disconnect", &ClientMessage::Disconnect);
+    check(
+        "client_snapshot_ack",
+        &ClientMessage::SnapshotAck { tick: 42 },
+    );
+}
+
+#[test]
+fn server_messages() {
+    check("server_map", &ServerMessage::Map(vec![chunk()]));
+    check("server_player", &ServerMessage::Player(player()));
+    check("server_snapshot", &ServerMessage::Snapshot(snapshot()));
+    check("server_message", &ServerMessage::Message(chat()));
+    check(
+        "server_disconnect",
+        &ServerMessage::Disconnect("3f2b8c1e".into()),
+    );
+    check(
+        "server_error",
+        &ServerMessage::Error {
+            code: ErrorCode::NotYourPlayer,
+            message: "player 9a0d77e4 belongs to another client".into(),
+        },
+    );
+    check("server_roster", &ServerMessage::Roster(vec![player()]));
+    check(
+        "server_session",
+        &ServerMessage::Session(SessionToken("5c1e0f6a9d2b4e7c".into())),
+    );
+    check(
+        "server_shutdown",
+        &ServerMessage::Shutdown("the server is restarting".into()),
+    );
+    check(
+        "server_command_output",
+        &ServerMessage::CommandOutput("World seed: 42".into()),
+    );
+}
+
+#[test]
+fn envelopes() {
+    check(
+        "client_envelope",
+        &ClientEnvelope {
+            id: RequestId(300),
+            message: ClientMessage::ConnectionRequest {
+                token: None,
+                name: "Wanderer".into(),
+            },
+        },
+    );
+    check(
+        "server_envelope_reply",
+        &ServerEnvelope::reply(RequestId(300), ServerMessage::Player(player())),
+    );
+    check(
+        "server_envelope_push",
+        &ServerEnvelope::push(ServerMessage::Disconnect("3f2b8c1e".into())),
+    );
+}
+
+#[test]
+fn handshake() {
+    // Peers of every version must read each other's hellos to tell a mismatch apart from
+    // garbage, so these pin literal versions rather than `PROTOCOL_VERSION`.
+    check(
+        "client_hello",
+        &ClientHello {
+            magic: PROTOCOL_MAGIC,
+            version: 12,
+            capabilities: Capabilities::COMPRESSION,
+        },
+    );
+    check(
+        "server_hello_accepted",
+        &ServerHello::Accepted {
+            version: 12,
+            capabilities: Capabilities::COMPRESSION,
+        },
+    );
+    check(
+        "server_hello_rejected",
+        &ServerHello::Rejected(HandshakeRejection::BadMagic),
+    );
+    check(
+        "handshake_rejection_version_mismatch",
+        &HandshakeRejection::VersionMismatch {
+            server: 12,
+            client: 11,
+        },
+    );
+    check(
+        "handshake_rejection_bad_magic",
+        &HandshakeRejection::BadMagic,
+    );
+}
+
+#[test]
+fn error_codes() {
+    // Codes are matched on by clients, so each keeps its index forever.
+    let codes = [
+        ErrorCode::UnknownPlayer,
+        ErrorCode::Unsupported,
+        ErrorCode::NotYourPlayer,
+        ErrorCode::NotConnected,
+        ErrorCode::MissedUpdates,
+        ErrorCode::Malformed,
+        ErrorCode::Internal,
+        ErrorCode::TakenOver,
+        ErrorCode::InvalidName,
+        ErrorCode::InvalidMessage,
+        ErrorCode::UnknownCommand,
+        ErrorCode::NotPermitted,
+        ErrorCode::InvalidArguments,
+        ErrorCode::Kicked,
+        ErrorCode::RateLimited,
+        ErrorCode::Muted,
+        ErrorCode::Banned,
+        ErrorCode::Blocked,
+        ErrorCode::InvalidChunks,
+    ];
+    check("error_codes", &codes.to_vec());
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    assert_eq!(diff("a\nb\nc", "a\nx\nc\nd"), "  a\n- b\n+ x\n  c\n+ d\n");
+}
@@ -0,0 +1,110 @@
+use shared::{
+    Capabilities, ClientHello, ClientMessage, FrameReader, FrameWriter, HandshakeError,
+    HandshakeRejection, Negotiated, PROTOCOL_VERSION, client_handshake, send_message,
+    server_handshake,
+};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn run_pair(
+    hello: ClientHello,
+    server_caps: Capabilities,
+) -> (
+    Result<Negotiated, HandshakeError>,
+    Result<Negotiated, HandshakeError>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, writer) = stream.into_split();
+        let (mut reader, mut writer) = (FrameReader::new(reader), FrameWriter::new(writer));
+        server_handshake(&mut reader, &mut writer, server_caps).await
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (reader, writer) = stream.into_split();
+    let (mut reader, mut writer) = (FrameReader::new(reader), FrameWriter::new(writer));
+    let client = client_handshake(&mut reader, &mut writer, hello).await;
+
+    (client, server.await.unwrap())
+}
+
+#[tokio::test]
+async fn matching_versions_negotiate_common_capabilities() {
+    let a = Capabilities::from_bits(0b01);
+    let b = Capabilities::from_bits(0b10);
+
+    let (client, server) = run_pair(ClientHello::new(a.union(b)), a).await;
+
+    let expected = Negotiated {
+        version: PROTOCOL_VERSION,
+        capabilities: a,
+    };
+    assert_eq!(client.unwrap(), expected);
+    assert_eq!(server.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn newer_client_is_rejected_with_version_mismatch() {
+    let hello = ClientHello {
+        version: PROTOCOL_VERSION + 1,
+        ..ClientHello::new(Capabilities::NONE)
+    };
+
+    let (client, server) = run_pair(hello, Capabilities::NONE).await;
+
+    let expected = HandshakeRejection::VersionMismatch {
+        server: PROTOCOL_VERSION,
+        client: PROTOCOL_VERSION + 1,
+    };
+    match client {
+        Err(HandshakeError::Rejected(rejection)) => assert_eq!(rejection, expected),
+        other => panic!("expected rejection, got {other:?}"),
+    }
+    match server {
+        Err(HandshakeError::Refused(rejection)) => assert_eq!(rejection, expected),
+        other => panic!("expected refusal, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn older_client_gets_a_displayable_rejection() {
+    let hello = ClientHello {
+        version: 0,
+        ..ClientHello::new(Capabilities::NONE)
+    };
+
+    let (client, _) = run_pair(hello, Capabilities::NONE).await;
+
+    let Err(HandshakeError::Rejected(rejection)) = client else {
+        panic!("expected rejection");
+    };
+    assert!(rejection.to_string().contains("please update"));
+}
+
+#[tokio::test]
+async fn pre_handshake_client_is_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, writer) = stream.into_split();
+        let (mut reader, mut writer) = (FrameReader::new(reader), FrameWriter::new(writer));
+        server_handshake(&mut reader, &mut writer, Capabilities::NONE).await
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (_reader, mut writer) = stream.into_split();
+    let request = ClientMessage::ConnectionRequest {
+        token: None,
+        name: "legacy".into(),
+    };
+    send_message(&mut writer, &request).await.unwrap();
+
+    match server.await.unwrap() {
+        Err(HandshakeError::Refused(HandshakeRejection::BadMagic)) => {}
+        other => panic!("expected bad magic refusal, got {other:?}"),
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use shared::{
+    ClientEnvelope, ClientMessage, ErrorCode, FrameReader, FrameWriter, MessageReader,
+    MessageWriter, RequestError, RequestId, RequestTracker, ServerEnvelope, ServerMessage, Timeout,
+};
+use tokio::io::duplex;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn move_request() -> ClientMessage {
+    ClientMessage::MoveRequest {
+        direction: [1.0, 0.0, 0.0],
+    }
+}
+
+fn connection_request() -> ClientMessage {
+    ClientMessage::ConnectionRequest {
+        token: None,
+        name: "tester".into(),
+    }
+}
+
+#[test]
+fn every_message_gets_a_distinct_id() {
+    let mut tracker = RequestTracker::new(TIMEOUT, 3);
+    let now = Instant::now();
+
+    let a = tracker.send(connection_request(), now);
+    let b = tracker.send(move_request(), now);
+    let c = tracker.send(ClientMessage::MapRequest(vec![[0, 0]]), now);
+
+    assert_ne!(a.id, b.id);
+    assert_ne!(b.id, c.id);
+    assert_ne!(a.id, c.id);
+}
+
+#[test]
+fn only_requests_expecting_a_reply_are_tracked() {
+    let mut tracker = RequestTracker::new(TIMEOUT, 3);
+    let now = Instant::now();
+
+    let connect = tracker.send(connection_request(), now);
+    let walk = tracker.send(move_request(), now);
+
+    assert!(tracker.is_pending(connect.id));
+    assert!(!tracker.is_pending(walk.id));
+}
+
+#[test]
+fn a_reply_resolves_its_request_once() {
+    let mut tracker = RequestTracker::new(TIMEOUT, 3);
+    let now = Instant::now();
+    let request = tracker.send(connection_request(), now);
+
+    assert!(tracker.resolve(request.id));
+    assert!(!tracker.resolve(request.id));
+    assert!(tracker.poll(now + TIMEOUT * 10).is_empty());
+}
+
+#[test]
+fn unanswered_requests_are_retried_with_the_same_id_then_abandoned() {
+    let mut tracker = RequestTracker::new(TIMEOUT, 2);
+    let start = Instant::now();
+    let request = tracker.send(ClientMessage::MapRequest(vec![[0, 0]]), start);
+
+    assert!(tracker.poll(start + TIMEOUT / 2).is_empty());
+
+    let retried = tracker.poll(start + TIMEOUT);
+    let [Timeout::Retry(envelope)] = retried.as_slice() else {
+        panic!("expected one retry");
+    };
+    assert_eq!(envelope.id, request.id);
+    assert!(matches!(envelope.message, ClientMessage::MapRequest(_)));
+
+    // The retry restarts the clock.
+    assert!(tracker.poll(start + TIMEOUT + TIMEOUT / 2).is_empty());
+
+    let abandoned = tracker.poll(start + TIMEOUT * 2);
+    let [Timeout::GaveUp(id, ClientMessage::MapRequest(_))] = abandoned.as_slice() else {
+        panic!("expected the request to be abandoned");
+    };
+    assert_eq!(*id, request.id);
+    assert!(!tracker.is_pending(request.id));
+}
+
+#[tokio::test]
+async fn envelopes_carry_the_request_id_across_the_wire() {
+    let (a, b) = duplex(64 * 1024);
+    let mut writer = FrameWriter::new(a);
+    let mut reader = FrameReader::new(b);
+
+    writer
+        .send(&ClientEnvelope {
+            id: RequestId(7),
+            message: connection_request(),
+        })
+        .await
+        .unwrap();
+    writer
+        .send(&ServerEnvelope::reply(
+            RequestId(7),
+            ServerMessage::Error {
+                code: ErrorCode::UnknownPlayer,
+                message: "who?".into(),
+            },
+        ))
+        .await
+        .unwrap();
+
+    let request = reader.recv::<ClientEnvelope>().await.unwrap();
+    assert_eq!(request.id, RequestId(7));
+
+    let reply = reader.recv::<ServerEnvelope>().await.unwrap();
+    assert_eq!(reply.reply_to, Some(RequestId(7)));
+    assert!(matches!(
+        reply.message,
+        ServerMessage::Error {
+            code: ErrorCode::UnknownPlayer,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn request_errors_become_error_replies() {
+    let error = RequestError::new(
+        ErrorCode::NotYourPlayer,
+        "player 42 belongs to another client",
+    );
+    assert_eq!(
+        error.to_string(),
+        "not your player: player 42 belongs to another client"
+    );
+
+    let ServerMessage::Error { code, message } = error.clone().into() else {
+        panic!("expected an error message");
+    };
+    assert_eq!(RequestError { code, message }, error);
+}
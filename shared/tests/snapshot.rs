@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use shared::{Player, PlayerDelta, SnapshotReceiver, SnapshotSender, SnapshotState};
+
+fn player(id: &str, x: f32) -> Player {
+    Player {
+        id: id.to_string(),
+        name: format!("player {id}"),
+        position: [x, 0.0, 0.0],
+        speed: 0.025,
+    }
+}
+
+fn state(tick: u32, players: &[Player]) -> Arc<SnapshotState> {
+    Arc::new(SnapshotState {
+        tick,
+        players: players
+            .iter()
+            .map(|p| (p.id.clone(), p.clone()))
+            .collect::<BTreeMap<_, _>>(),
+    })
+}
+
+#[test]
+fn full_snapshot_carries_every_field() {
+    let snapshot = state(1, &[player("a", 1.0)]).delta_from(None);
+
+    assert_eq!(snapshot.baseline, None);
+    assert_eq!(
+        snapshot.changed,
+        vec![PlayerDelta {
+            id: "a".into(),
+            name: Some("player a".into()),
+            position: Some([1.0, 0.0, 0.0]),
+            speed: Some(0.025),
+        }]
+    );
+}
+
+#[test]
+fn delta_only_carries_changed_fields_and_players() {
+    let players: Vec<Player> = (0..40).map(|i| player(&i.to_string(), 0.0)).collect();
+    let before = state(1, &players);
+    let mut moved = players.clone();
+    moved[7].position[0] = 2.0;
+    moved.remove(3);
+    let after = state(2, &moved);
+
+    let snapshot = after.delta_from(Some(&before));
+
+    assert_eq!(snapshot.baseline, Some(1));
+    assert_eq!(
+        snapshot.changed,
+        vec![PlayerDelta {
+            id: "7".into(),
+            name: None,
+            position: Some([2.0, 0.0, 0.0]),
+            speed: None,
+        }]
+    );
+    assert_eq!(snapshot.removed, vec!["3".to_string()]);
+    assert_eq!(snapshot.apply(Some(&before)).unwrap(), *after);
+}
+
+#[test]
+fn renames_are_sent_without_the_rest() {
+    let before = state(1, &[player("a", 1.0)]);
+    let mut renamed = player("a", 1.0);
+    renamed.name = "Alice".into();
+    let after = state(2, &[renamed]);
+
+    let snapshot = after.delta_from(Some(&before));
+
+    assert_eq!(
+        snapshot.changed,
+        vec![PlayerDelta {
+            id: "a".into(),
+            name: Some("Alice".into()),
+            position: None,
+            speed: None,
+        }]
+    );
+    assert_eq!(snapshot.apply(Some(&before)).unwrap(), *after);
+}
+
+#[test]
+fn sender_stays_quiet_once_the_client_is_up_to_date() {
+    let mut sender = SnapshotSender::default();
+    let first = state(1, &[player("a", 0.0)]);
+
+    assert!(sender.next(&first).is_some());
+    sender.ack(1);
+
+    assert!(sender.next(&state(2, &[player("a", 0.0)])).is_none());
+
+    let moved = sender.next(&state(3, &[player("a", 1.0)])).unwrap();
+    assert_eq!(moved.baseline, Some(1));
+}
+
+#[test]
+fn sender_repeats_changes_until_they_are_acknowledged() {
+    let mut sender = SnapshotSender::default();
+    sender.next(&state(1, &[player("a", 0.0)]));
+    sender.ack(1);
+
+    // Tick 2 is lost, so tick 3 must still carry the move.
+    sender.next(&state(2, &[player("a", 1.0)])).unwrap();
+    let resent = sender.next(&state(3, &[player("a", 1.0)])).unwrap();
+    assert_eq!(resent.baseline, Some(1));
+    assert_eq!(resent.changed.len(), 1);
+
+    sender.ack(3);
+    // An ack for an older tick arriving late changes nothing.
+    sender.ack(2);
+    assert!(sender.next(&state(4, &[player("a", 1.0)])).is_none());
+}
+
+#[test]
+fn receiver_rejects_stale_snapshots_and_unknown_baselines() {
+    let mut receiver = SnapshotReceiver::default();
+    let one = state(1, &[player("a", 0.0)]);
+    let two = state(2, &[player("a", 1.0)]);
+    let three = state(3, &[player("a", 2.0)]);
+
+    assert!(receiver.receive(&two.delta_from(None)).is_some());
+    assert!(receiver.receive(&one.delta_from(None)).is_none());
+    assert!(receiver.receive(&three.delta_from(Some(&one))).is_none());
+    assert_eq!(
+        receiver.receive(&three.delta_from(Some(&two))),
+        Some(&*three)
+    );
+}
+
+#[test]
+fn client_converges_despite_lost_snapshots_and_acks() {
+    let mut sender = SnapshotSender::default();
+    let mut receiver = SnapshotReceiver::default();
+    let mut players: Vec<Player> = (0..24).map(|i| player(&i.to_string(), 0.0)).collect();
+    let mut rng = 0x2545_F491_4F6C_DD1Du64;
+    let mut coin = || {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng.is_multiple_of(3)
+    };
+
+    let mut last = state(0, &players);
+    for tick in 1..300 {
+        if tick < 250 {
+            let who = tick as usize % players.len();
+            players[who].position[1] += 1.0;
+            if tick % 50 == 0 {
+                players.remove(who);
+            }
+        }
+        last = state(tick, &players);
+
+        let Some(snapshot) = sender.next(&last) else {
+            continue;
+        };
+        if coin() {
+            continue;
+        }
+        let Some(applied) = receiver.receive(&snapshot) else {
+            continue;
+        };
+        let acked = applied.tick;
+        if !coin() {
+            sender.ack(acked);
+        }
+    }
+
+    assert_eq!(receiver.latest().unwrap().players, last.players);
+}
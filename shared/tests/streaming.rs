@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+use shared::{
+    CHUNK_RETRY_DELAY, CHUNK_SIZE, Chunk, ChunkCoord, ChunkStreamer, MAX_CHUNK_COORD,
+    MAX_CHUNKS_PER_REQUEST, MAX_VIEW_RADIUS, WorldGen,
+};
+
+/// A position just inside the chunk `coord`.
+fn inside(coord: ChunkCoord) -> [f32; 3] {
+    [
+        (coord[0] * CHUNK_SIZE) as f32 + 0.5,
+        (coord[1] * CHUNK_SIZE) as f32 + 0.5,
+        0.0,
+    ]
+}
+
+fn chunks(coords: &[ChunkCoord]) -> Vec<Chunk> {
+    coords
+        .iter()
+        .map(|&coord| Chunk::generate(coord, &WorldGen::default()))
+        .collect()
+}
+
+/// Builds every chunk waiting to be built, returning their coordinates in build order.
+fn build_all(streamer: &mut ChunkStreamer) -> Vec<ChunkCoord> {
+    std::iter::from_fn(|| streamer.next_to_build())
+        .map(|chunk| chunk.coord)
+        .collect()
+}
+
+/// Requests everything in view, receives it and builds it.
+fn fill_view(streamer: &mut ChunkStreamer) {
+    let coords = streamer.to_request();
+    streamer.receive(chunks(&coords));
+    build_all(streamer);
+}
+
+#[test]
+fn the_view_is_requested_once_nearest_first() {
+    let mut streamer = ChunkStreamer::new(1);
+    assert!(streamer.to_request().is_empty(), "nowhere to look yet");
+
+    streamer.move_to(inside([3, -2]));
+    let coords = streamer.to_request();
+    assert_eq!(coords.len(), 9);
+    assert_eq!(coords[0], [3, -2]);
+    assert!(streamer.to_request().is_empty(), "already asked for");
+}
+
+#[test]
+fn requests_never_ask_for_more_than_the_server_allows() {
+    let mut streamer = ChunkStreamer::new(MAX_VIEW_RADIUS);
+    streamer.move_to(inside([0, 0]));
+
+    let first = streamer.to_request();
+    let second = streamer.to_request();
+    assert_eq!(first.len(), MAX_CHUNKS_PER_REQUEST);
+    assert!(!second.is_empty());
+    assert!(first.iter().all(|coord| !second.contains(coord)));
+}
+
+#[test]
+fn the_view_stops_at_the_edge_of_the_world() {
+    let mut streamer = ChunkStreamer::new(1);
+    streamer.move_to(inside([MAX_CHUNK_COORD, MAX_CHUNK_COORD]));
+    assert_eq!(streamer.to_request().len(), 4);
+}
+
+#[test]
+fn received_chunks_are_built_nearest_first() {
+    let mut streamer = ChunkStreamer::new(2);
+    streamer.move_to(inside([0, 0]));
+    streamer.to_request();
+
+    streamer.receive(chunks(&[[2, 2], [0, 1], [0, 0], [-1, -1]]));
+    assert_eq!(build_all(&mut streamer), [[0, 0], [0, 1], [-1, -1], [2, 2]]);
+    assert!(streamer.is_loaded([2, 2]));
+    assert!(
+        streamer.to_request().is_empty(),
+        "the rest is still on its way"
+    );
+}
+
+#[test]
+fn chunks_out_of_view_are_unloaded_as_the_player_moves() {
+    let mut streamer = ChunkStreamer::new(1);
+    streamer.move_to(inside([0, 0]));
+    fill_view(&mut streamer);
+
+    assert!(streamer.move_to(inside([0, 0])).is_empty());
+    let mut unloaded = streamer.move_to(inside([1, 0]));
+    unloaded.sort();
+    assert_eq!(unloaded, [[-1, -1], [-1, 0], [-1, 1]]);
+    assert_eq!(streamer.loaded().count(), 6);
+
+    let mut coords = streamer.to_request();
+    coords.sort();
+    assert_eq!(coords, [[2, -1], [2, 0], [2, 1]]);
+}
+
+#[test]
+fn chunks_arriving_after_the_player_left_them_are_dropped() {
+    let mut streamer = ChunkStreamer::new(1);
+    streamer.move_to(inside([0, 0]));
+    let requested = streamer.to_request();
+
+    streamer.move_to(inside([5, 5]));
+    streamer.receive(chunks(&requested));
+    assert!(streamer.next_to_build().is_none());
+}
+
+#[test]
+fn chunks_sent_before_the_player_is_known_are_kept_if_in_view() {
+    // The server sends the chunks around a joining player along with the player.
+    let mut streamer = ChunkStreamer::new(1);
+    streamer.receive(chunks(&[[0, 0], [0, 1], [7, 7]]));
+
+    streamer.move_to(inside([0, 0]));
+    assert_eq!(build_all(&mut streamer), [[0, 0], [0, 1]]);
+    assert_eq!(streamer.to_request().len(), 7);
+}
+
+#[test]
+fn forgotten_requests_are_asked_for_again_after_a_delay() {
+    let mut streamer = ChunkStreamer::new(0);
+    streamer.move_to(inside([-4, 9]));
+    let start = Instant::now();
+    let coords = streamer.to_request_at(start);
+    assert_eq!(coords, [[-4, 9]]);
+
+    streamer.forget(&coords, start);
+    let almost = start + CHUNK_RETRY_DELAY - Duration::from_millis(1);
+    assert!(streamer.to_request_at(almost).is_empty(), "too soon");
+    assert_eq!(streamer.to_request_at(start + CHUNK_RETRY_DELAY), coords);
+}
+
+#[test]
+fn the_view_radius_is_clamped() {
+    assert_eq!(ChunkStreamer::new(-3).view_radius(), 0);
+    assert_eq!(
+        ChunkStreamer::new(MAX_VIEW_RADIUS + 10).view_radius(),
+        MAX_VIEW_RADIUS
+    );
+}
@@ -0,0 +1,58 @@
+use shared::{
+    Capabilities, ClientHello, ClientMessage, FrameReader, FrameWriter, MessageReader,
+    MessageWriter, PROTOCOL_VERSION, Transport, client_handshake, server_handshake,
+};
+use tokio::{
+    io::{duplex, split},
+    net::{UnixListener, UnixStream},
+};
+
+/// Runs a handshake and one request over any pair of connected transports.
+async fn exchange<C: Transport, S: Transport>(client: C, server: S) {
+    let server = tokio::spawn(async move {
+        let (reader, writer) = split(server);
+        let (mut reader, mut writer) = (FrameReader::new(reader), FrameWriter::new(writer));
+        server_handshake(&mut reader, &mut writer, Capabilities::NONE)
+            .await
+            .unwrap();
+        reader.recv::<ClientMessage>().await.unwrap()
+    });
+
+    let (reader, writer) = split(client);
+    let (mut reader, mut writer) = (FrameReader::new(reader), FrameWriter::new(writer));
+    let negotiated = client_handshake(
+        &mut reader,
+        &mut writer,
+        ClientHello::new(Capabilities::NONE),
+    )
+    .await
+    .unwrap();
+    assert_eq!(negotiated.version, PROTOCOL_VERSION);
+
+    let request = ClientMessage::ConnectionRequest {
+        token: None,
+        name: "tester".into(),
+    };
+    writer.send(&request).await.unwrap();
+
+    assert_eq!(server.await.unwrap(), request);
+}
+
+#[tokio::test]
+async fn protocol_runs_over_duplex_pipe() {
+    let (client, server) = duplex(4096);
+    exchange(client, server).await;
+}
+
+#[tokio::test]
+async fn protocol_runs_over_unix_socket() {
+    let path = std::env::temp_dir().join(format!("game-transport-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path).unwrap();
+    let client = UnixStream::connect(&path).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    exchange(client, server).await;
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use shared::{
+    Capabilities, ClientHello, ClientMessage, FrameError, LinkConditions, MessageReader,
+    MessageWriter, PROTOCOL_VERSION, UdpConfig, UdpConnection, UdpListener, client_handshake,
+    server_handshake,
+};
+use tokio::time::timeout;
+
+fn lossy(loss: f64) -> UdpConfig {
+    UdpConfig {
+        resend_interval: Duration::from_millis(20),
+        conditions: LinkConditions {
+            loss,
+            ..LinkConditions::default()
+        },
+        ..UdpConfig::default()
+    }
+}
+
+async fn connect_pair(config: UdpConfig) -> (UdpConnection, UdpConnection) {
+    let mut listener = UdpListener::bind("127.0.0.1:0", config).await.unwrap();
+    let client = UdpConnection::connect(listener.local_addr(), config)
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (client, server)
+}
+
+#[tokio::test]
+async fn reliable_messages_arrive_in_order_despite_loss() {
+    let (client, server) = connect_pair(lossy(0.3)).await;
+    let (_, mut client_writer) = client.into_split();
+    let (mut server_reader, _server_writer) = server.into_split();
+
+    for i in 0..100 {
+        client_writer
+            .send(&ClientMessage::MapRequest(vec![[i, 0]]))
+            .await
+            .unwrap();
+    }
+
+    for i in 0..100 {
+        let msg = timeout(
+            Duration::from_secs(10),
+            server_reader.recv::<ClientMessage>(),
+        )
+        .await
+        .expect("reliable message never arrived")
+        .unwrap();
+        match msg {
+            ClientMessage::MapRequest(n) => assert_eq!(n, vec![[i, 0]]),
+            _ => panic!("unexpected message"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn large_messages_are_fragmented_and_reassembled() {
+    let (client, server) = connect_pair(lossy(0.2)).await;
+    let (_, mut client_writer) = client.into_split();
+    let (mut server_reader, _server_writer) = server.into_split();
+
+    let big: Vec<[i64; 2]> = (0..5_000).map(|i| [i, -i]).collect();
+    client_writer
+        .send(&ClientMessage::MapRequest(big.clone()))
+        .await
+        .unwrap();
+
+    match timeout(
+        Duration::from_secs(10),
+        server_reader.recv::<ClientMessage>(),
+    )
+    .await
+    .unwrap()
+    .unwrap()
+    {
+        ClientMessage::MapRequest(n) => assert_eq!(n, big),
+        _ => panic!("unexpected message"),
+    }
+}
+
+#[tokio::test]
+async fn messages_longer_than_the_receive_window_arrive_despite_loss() {
+    let (client, server) = connect_pair(lossy(0.05)).await;
+    let (_, mut client_writer) = client.into_split();
+    let (mut server_reader, _server_writer) = server.into_split();
+
+    // Several thousand fragments, more than the receiver buffers ahead of a lost one.
+    let huge: Vec<[i64; 2]> = (0..450_000).map(|i| [i * 1_000, -i * 1_000]).collect();
+    client_writer
+        .send(&ClientMessage::MapRequest(huge.clone()))
+        .await
+        .unwrap();
+
+    match timeout(
+        Duration::from_secs(30),
+        server_reader.recv::<ClientMessage>(),
+    )
+    .await
+    .expect("the message never arrived")
+    .unwrap()
+    {
+        ClientMessage::MapRequest(n) => assert_eq!(n, huge),
+        _ => panic!("unexpected message"),
+    }
+}
+
+#[tokio::test]
+async fn unreliable_messages_are_never_delivered_out_of_order() {
+    let (client, server) = connect_pair(lossy(0.2)).await;
+    let (_, mut client_writer) = client.into_split();
+    let (mut server_reader, _server_writer) = server.into_split();
+
+    for i in 0..200 {
+        client_writer
+            .send(&ClientMessage::MoveRequest {
+                direction: [i as f32, 0.0, 0.0],
+            })
+            .await
+            .unwrap();
+    }
+
+    let mut received = Vec::new();
+    while let Ok(msg) = timeout(
+        Duration::from_millis(300),
+        server_reader.recv::<ClientMessage>(),
+    )
+    .await
+    {
+        match msg.unwrap() {
+            ClientMessage::MoveRequest { direction, .. } => received.push(direction[0]),
+            _ => panic!("unexpected message"),
+        }
+    }
+
+    assert!(!received.is_empty());
+    assert!(received.len() < 200, "simulated loss dropped nothing");
+    assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn handshake_runs_over_udp() {
+    let (client, server) = connect_pair(lossy(0.2)).await;
+    let (mut client_reader, mut client_writer) = client.into_split();
+    let (mut server_reader, mut server_writer) = server.into_split();
+
+    let server = tokio::spawn(async move {
+        server_handshake(&mut server_reader, &mut server_writer, Capabilities::NONE).await
+    });
+    let negotiated = client_handshake(
+        &mut client_reader,
+        &mut client_writer,
+        ClientHello::new(Capabilities::NONE),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(negotiated.version, PROTOCOL_VERSION);
+    assert_eq!(server.await.unwrap().unwrap(), negotiated);
+}
+
+#[tokio::test]
+async fn dropping_the_writer_disconnects_the_peer() {
+    let (client, server) = connect_pair(UdpConfig::default()).await;
+    let (_client_reader, client_writer) = client.into_split();
+    let (mut server_reader, _server_writer) = server.into_split();
+
+    drop(client_writer);
+
+    let result = timeout(
+        Duration::from_secs(5),
+        server_reader.recv::<ClientMessage>(),
+    )
+    .await
+    .expect("peer never noticed the disconnect");
+    assert!(matches!(result, Err(FrameError::Closed)));
+}
+
+#[tokio::test]
+async fn closing_the_writer_waits_until_everything_is_delivered() {
+    let (client, server) = connect_pair(lossy(0.3)).await;
+    let (_client_reader, mut client_writer) = client.into_split();
+    let (mut server_reader, _server_writer) = server.into_split();
+
+    for i in 0..20 {
+        client_writer
+            .send(&ClientMessage::MapRequest(vec![[i, 0]]))
+            .await
+            .unwrap();
+    }
+    timeout(Duration::from_secs(10), client_writer.close())
+        .await
+        .expect("close never finished")
+        .unwrap();
+
+    // Everything is already buffered on the server's side by the time `close` returns.
+    for i in 0..20 {
+        let msg = server_reader.recv::<ClientMessage>().await.unwrap();
+        assert_eq!(msg, ClientMessage::MapRequest(vec![[i, 0]]));
+    }
+}
+
+#[tokio::test]
+async fn connect_gives_up_when_nobody_answers() {
+    let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let config = UdpConfig {
+        connect_timeout: Duration::from_millis(300),
+        ..UdpConfig::default()
+    };
+
+    let err = UdpConnection::connect(silent.local_addr().unwrap(), config)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}